@@ -1 +1,3 @@
-slangc shaders/shader.slang -target spirv -profile spirv_1_4 -emit-spirv-directly -fvk-use-entrypoint-name -entry vertMain -entry fragMain -o shaders/slang.spv
+slangc shaders/shader.slang -target spirv -profile spirv_1_4 -emit-spirv-directly -fvk-use-entrypoint-name -matrix-layout-column-major -entry vertMain -entry fragMain -o shaders/slang.spv
+slangc shaders/scene.slang -target spirv -profile spirv_1_4 -emit-spirv-directly -fvk-use-entrypoint-name -matrix-layout-column-major -entry vertMain -entry fragMain -o shaders/scene.spv
+slangc shaders/lit.slang -target spirv -profile spirv_1_4 -emit-spirv-directly -fvk-use-entrypoint-name -matrix-layout-column-major -entry vertMain -entry fragMain -o shaders/lit.spv
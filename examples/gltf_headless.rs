@@ -0,0 +1,41 @@
+use ash::vk;
+use lvkrs::VkConfig;
+use lvkrs::app::{Gltf, VkCore, render_gltf};
+
+const DEFAULT_ASSET: &str = "assets/box.gltf";
+const DEFAULT_OUTPUT: &str = "gltf_headless.png";
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 600;
+
+// renders one frame of a glTF offscreen and writes it to a png, no window or swapchain involved:
+// cargo run --example gltf_headless -- model.gltf out.png
+// tests/gltf_offscreen.rs checks the same render against expected pixels
+fn main() {
+	let _ = env_logger::builder()
+		.filter_module("lvkrs", log::LevelFilter::Info)
+		.format_timestamp(None)
+		.try_init();
+	let mut args = std::env::args().skip(1);
+	let gltf_path = args.next().unwrap_or_else(|| DEFAULT_ASSET.to_owned());
+	let output_path = args.next().unwrap_or_else(|| DEFAULT_OUTPUT.to_owned());
+
+	let vk = VkCore::headless(&VkConfig::default())
+		.unwrap_or_else(|e| panic!("Could not build VkCore: {}", e));
+	let gltf =
+		Gltf::load(&gltf_path).unwrap_or_else(|e| panic!("Could not load {}: {}", gltf_path, e));
+	let extent = vk::Extent2D {
+		width: WIDTH,
+		height: HEIGHT,
+	};
+	let pixels = render_gltf(&vk, &gltf, include_bytes!("../shaders/scene.spv"), extent);
+	vk.cleanup();
+	image::save_buffer(
+		&output_path,
+		&pixels,
+		WIDTH,
+		HEIGHT,
+		image::ExtendedColorType::Rgba8,
+	)
+	.unwrap_or_else(|e| panic!("Could not write {}: {}", output_path, e));
+	log::info!("wrote {}", output_path);
+}
@@ -0,0 +1,129 @@
+use ash::vk;
+use cgmath::{Deg, Point3};
+use lvkrs::app::{
+	AttachmentLoad, Camera, Gltf, InputState, OrbitController, Projection, RunControl,
+	SCENE_DEPTH_FORMAT, Scene, SceneRenderer, TransientImageDesc, VkSwap,
+};
+use lvkrs::{App, FrameContext, Runner, VkConfig, app::VkCore};
+use std::rc::Rc;
+
+const DEFAULT_ASSET: &str = "assets/box.gltf";
+
+// cargo run --example gltf_viewer -- path/to/model.gltf (or .glb). drag to orbit, scroll to zoom
+struct Viewer {
+	gltf: Gltf,
+	// Rc so the pass closure can hold on to them for the frame
+	renderer: Option<Rc<SceneRenderer>>,
+	scene: Option<Rc<Scene>>,
+	camera: Camera,
+	orbit: OrbitController,
+}
+
+impl Viewer {
+	fn new(gltf: Gltf) -> Viewer {
+		// far enough back to see the whole thing
+		let (min, max) = gltf.bounds().unwrap_or(([-1.; 3], [1.; 3]));
+		let center = Point3::new(
+			(min[0] + max[0]) / 2.,
+			(min[1] + max[1]) / 2.,
+			(min[2] + max[2]) / 2.,
+		);
+		let radius = (0..3)
+			.map(|i| (max[i] - min[i]) / 2.)
+			.fold(0., f32::max)
+			.max(0.01);
+		let mut orbit = OrbitController::new(center, radius * 3.);
+		orbit.min_distance = radius * 0.1;
+		Viewer {
+			gltf,
+			renderer: None,
+			scene: None,
+			camera: Camera::new(
+				center,
+				Projection::perspective(Deg(45.), radius * 0.01, radius * 100.),
+			),
+			orbit,
+		}
+	}
+}
+
+impl App for Viewer {
+	fn init(&mut self, vk: &VkCore, vk_swap: &VkSwap, _control: &RunControl) {
+		let renderer = SceneRenderer::new(
+			&vk.device_ctx,
+			include_bytes!("../shaders/scene.spv"),
+			vk_swap.swapchain_ctx.swapchain_format,
+			vk_swap.camera_set_layout,
+		);
+		self.scene = Some(Rc::new(Scene::new(
+			&vk.instance_ctx,
+			&vk.device_ctx,
+			&renderer,
+			&self.gltf,
+		)));
+		self.renderer = Some(Rc::new(renderer));
+	}
+
+	fn update(&mut self, _dt: f32, input: &InputState) {
+		self.orbit.update(&mut self.camera, input);
+	}
+
+	fn render(&mut self, frame: &mut FrameContext) {
+		let (Some(renderer), Some(scene)) = (&self.renderer, &self.scene) else {
+			return;
+		};
+		let (renderer, scene) = (renderer.clone(), scene.clone());
+		// aspect from the image being drawn, the window may already have a size the swapchain
+		// doesn't
+		self.camera
+			.set_viewport(frame.extent.width, frame.extent.height);
+		frame.set_camera(&self.camera);
+		let camera_set = frame.frame.camera_set;
+		let depth = frame.graph.create_image(
+			"depth",
+			TransientImageDesc {
+				format: SCENE_DEPTH_FORMAT,
+				extent: frame.extent,
+				usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+			},
+		);
+		let mut pass = frame.graph.add_pass("scene");
+		let clear_color = vk::ClearValue {
+			color: vk::ClearColorValue {
+				float32: [0.02, 0.02, 0.03, 1.],
+			},
+		};
+		let clear_depth = vk::ClearValue {
+			depth_stencil: vk::ClearDepthStencilValue {
+				depth: 1.,
+				stencil: 0,
+			},
+		};
+		frame.swapchain =
+			pass.color_attachment(frame.swapchain, AttachmentLoad::Clear(clear_color));
+		pass.depth_attachment(depth, AttachmentLoad::Clear(clear_depth));
+		pass.execute(move |ctx| {
+			renderer.draw(ctx.device, ctx.cmd_buff, &scene, camera_set);
+		});
+	}
+
+	fn cleanup(&mut self, vk: &VkCore) {
+		let device = vk.device_ctx.device();
+		if let Some(scene) = self.scene.take() {
+			scene.cleanup(device);
+		}
+		if let Some(renderer) = self.renderer.take() {
+			renderer.cleanup(device);
+		}
+	}
+}
+
+fn main() {
+	let path = std::env::args()
+		.nth(1)
+		.unwrap_or_else(|| DEFAULT_ASSET.to_owned());
+	let gltf = Gltf::load(&path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e));
+	Runner::with_config(Viewer::new(gltf), VkConfig::default())
+		.run()
+		.expect("Should have been able to run app loop");
+}
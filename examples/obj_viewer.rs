@@ -0,0 +1,128 @@
+use ash::vk;
+use cgmath::{Deg, Matrix4, Point3, SquareMatrix};
+use lvkrs::app::{
+	AttachmentLoad, Camera, InputState, LitModel, LitRenderer, Obj, OrbitController, Projection,
+	RunControl, SCENE_DEPTH_FORMAT, TransientImageDesc, VkSwap,
+};
+use lvkrs::{App, FrameContext, Runner, VkConfig, app::VkCore};
+use std::rc::Rc;
+
+const DEFAULT_ASSET: &str = "assets/shapes.obj";
+
+// cargo run --example obj_viewer -- path/to/model.obj. drag to orbit, scroll to zoom
+struct Viewer {
+	obj: Obj,
+	// Rc so the pass closure can hold on to them for the frame
+	renderer: Option<Rc<LitRenderer>>,
+	model: Option<Rc<LitModel>>,
+	camera: Camera,
+	orbit: OrbitController,
+}
+
+impl Viewer {
+	fn new(obj: Obj) -> Viewer {
+		let (min, max) = obj.bounds().unwrap_or(([-1.; 3], [1.; 3]));
+		let center = Point3::new(
+			(min[0] + max[0]) / 2.,
+			(min[1] + max[1]) / 2.,
+			(min[2] + max[2]) / 2.,
+		);
+		let radius = (0..3)
+			.map(|i| (max[i] - min[i]) / 2.)
+			.fold(0., f32::max)
+			.max(0.01);
+		let mut orbit = OrbitController::new(center, radius * 3.);
+		orbit.min_distance = radius * 0.1;
+		Viewer {
+			obj,
+			renderer: None,
+			model: None,
+			camera: Camera::new(
+				center,
+				Projection::perspective(Deg(45.), radius * 0.01, radius * 100.),
+			),
+			orbit,
+		}
+	}
+}
+
+impl App for Viewer {
+	fn init(&mut self, vk: &VkCore, vk_swap: &VkSwap, _control: &RunControl) {
+		self.renderer = Some(Rc::new(LitRenderer::new(
+			&vk.device_ctx,
+			include_bytes!("../shaders/lit.spv"),
+			vk_swap.swapchain_ctx.swapchain_format,
+			vk_swap.camera_set_layout,
+		)));
+		self.model = Some(Rc::new(LitModel::from_obj(&vk.device_ctx, &self.obj)));
+	}
+
+	fn update(&mut self, _dt: f32, input: &InputState) {
+		self.orbit.update(&mut self.camera, input);
+	}
+
+	fn render(&mut self, frame: &mut FrameContext) {
+		let (Some(renderer), Some(model)) = (&self.renderer, &self.model) else {
+			return;
+		};
+		let (renderer, model) = (renderer.clone(), model.clone());
+		// aspect from the image being drawn, the window may already have a size the swapchain
+		// doesn't
+		self.camera
+			.set_viewport(frame.extent.width, frame.extent.height);
+		frame.set_camera(&self.camera);
+		let camera_set = frame.frame.camera_set;
+		let depth = frame.graph.create_image(
+			"depth",
+			TransientImageDesc {
+				format: SCENE_DEPTH_FORMAT,
+				extent: frame.extent,
+				usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+			},
+		);
+		let mut pass = frame.graph.add_pass("obj");
+		let clear_color = vk::ClearValue {
+			color: vk::ClearColorValue {
+				float32: [0.02, 0.02, 0.03, 1.],
+			},
+		};
+		let clear_depth = vk::ClearValue {
+			depth_stencil: vk::ClearDepthStencilValue {
+				depth: 1.,
+				stencil: 0,
+			},
+		};
+		frame.swapchain =
+			pass.color_attachment(frame.swapchain, AttachmentLoad::Clear(clear_color));
+		pass.depth_attachment(depth, AttachmentLoad::Clear(clear_depth));
+		pass.execute(move |ctx| {
+			renderer.draw(
+				ctx.device,
+				ctx.cmd_buff,
+				&model,
+				Matrix4::identity(),
+				camera_set,
+			);
+		});
+	}
+
+	fn cleanup(&mut self, vk: &VkCore) {
+		let device = vk.device_ctx.device();
+		if let Some(model) = self.model.take() {
+			model.cleanup(device);
+		}
+		if let Some(renderer) = self.renderer.take() {
+			renderer.cleanup(device);
+		}
+	}
+}
+
+fn main() {
+	let path = std::env::args()
+		.nth(1)
+		.unwrap_or_else(|| DEFAULT_ASSET.to_owned());
+	let obj = Obj::load(&path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e));
+	Runner::with_config(Viewer::new(obj), VkConfig::default())
+		.run()
+		.expect("Should have been able to run app loop");
+}
@@ -0,0 +1,93 @@
+use ash::vk;
+use cgmath::Point3;
+use lvkrs::app::{
+	AttachmentLoad, Camera, InputState, OrbitController, PipelineContext, RunControl, VkSwap,
+};
+use lvkrs::{App, FrameContext, Runner, VkConfig, app::VkCore};
+
+// occlusion query id of the triangle draw
+const TRIANGLE_QUERY_ID: u64 = 0;
+
+// the original demo: one hard-coded triangle, vertices come from the shader. drag to orbit
+// around it, scroll to zoom
+struct Triangle {
+	pipeline_ctx: Option<PipelineContext>,
+	camera: Camera,
+	orbit: OrbitController,
+}
+
+impl Default for Triangle {
+	fn default() -> Triangle {
+		Triangle {
+			pipeline_ctx: None,
+			camera: Camera::default(),
+			orbit: OrbitController::new(Point3::new(0., 0., 0.), 2.),
+		}
+	}
+}
+
+impl App for Triangle {
+	fn init(&mut self, vk: &VkCore, vk_swap: &VkSwap, _control: &RunControl) {
+		self.pipeline_ctx = Some(PipelineContext::new(
+			&vk.device_ctx,
+			include_bytes!("../shaders/slang.spv"),
+			vk_swap.swapchain_ctx.swapchain_format,
+			&[vk_swap.camera_set_layout],
+		));
+	}
+
+	fn update(&mut self, _dt: f32, input: &InputState) {
+		self.orbit.update(&mut self.camera, input);
+	}
+
+	fn render(&mut self, frame: &mut FrameContext) {
+		let pipeline_ctx = self
+			.pipeline_ctx
+			.as_ref()
+			.expect("init should have created the pipeline");
+		let (pipeline, layout) = (pipeline_ctx.graphics_pipeline, pipeline_ctx.pipeline_layout);
+		// the image being drawn decides the aspect, not the window, which can already have a new
+		// size the swapchain doesn't
+		self.camera
+			.set_viewport(frame.extent.width, frame.extent.height);
+		frame.set_camera(&self.camera);
+		let camera_set = frame.frame.camera_set;
+		let mut pass = frame.graph.add_pass("triangle");
+		// vk::ClearValue is a union expression, can only hold one field
+		let clear_color = vk::ClearValue {
+			color: vk::ClearColorValue {
+				float32: [0., 0., 0., 1.],
+			},
+		};
+		frame.swapchain =
+			pass.color_attachment(frame.swapchain, AttachmentLoad::Clear(clear_color));
+		pass.execute(move |ctx| unsafe {
+			let _occlusion = ctx
+				.queries
+				.map(|queries| queries.occlusion(ctx.device, ctx.cmd_buff, TRIANGLE_QUERY_ID));
+			ctx.device
+				.cmd_bind_pipeline(ctx.cmd_buff, vk::PipelineBindPoint::GRAPHICS, pipeline);
+			ctx.device.cmd_bind_descriptor_sets(
+				ctx.cmd_buff,
+				vk::PipelineBindPoint::GRAPHICS,
+				layout,
+				0,
+				&[camera_set],
+				&[],
+			);
+			ctx.device.cmd_draw(ctx.cmd_buff, 3, 1, 0, 0);
+		});
+	}
+
+	fn cleanup(&mut self, vk: &VkCore) {
+		if let Some(pipeline_ctx) = self.pipeline_ctx.take() {
+			pipeline_ctx.cleanup(vk.device_ctx.device());
+		}
+	}
+}
+
+fn main() {
+	Runner::with_config(Triangle::default(), VkConfig::default())
+		.run()
+		.expect("Should have been able to run app loop");
+}
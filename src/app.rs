@@ -3,51 +3,181 @@ use ash::khr::surface;
 use ash::khr::swapchain;
 use ash::{Device, Entry, Instance, vk};
 use std::time::Instant;
+use winit::error::EventLoopError;
+use winit::event::WindowEvent;
+use winit::event_loop::EventLoop;
 use winit::window::Window;
 
+pub mod barriers;
+pub mod buffer;
+pub mod camera;
 pub mod common;
+pub mod config;
 pub mod device_ctx;
+pub mod device_requirements;
+pub mod device_selection;
+pub mod error;
+pub mod frame_context;
 pub mod frame_data;
+pub mod frame_queries;
+pub mod gltf;
+pub mod gpu_profiler;
+pub mod image_tracker;
+pub mod input;
 pub mod instance_ctx;
+pub mod lit;
+pub mod mesh;
+pub mod obj;
+pub mod offscreen;
+pub mod pipeline_cache;
 pub mod pipeline_ctx;
+pub mod render_graph;
+pub mod run_mode;
+pub mod sampler_cache;
+pub mod scene;
+pub mod stats;
 pub mod swapchain_ctx;
+pub mod texture;
+pub mod timeline;
+pub mod timestep;
+pub mod validation;
 pub mod vk_core;
 pub mod vk_swap;
 pub mod window;
 
+pub use buffer::Buffer;
+pub use camera::{Camera, CameraUniforms, FlyController, OrbitController, Projection};
 pub use common::*;
-pub use device_ctx::DeviceContext;
+pub use config::{LoaderSource, VkConfig};
+pub use device_ctx::{DeviceContext, QueueFamilies, QueueKind};
+pub use device_requirements::{
+	DeviceFeatures, DeviceRequirements, EnabledFeatures, FeatureField,
+	PhysicalDeviceVulkan14Features,
+};
+pub use device_selection::{
+	DeviceReport, DeviceSelectionError, DeviceSelectionReport, GpuSelector, Rejection,
+};
+pub use error::VkError;
+pub use frame_context::FrameContext;
 pub use frame_data::FrameData;
+pub use frame_queries::{
+	FrameQueries, FrameQueryResults, PipelineStatistics, QueryReport, QueryScope,
+};
+pub use gltf::{
+	AlphaMode, Gltf, GltfError, GltfMesh, GltfNode, GltfTexture, Material, Primitive, TextureRef,
+};
+pub use gpu_profiler::{
+	FrameTimestamps, GPU_TRACE_ENV_VAR, GpuProfiler, GpuScopeStats, GpuScopeTiming, ProfileScope,
+};
+pub use image_tracker::{ImageState, ImageTracker, ImageUsage};
+pub use input::{ActionMap, Binding, InputConfigError, InputState};
 pub use instance_ctx::InstanceContext;
-pub use pipeline_ctx::PipelineContext;
-pub use swapchain_ctx::SwapchainContext;
+pub use lit::{LitMaterial, LitModel, LitRenderer};
+pub use mesh::{Mesh, MeshData, Vertex};
+pub use obj::{Obj, ObjError, ObjMaterial, ObjMesh};
+pub use offscreen::{OFFSCREEN_CLEAR_COLOR, OFFSCREEN_COLOR_FORMAT, render_gltf};
+pub use pipeline_cache::{PipelineCache, PipelineCacheDir};
+pub use pipeline_ctx::{PipelineContext, PipelineDesc};
+pub use render_graph::{
+	AttachmentLoad, BufferHandle, BufferUsage, ImageHandle, PassContext, RenderGraph,
+	TransientImageDesc, TransientPool,
+};
+pub use run_mode::{RunControl, RunMode};
+pub use sampler_cache::{SamplerCache, SamplerDesc};
+pub use scene::{MaterialUniforms, SCENE_DEPTH_FORMAT, Scene, SceneRenderer};
+pub use stats::{FrameStats, FrameStatsConfig, FrameTimings, RollingStats};
+pub use swapchain_ctx::{PresentSharing, SwapchainContext};
+pub use texture::{Texture, TextureError};
+pub use timeline::{SubmitDeps, Timeline, TimelineWait};
+pub use timestep::{Clock, ManualClock, SystemClock, Tick, Timestep};
+pub use validation::{
+	DebugMessenger, MessageId, PrintfMessage, ValidationConfig, ValidationObject, ValidationRecord,
+	ValidationSink,
+};
 pub use vk_core::VkCore;
 pub use vk_swap::VkSwap;
 
-pub struct Application {
+// what a program built on the engine implements, the Runner calls into it
+pub trait App {
+	// VkCore and the first VkSwap exist, pipelines etc. get created here. `control` can be kept
+	// around to switch run modes or request redraws later
+	fn init(&mut self, _vk: &VkCore, _vk_swap: &VkSwap, _control: &RunControl) {}
+	// once per fixed step, or once per frame with the frame's dt (see Timestep). the just_*
+	// edges of `input` only show up in the first update after they happened
+	fn update(&mut self, _dt: f32, _input: &InputState) {}
+	// add passes to frame.graph, whatever ends up in frame.swapchain gets presented
+	fn render(&mut self, frame: &mut FrameContext);
+	// every window event, before the runner handles it
+	fn on_event(&mut self, _event: &WindowEvent) {}
+	// the swapchain was recreated at this extent, before the first frame that renders to it
+	fn on_resize(&mut self, _width: u32, _height: u32) {}
+	// the device is idle, destroy whatever init created
+	fn cleanup(&mut self, _vk: &VkCore) {}
+}
+
+// owns the event loop, VkCore, VkSwap and the App
+pub struct Runner<A: App> {
+	pub app: A,
 	pub window: Option<Window>,
-	pub last_frame: Instant,
+	pub timestep: Timestep,
+	pub config: VkConfig,
+	pub frame_stats: FrameStats,
+	pub control: RunControl,
+	pub input: InputState,
 
 	vk: Option<VkCore>,
 	vk_swap: Option<VkSwap>,
+	initialized: bool,
+	// when the next frame is due in RunMode::CappedFps
+	next_frame: Instant,
 }
 
-impl Application {
-	pub fn new() -> Application {
-		env_logger::builder()
+impl<A: App> Runner<A> {
+	pub fn new(app: A) -> Runner<A> {
+		Runner::with_config(app, VkConfig::default())
+	}
+
+	pub fn with_config(app: A, config: VkConfig) -> Runner<A> {
+		// whoever uses the library may have set up their own logger already
+		let _ = env_logger::builder()
 			.filter_module("lvkrs", log::LevelFilter::Info)
 			.format_timestamp(None)
-			.init();
+			.try_init();
 
 		log::info!("Building application!");
-		Application {
+		Runner {
+			app,
 			window: None,
 			vk: None,
 			vk_swap: None,
+			initialized: false,
+			next_frame: Instant::now(),
+			control: RunControl::default(),
+			input: InputState::new(),
+			// replaced once VkCore has applied the env vars
+			frame_stats: FrameStats::new(&FrameStatsConfig::default()),
+			config,
 
-			last_frame: Instant::now(),
+			timestep: Timestep::default(),
 		}
 	}
+
+	pub fn with_timestep(mut self, timestep: Timestep) -> Runner<A> {
+		self.timestep = timestep;
+		self
+	}
+
+	pub fn with_run_mode(self, mode: RunMode) -> Runner<A> {
+		self.control.set_mode(mode);
+		self
+	}
+
+	// blocks until the window is closed
+	pub fn run(mut self) -> Result<(), EventLoopError> {
+		// control flow is set every iteration in about_to_wait, from the run mode
+		let event_loop = EventLoop::new()?;
+		event_loop.run_app(&mut self)
+	}
 	pub fn vk(&self) -> &VkCore {
 		self.vk
 			.as_ref()
@@ -59,81 +189,205 @@ impl Application {
 			.expect("VkSwap should have been initialized")
 	}
 
-	// theoretical game update method
-	pub fn update(&self, dt: f32) {}
-	pub fn draw_frame(&self) {
-		let device = self.vk().device_ctx.device();
-		let curr_frame = self.vk_swap().current_frame;
-		let frame = &self
-			.vk_swap()
+	// ticks the timestep and runs the updates it hands out, no window or device needed. the
+	// redraw handler calls this before draw_frame, tests can call it to step exactly N frames
+	pub fn step_frame(&mut self) -> Tick {
+		let tick = self.timestep.tick();
+		if !tick.dropped.is_zero() {
+			log::debug!("fell behind, dropped {:?} of simulation", tick.dropped);
+		}
+		let update_dt = self.timestep.update_dt(&tick);
+		for _ in 0..tick.steps {
+			self.app.update(update_dt, &self.input);
+			self.input.end_frame();
+		}
+		tick
+	}
+
+	// false when there's nothing to draw to, e.g. a minimized window
+	fn recreate_swapchain(&mut self) -> bool {
+		let (Some(window), Some(vk), Some(vk_swap)) = (&self.window, &self.vk, &mut self.vk_swap)
+		else {
+			return false;
+		};
+		let size = window.inner_size();
+		if size.width == 0 || size.height == 0 {
+			return false;
+		}
+		vk_swap.recreate_swapchain(window, &vk.instance_ctx, &vk.device_ctx);
+		let extent = vk_swap.swapchain_ctx.swapchain_extent;
+		self.app.on_resize(extent.width, extent.height);
+		true
+	}
+
+	// everything but frame_ms, the caller knows when the frame started. skips the frame (all
+	// zero timings) while the swapchain can't be recreated
+	pub fn draw_frame(&mut self, alpha: f32) -> FrameTimings {
+		if self.vk_swap().out_of_date.get() && !self.recreate_swapchain() {
+			return FrameTimings::default();
+		}
+		// fields directly, the app is borrowed mutably for render
+		let vk = self
+			.vk
+			.as_ref()
+			.expect("VkCore should have been initialized");
+		let vk_swap = self
+			.vk_swap
+			.as_ref()
+			.expect("VkSwap should have been initialized");
+		let app = &mut self.app;
+		let device_ctx = &vk.device_ctx;
+		let device = device_ctx.device();
+		let curr_frame = vk_swap.current_frame;
+		let frame = &vk_swap
 			.frames
 			.get(curr_frame as usize)
 			.expect("curr_frame should index into a valid frame");
-		let swap_device = &self.vk_swap().swapchain_ctx.swapchain_device;
-		let swapchain = self.vk_swap().swapchain_ctx.swapchain;
-		let queue = self.vk().device_ctx.graphics_queue;
-		unsafe {
-			let _ = device.queue_wait_idle(queue);
-		};
+		let swap_device = &vk_swap.swapchain_ctx.swapchain_device;
+		let swapchain = vk_swap.swapchain_ctx.swapchain;
+		let queue = device_ctx.graphics_queue;
+		let present_queue = device_ctx.present_queue;
+		let timeline = &device_ctx.graphics_timeline;
 
-		let (img_idx, res) = unsafe {
-			swap_device
-				.acquire_next_image(swapchain, u64::MAX, frame.img_available, vk::Fence::null())
-				.expect("Should have been able to acquire next image")
-		};
-		self.vk_swap().record_command_buff(img_idx, device);
+		let mut timings = FrameTimings::default();
+		let ms_since = |start: Instant| start.elapsed().as_secs_f64() * 1000.;
 
-		// reset fence
-		match unsafe { device.reset_fences(&[frame.draw_fence]) } {
-			Ok(()) => {}
-			Err(e) => panic!("Failed to wait for fence: {:?}", e),
-		};
+		// wait for the gpu to be done with whatever this frame submitted last time around
+		let start = Instant::now();
+		while !timeline.wait_for_value(device, frame.timeline_value.get(), u64::MAX) {}
+		// so its timestamps and queries are in, FRAMES_IN_FLIGHT frames after they were recorded
+		vk_swap.collect_gpu_queries(device);
+		timings.wait_ms = ms_since(start);
 
-		let wait_dest_stage_mask = vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT;
-		let submit_info = vk::SubmitInfo {
-			p_wait_dst_stage_mask: &wait_dest_stage_mask,
-			p_wait_semaphores: &frame.img_available,
-			wait_semaphore_count: 1,
-			p_command_buffers: &frame.cmd_buff,
-			p_signal_semaphores: &frame.render_finished,
-			signal_semaphore_count: 1,
-			..Default::default()
+		let start = Instant::now();
+		let acquired = unsafe {
+			swap_device.acquire_next_image(
+				swapchain,
+				u64::MAX,
+				frame.img_available,
+				vk::Fence::null(),
+			)
 		};
-		match unsafe { device.queue_submit(queue, &[submit_info], frame.draw_fence) } {
-			Ok(()) => {}
-			Err(e) => panic!("Failed to submit to queue: {:?}", e),
+		let img_idx = match acquired {
+			// suboptimal still signals img_available, draw this one and recreate after
+			Ok((img_idx, suboptimal)) => {
+				if suboptimal {
+					vk_swap.out_of_date.set(true);
+				}
+				img_idx
+			}
+			// nothing was acquired or signaled, try again with a new swapchain next frame
+			Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+				vk_swap.out_of_date.set(true);
+				timings.acquire_ms = ms_since(start);
+				return timings;
+			}
+			Err(e) => panic!("Failed to acquire next image: {:?}", e),
 		};
+		// present semaphores are per swapchain image, we can't know when presentation is done
+		// with one so it's only safe to reuse once the same image comes back from acquire
+		timings.acquire_ms = ms_since(start);
+		let render_finished = vk_swap.render_finished[img_idx as usize];
+		let start = Instant::now();
+		vk_swap.record_command_buff(img_idx, vk, alpha, |frame| app.render(frame));
+		timings.record_ms = ms_since(start);
 
-		// loop until done waiting for fence
-		loop {
-			match unsafe { device.wait_for_fences(&[frame.draw_fence], true, u64::MAX) } {
-				Ok(()) => break,
-				Err(vk::Result::TIMEOUT) => continue,
-				Err(e) => panic!("Failed to wait for fence {:?}", e),
-			}
+		// with an ownership transfer the present queue has to run the acquire half first, and
+		// that submit is the one that signals render_finished
+		let start = Instant::now();
+		let transfers_ownership = vk_swap.transfers_present_ownership();
+		let graphics_signals = if transfers_ownership {
+			vec![]
+		} else {
+			vec![render_finished]
+		};
+		let value = timeline.submit(
+			device_ctx,
+			queue,
+			&[frame.cmd_buff],
+			&SubmitDeps {
+				binary_waits: &[(
+					frame.img_available,
+					vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+				)],
+				binary_signals: &graphics_signals,
+				..Default::default()
+			},
+		);
+		frame.timeline_value.set(value);
+		if transfers_ownership {
+			device_ctx.present_timeline.submit(
+				device_ctx,
+				present_queue,
+				&[vk_swap.present_acquire_cmds[img_idx as usize]],
+				&SubmitDeps {
+					timeline_waits: &[TimelineWait {
+						timeline,
+						value,
+						stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+					}],
+					binary_signals: &[render_finished],
+					..Default::default()
+				},
+			);
 		}
 
+		timings.submit_ms = ms_since(start);
+
+		let start = Instant::now();
 		let present_info_khr = vk::PresentInfoKHR {
 			wait_semaphore_count: 1,
-			p_wait_semaphores: &frame.render_finished,
+			p_wait_semaphores: &render_finished,
 			swapchain_count: 1,
 			p_swapchains: &swapchain,
 			p_image_indices: &img_idx,
 			..Default::default()
 		};
-		let result = unsafe { swap_device.queue_present(queue, &present_info_khr) };
+		let result = unsafe { swap_device.queue_present(present_queue, &present_info_khr) };
 		match result {
-			Ok(subobtimal) => {
-				if subobtimal {
-					log::info!("suboptimal swapchain");
-				}
-			}
-			Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
-				// window-resized
-				// recreate_swapchain
-			}
-			Err(vk::Result::SUBOPTIMAL_KHR) => {}
+			Ok(false) => {}
+			// the frame was submitted either way, the next one recreates first
+			Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => vk_swap.out_of_date.set(true),
 			Err(e) => panic!("Failed to present: {:?}", e),
 		};
+		timings.present_ms = ms_since(start);
+		vk.instance_ctx.check_validation();
+
+		let vk_swap = self
+			.vk_swap
+			.as_mut()
+			.expect("VkSwap should have been initialized");
+		vk_swap.current_frame = (curr_frame + 1) % FRAMES_IN_FLIGHT as u32;
+		timings
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[derive(Default)]
+	struct Counter {
+		dts: Vec<f32>,
+	}
+
+	impl App for Counter {
+		fn update(&mut self, dt: f32, _input: &InputState) {
+			self.dts.push(dt);
+		}
+		fn render(&mut self, _frame: &mut FrameContext) {}
+	}
+
+	#[test]
+	fn steps_frames_without_a_window() {
+		let frame = Duration::from_millis(25);
+		let mut runner = Runner::new(Counter::default()).with_timestep(
+			Timestep::fixed(Duration::from_millis(10)).with_clock(ManualClock::stepping(frame)),
+		);
+		let steps: Vec<u32> = (0..4).map(|_| runner.step_frame().steps).collect();
+		assert_eq!(steps, [2, 3, 2, 3]);
+		assert_eq!(runner.app.dts, [0.01; 10]);
+		assert_eq!(runner.timestep.total_steps(), 10);
 	}
 }
@@ -0,0 +1,119 @@
+use super::{BufferUsage, DeviceContext, ImageUsage, vk};
+
+// full color range of a single layer image, the common case
+pub fn color_subresource_range(base_mip_level: u32, level_count: u32) -> vk::ImageSubresourceRange {
+	vk::ImageSubresourceRange {
+		aspect_mask: vk::ImageAspectFlags::COLOR,
+		base_mip_level,
+		level_count,
+		base_array_layer: 0,
+		layer_count: 1,
+	}
+}
+
+pub fn aspect_for_format(format: vk::Format) -> vk::ImageAspectFlags {
+	match format {
+		vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+			vk::ImageAspectFlags::DEPTH
+		}
+		vk::Format::D16_UNORM_S8_UINT
+		| vk::Format::D24_UNORM_S8_UINT
+		| vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+		vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+		_ => vk::ImageAspectFlags::COLOR,
+	}
+}
+
+// queue family ownership transfer: the release half gets recorded on the source queue, the
+// acquire half on the destination queue, and the destination submit has to wait on the
+// source one (timeline wait). None when both families are the same, nothing to transfer then
+pub struct OwnershipTransfer<B> {
+	pub release: B,
+	pub acquire: B,
+}
+
+pub fn image_ownership_transfer(
+	image: vk::Image,
+	subresource_range: vk::ImageSubresourceRange,
+	from: ImageUsage,
+	to: ImageUsage,
+	src_family: u32,
+	dst_family: u32,
+) -> Option<OwnershipTransfer<vk::ImageMemoryBarrier2<'static>>> {
+	if src_family == dst_family {
+		return None;
+	}
+	let src = from.state();
+	let dst = to.state();
+	// both halves have to describe the same layout transition
+	let barrier = vk::ImageMemoryBarrier2 {
+		old_layout: src.layout,
+		new_layout: dst.layout,
+		src_queue_family_index: src_family,
+		dst_queue_family_index: dst_family,
+		image,
+		subresource_range,
+		..Default::default()
+	};
+	Some(OwnershipTransfer {
+		release: vk::ImageMemoryBarrier2 {
+			src_stage_mask: src.stage,
+			src_access_mask: src.access,
+			..barrier
+		},
+		acquire: vk::ImageMemoryBarrier2 {
+			dst_stage_mask: dst.stage,
+			dst_access_mask: dst.access,
+			..barrier
+		},
+	})
+}
+
+pub fn buffer_ownership_transfer(
+	buffer: vk::Buffer,
+	from: BufferUsage,
+	to: BufferUsage,
+	src_family: u32,
+	dst_family: u32,
+) -> Option<OwnershipTransfer<vk::BufferMemoryBarrier2<'static>>> {
+	if src_family == dst_family {
+		return None;
+	}
+	let (src_access, src_stage) = from.state();
+	let (dst_access, dst_stage) = to.state();
+	let barrier = vk::BufferMemoryBarrier2 {
+		src_queue_family_index: src_family,
+		dst_queue_family_index: dst_family,
+		buffer,
+		offset: 0,
+		size: vk::WHOLE_SIZE,
+		..Default::default()
+	};
+	Some(OwnershipTransfer {
+		release: vk::BufferMemoryBarrier2 {
+			src_stage_mask: src_stage,
+			src_access_mask: src_access,
+			..barrier
+		},
+		acquire: vk::BufferMemoryBarrier2 {
+			dst_stage_mask: dst_stage,
+			dst_access_mask: dst_access,
+			..barrier
+		},
+	})
+}
+
+pub fn cmd_barriers(
+	device_ctx: &DeviceContext,
+	cmd_buff: vk::CommandBuffer,
+	image_barriers: &[vk::ImageMemoryBarrier2],
+	buffer_barriers: &[vk::BufferMemoryBarrier2],
+) {
+	if image_barriers.is_empty() && buffer_barriers.is_empty() {
+		return;
+	}
+	let deps_info = vk::DependencyInfo::default()
+		.image_memory_barriers(image_barriers)
+		.buffer_memory_barriers(buffer_barriers);
+	device_ctx.cmd_pipeline_barrier2(cmd_buff, &deps_info);
+}
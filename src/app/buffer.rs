@@ -0,0 +1,122 @@
+use super::{Device, DeviceContext, vk};
+
+pub struct Buffer {
+	pub buffer: vk::Buffer,
+	pub memory: vk::DeviceMemory,
+	pub size: vk::DeviceSize,
+}
+
+impl Buffer {
+	pub fn new(
+		device_ctx: &DeviceContext,
+		size: vk::DeviceSize,
+		usage: vk::BufferUsageFlags,
+		mem_props: vk::MemoryPropertyFlags,
+	) -> Buffer {
+		let device = device_ctx.device();
+		let buffer_info = vk::BufferCreateInfo {
+			size,
+			usage,
+			sharing_mode: vk::SharingMode::EXCLUSIVE,
+			..Default::default()
+		};
+		let buffer = unsafe {
+			device
+				.create_buffer(&buffer_info, None)
+				.expect("Should have been able to create buffer")
+		};
+		let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+		let memory = device_ctx.allocate_memory(requirements, mem_props);
+		unsafe {
+			device
+				.bind_buffer_memory(buffer, memory, 0)
+				.expect("Should have been able to bind buffer memory")
+		};
+
+		Buffer {
+			buffer,
+			memory,
+			size,
+		}
+	}
+
+	// host visible buffer filled with `data`, meant to be copied from and thrown away
+	pub fn staging(device_ctx: &DeviceContext, data: &[u8]) -> Buffer {
+		let staging = Buffer::new(
+			device_ctx,
+			data.len() as vk::DeviceSize,
+			vk::BufferUsageFlags::TRANSFER_SRC,
+			vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+		);
+		staging.write(device_ctx.device(), data);
+		staging.set_name(device_ctx, "staging");
+		staging
+	}
+
+	// DEVICE_LOCAL buffer holding `data`, copied in through a staging buffer. blocks until the
+	// copy is done, so it's for loading only
+	pub fn device_local(
+		device_ctx: &DeviceContext,
+		data: &[u8],
+		usage: vk::BufferUsageFlags,
+	) -> Buffer {
+		let buffer = Buffer::new(
+			device_ctx,
+			data.len() as vk::DeviceSize,
+			usage | vk::BufferUsageFlags::TRANSFER_DST,
+			vk::MemoryPropertyFlags::DEVICE_LOCAL,
+		);
+		let staging = Buffer::staging(device_ctx, data);
+		device_ctx.immediate_submit(|cmd_buff| unsafe {
+			device_ctx.device().cmd_copy_buffer(
+				cmd_buff,
+				staging.buffer,
+				buffer.buffer,
+				&[vk::BufferCopy {
+					src_offset: 0,
+					dst_offset: 0,
+					size: buffer.size,
+				}],
+			);
+		});
+		staging.cleanup(device_ctx.device());
+		buffer
+	}
+
+	pub fn set_name(&self, device_ctx: &DeviceContext, name: &str) {
+		device_ctx.set_name(self.buffer, name);
+		device_ctx.set_name(self.memory, &format!("{} memory", name));
+	}
+
+	// only valid for HOST_VISIBLE | HOST_COHERENT memory
+	pub fn write(&self, device: &Device, data: &[u8]) {
+		debug_assert!(data.len() as vk::DeviceSize <= self.size);
+		unsafe {
+			let ptr = device
+				.map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())
+				.expect("Should have been able to map buffer memory") as *mut u8;
+			std::ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+			device.unmap_memory(self.memory);
+		}
+	}
+
+	// whole buffer back to the host, same memory requirements as write. the gpu writes have to
+	// be finished and made visible to the host (TRANSFER -> HOST barrier) first
+	pub fn read(&self, device: &Device) -> Vec<u8> {
+		unsafe {
+			let ptr = device
+				.map_memory(self.memory, 0, self.size, vk::MemoryMapFlags::empty())
+				.expect("Should have been able to map buffer memory") as *const u8;
+			let data = std::slice::from_raw_parts(ptr, self.size as usize).to_vec();
+			device.unmap_memory(self.memory);
+			data
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		unsafe {
+			device.destroy_buffer(self.buffer, None);
+			device.free_memory(self.memory, None);
+		}
+	}
+}
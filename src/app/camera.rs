@@ -0,0 +1,465 @@
+use super::InputState;
+use cgmath::{Angle, Deg, InnerSpace, Matrix4, Point3, Rad, Vector3, Vector4};
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+// a little short of straight up/down, look_to_rh falls apart when forward is parallel to up
+const MAX_PITCH: Rad<f32> = Rad(1.55);
+
+// Vulkan clip space: x right, y down, depth 0 at near and 1 at far. cgmath's perspective and
+// ortho are OpenGL style (y up, depth -1..1), so the matrices are built by hand.
+// `reverse_z` flips depth to 1 at near and 0 at far, the pipeline then needs a GREATER depth
+// test and a depth clear of 0
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+	// `far` can be f32::INFINITY
+	Perspective {
+		fovy: Rad<f32>,
+		near: f32,
+		far: f32,
+		reverse_z: bool,
+	},
+	// `height` in world units, the width follows from the aspect ratio
+	Orthographic {
+		height: f32,
+		near: f32,
+		far: f32,
+		reverse_z: bool,
+	},
+}
+
+impl Projection {
+	pub fn perspective(fovy: impl Into<Rad<f32>>, near: f32, far: f32) -> Projection {
+		Projection::Perspective {
+			fovy: fovy.into(),
+			near,
+			far,
+			reverse_z: false,
+		}
+	}
+
+	// no far plane, everything in front of `near` ends up in depth 0..1
+	pub fn infinite_perspective(fovy: impl Into<Rad<f32>>, near: f32) -> Projection {
+		Projection::perspective(fovy, near, f32::INFINITY)
+	}
+
+	pub fn orthographic(height: f32, near: f32, far: f32) -> Projection {
+		Projection::Orthographic {
+			height,
+			near,
+			far,
+			reverse_z: false,
+		}
+	}
+
+	pub fn reversed(mut self) -> Projection {
+		match &mut self {
+			Projection::Perspective { reverse_z, .. }
+			| Projection::Orthographic { reverse_z, .. } => *reverse_z = true,
+		}
+		self
+	}
+
+	// right handed view space looking down -z
+	pub fn matrix(&self, aspect: f32) -> Matrix4<f32> {
+		match *self {
+			Projection::Perspective {
+				fovy,
+				near,
+				far,
+				reverse_z,
+			} => {
+				let f = 1. / (fovy / 2.).tan();
+				// depth = (scale * z + offset) / -z
+				let (scale, offset) = match (reverse_z, far.is_infinite()) {
+					(false, false) => (far / (near - far), near * far / (near - far)),
+					(false, true) => (-1., -near),
+					(true, false) => (near / (far - near), near * far / (far - near)),
+					(true, true) => (0., near),
+				};
+				Matrix4::from_cols(
+					Vector4::new(f / aspect, 0., 0., 0.),
+					Vector4::new(0., -f, 0., 0.),
+					Vector4::new(0., 0., scale, -1.),
+					Vector4::new(0., 0., offset, 0.),
+				)
+			}
+			Projection::Orthographic {
+				height,
+				near,
+				far,
+				reverse_z,
+			} => {
+				let (scale, offset) = if reverse_z {
+					(1. / (far - near), far / (far - near))
+				} else {
+					(-1. / (far - near), -near / (far - near))
+				};
+				Matrix4::from_cols(
+					Vector4::new(2. / (height * aspect), 0., 0., 0.),
+					Vector4::new(0., -2. / height, 0., 0.),
+					Vector4::new(0., 0., scale, 0.),
+					Vector4::new(0., 0., offset, 1.),
+				)
+			}
+		}
+	}
+}
+
+impl Default for Projection {
+	fn default() -> Projection {
+		Projection::perspective(Deg(60.), 0.1, 100.)
+	}
+}
+
+// position plus yaw/pitch, y is up. yaw 0 looks down -z, positive yaw turns right
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Camera {
+	pub position: Point3<f32>,
+	pub yaw: Rad<f32>,
+	pub pitch: Rad<f32>,
+	pub projection: Projection,
+	// width / height of whatever the camera renders to
+	pub aspect: f32,
+}
+
+impl Camera {
+	pub fn new(position: Point3<f32>, projection: Projection) -> Camera {
+		Camera {
+			position,
+			yaw: Rad(0.),
+			pitch: Rad(0.),
+			projection,
+			aspect: 1.,
+		}
+	}
+
+	pub fn looking_at(mut self, target: Point3<f32>) -> Camera {
+		self.look_at(target);
+		self
+	}
+
+	pub fn look_at(&mut self, target: Point3<f32>) {
+		let dir = target - self.position;
+		if dir.magnitude2() == 0. {
+			return;
+		}
+		let dir = dir.normalize();
+		self.yaw = Rad::atan2(dir.x, -dir.z);
+		self.pitch = Rad(Rad::asin(dir.y).0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+	}
+
+	// a minimized window reports 0x0, keep the old aspect instead of dividing by zero
+	pub fn set_viewport(&mut self, width: u32, height: u32) {
+		if width > 0 && height > 0 {
+			self.aspect = width as f32 / height as f32;
+		}
+	}
+
+	pub fn forward(&self) -> Vector3<f32> {
+		let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+		let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+		Vector3::new(cos_pitch * sin_yaw, sin_pitch, -cos_pitch * cos_yaw)
+	}
+
+	pub fn right(&self) -> Vector3<f32> {
+		self.forward().cross(Vector3::unit_y()).normalize()
+	}
+
+	pub fn view(&self) -> Matrix4<f32> {
+		Matrix4::look_to_rh(self.position, self.forward(), Vector3::unit_y())
+	}
+
+	pub fn projection(&self) -> Matrix4<f32> {
+		self.projection.matrix(self.aspect)
+	}
+
+	fn rotate(&mut self, yaw: Rad<f32>, pitch: Rad<f32>) {
+		self.yaw = (self.yaw + yaw).normalize();
+		self.pitch = Rad((self.pitch + pitch).0.clamp(-MAX_PITCH.0, MAX_PITCH.0));
+	}
+}
+
+impl Default for Camera {
+	fn default() -> Camera {
+		Camera::new(Point3::new(0., 0., 2.), Projection::default())
+	}
+}
+
+// what the shaders see in set 0 binding 0, column major like cgmath
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniforms {
+	pub view: [[f32; 4]; 4],
+	pub projection: [[f32; 4]; 4],
+	pub view_projection: [[f32; 4]; 4],
+	// w unused, vec3 in a uniform buffer gets padded to 16 bytes anyway
+	pub position: [f32; 4],
+}
+
+impl CameraUniforms {
+	pub fn new(camera: &Camera) -> CameraUniforms {
+		let view = camera.view();
+		let projection = camera.projection();
+		CameraUniforms {
+			view: view.into(),
+			projection: projection.into(),
+			view_projection: (projection * view).into(),
+			position: camera.position.to_homogeneous().into(),
+		}
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		// plain f32s, no padding
+		unsafe {
+			std::slice::from_raw_parts(
+				self as *const CameraUniforms as *const u8,
+				size_of::<CameraUniforms>(),
+			)
+		}
+	}
+}
+
+// wasd + space/shift to move, hold the right mouse button to look around
+#[derive(Clone, Copy, Debug)]
+pub struct FlyController {
+	// world units per second
+	pub speed: f32,
+	// radians per pixel of mouse motion
+	pub sensitivity: f32,
+}
+
+impl Default for FlyController {
+	fn default() -> FlyController {
+		FlyController {
+			speed: 3.,
+			sensitivity: 0.003,
+		}
+	}
+}
+
+impl FlyController {
+	pub fn update(&self, camera: &mut Camera, input: &InputState, dt: f32) {
+		if input.mouse_pressed(MouseButton::Right) {
+			let (dx, dy) = input.mouse_motion();
+			camera.rotate(
+				Rad(dx as f32 * self.sensitivity),
+				Rad(-dy as f32 * self.sensitivity),
+			);
+		}
+		let axis = |positive: KeyCode, negative: KeyCode| {
+			input.pressed(positive) as i32 as f32 - input.pressed(negative) as i32 as f32
+		};
+		let movement = camera.forward() * axis(KeyCode::KeyW, KeyCode::KeyS)
+			+ camera.right() * axis(KeyCode::KeyD, KeyCode::KeyA)
+			+ Vector3::unit_y() * axis(KeyCode::Space, KeyCode::ShiftLeft);
+		if movement.magnitude2() > 0. {
+			camera.position += movement.normalize() * self.speed * dt;
+		}
+	}
+}
+
+// circles `target`: drag with the left mouse button to rotate, scroll to zoom
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitController {
+	pub target: Point3<f32>,
+	pub distance: f32,
+	pub min_distance: f32,
+	// radians per pixel of cursor movement
+	pub sensitivity: f32,
+	// fraction of the distance one scroll line zooms by
+	pub zoom_speed: f32,
+}
+
+impl OrbitController {
+	pub fn new(target: Point3<f32>, distance: f32) -> OrbitController {
+		OrbitController {
+			target,
+			distance,
+			min_distance: 0.1,
+			sensitivity: 0.005,
+			zoom_speed: 0.1,
+		}
+	}
+
+	pub fn update(&mut self, camera: &mut Camera, input: &InputState) {
+		if input.mouse_pressed(MouseButton::Left) {
+			let (dx, dy) = input.cursor_delta();
+			camera.rotate(
+				Rad(dx as f32 * self.sensitivity),
+				Rad(dy as f32 * self.sensitivity),
+			);
+		}
+		let (_, scroll) = input.scroll();
+		self.distance =
+			(self.distance * (1. - scroll * self.zoom_speed).max(0.1)).max(self.min_distance);
+		camera.position = self.target - camera.forward() * self.distance;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EPSILON: f32 = 1e-5;
+
+	// view space point -> normalized device coordinates
+	fn project(projection: Projection, aspect: f32, point: Vector3<f32>) -> Vector3<f32> {
+		let clip = projection.matrix(aspect) * point.extend(1.);
+		clip.truncate() / clip.w
+	}
+
+	fn assert_close(actual: f32, expected: f32) {
+		assert!(
+			(actual - expected).abs() < EPSILON,
+			"{} is not {}",
+			actual,
+			expected
+		);
+	}
+
+	#[test]
+	fn perspective_maps_near_to_0_and_far_to_1() {
+		let projection = Projection::perspective(Deg(60.), 0.5, 50.);
+		assert_close(project(projection, 1.5, Vector3::new(0., 0., -0.5)).z, 0.);
+		assert_close(project(projection, 1.5, Vector3::new(0., 0., -50.)).z, 1.);
+		let middle = project(projection, 1.5, Vector3::new(0., 0., -5.)).z;
+		assert!(middle > 0. && middle < 1.);
+	}
+
+	#[test]
+	fn up_in_view_space_is_down_in_clip_space() {
+		let projection = Projection::perspective(Deg(90.), 0.1, 10.);
+		let up = project(projection, 1., Vector3::new(0., 1., -1.));
+		assert_close(up.y, -1.);
+		let right = project(projection, 1., Vector3::new(1., 0., -1.));
+		assert_close(right.x, 1.);
+
+		let ortho = Projection::orthographic(2., 0.1, 10.);
+		assert_close(project(ortho, 1., Vector3::new(0., 1., -1.)).y, -1.);
+	}
+
+	#[test]
+	fn aspect_only_squeezes_x() {
+		let projection = Projection::perspective(Deg(90.), 0.1, 10.);
+		let point = project(projection, 2., Vector3::new(1., 1., -1.));
+		assert_close(point.x, 0.5);
+		assert_close(point.y, -1.);
+	}
+
+	#[test]
+	fn reversed_perspective_maps_near_to_1_and_far_to_0() {
+		let projection = Projection::perspective(Deg(60.), 0.5, 50.).reversed();
+		assert_close(project(projection, 1., Vector3::new(0., 0., -0.5)).z, 1.);
+		assert_close(project(projection, 1., Vector3::new(0., 0., -50.)).z, 0.);
+	}
+
+	#[test]
+	fn infinite_perspective_approaches_the_far_end() {
+		let projection = Projection::infinite_perspective(Deg(60.), 0.5);
+		assert_close(project(projection, 1., Vector3::new(0., 0., -0.5)).z, 0.);
+		let far = project(projection, 1., Vector3::new(0., 0., -1e6)).z;
+		assert!(far < 1. && far > 1. - EPSILON);
+
+		let reversed = projection.reversed();
+		assert_close(project(reversed, 1., Vector3::new(0., 0., -0.5)).z, 1.);
+		let far = project(reversed, 1., Vector3::new(0., 0., -1e6)).z;
+		assert!(far > 0. && far < EPSILON);
+	}
+
+	#[test]
+	fn orthographic_bounds_hit_the_clip_edges() {
+		let projection = Projection::orthographic(4., 1., 11.);
+		// height 4 at aspect 2 spans x -4..4, y -2..2
+		let corner = project(projection, 2., Vector3::new(4., 2., -1.));
+		assert_close(corner.x, 1.);
+		assert_close(corner.y, -1.);
+		assert_close(corner.z, 0.);
+		let corner = project(projection, 2., Vector3::new(-4., -2., -11.));
+		assert_close(corner.x, -1.);
+		assert_close(corner.y, 1.);
+		assert_close(corner.z, 1.);
+		// no perspective divide, distance doesn't move x
+		assert_close(project(projection, 2., Vector3::new(2., 0., -6.)).x, 0.5);
+
+		let reversed = projection.reversed();
+		assert_close(project(reversed, 2., Vector3::new(0., 0., -1.)).z, 1.);
+		assert_close(project(reversed, 2., Vector3::new(0., 0., -11.)).z, 0.);
+	}
+
+	#[test]
+	fn look_at_points_forward_at_target() {
+		let camera = Camera::new(Point3::new(1., 2., 3.), Projection::default())
+			.looking_at(Point3::new(4., -1., 0.));
+		let expected = Vector3::new(3., -3., -3.).normalize();
+		let forward = camera.forward();
+		assert_close(forward.dot(expected), 1.);
+		// and the view matrix puts the target straight ahead, down -z
+		let target = camera.view() * Point3::new(4., -1., 0.).to_homogeneous();
+		assert_close(target.x, 0.);
+		assert_close(target.y, 0.);
+		assert!(target.z < 0.);
+	}
+
+	#[test]
+	fn set_viewport_ignores_minimized_windows() {
+		let mut camera = Camera::default();
+		camera.set_viewport(1920, 1080);
+		assert_close(camera.aspect, 16. / 9.);
+		camera.set_viewport(0, 0);
+		assert_close(camera.aspect, 16. / 9.);
+	}
+
+	#[test]
+	fn fly_controller_clamps_pitch() {
+		let controller = FlyController::default();
+		let mut camera = Camera::default();
+		let mut input = InputState::new();
+		input.press_mouse(MouseButton::Right);
+		// far more than straight up
+		input.move_mouse(0., -10_000.);
+		controller.update(&mut camera, &input, 0.);
+		assert_eq!(camera.pitch, MAX_PITCH);
+		input.end_frame();
+		input.move_mouse(0., 20_000.);
+		controller.update(&mut camera, &input, 0.);
+		assert_eq!(camera.pitch, -MAX_PITCH);
+	}
+
+	#[test]
+	fn orbit_controller_wraps_yaw_and_clamps_pitch() {
+		let mut controller = OrbitController::new(Point3::new(0., 1., 0.), 5.);
+		let mut camera = Camera::default();
+		let mut input = InputState::new();
+		input.press_mouse(MouseButton::Left);
+		input.move_cursor(0., 0.);
+		// 1.5 turns of yaw and way past the pole
+		let pixels = 3. * std::f32::consts::PI / controller.sensitivity;
+		input.move_cursor(pixels as f64, 10_000.);
+		controller.update(&mut camera, &input);
+		assert!(camera.yaw.0 >= 0. && camera.yaw.0 < 2. * std::f32::consts::PI);
+		assert!((camera.yaw.0 - std::f32::consts::PI).abs() < 1e-3);
+		assert_eq!(camera.pitch, MAX_PITCH);
+		// still sits on the sphere around the target, looking at it
+		assert_close((camera.position - controller.target).magnitude(), 5.);
+		let to_target = (controller.target - camera.position).normalize();
+		assert_close(to_target.dot(camera.forward()), 1.);
+	}
+
+	#[test]
+	fn orbit_zoom_stops_at_min_distance() {
+		let mut controller = OrbitController::new(Point3::new(0., 0., 0.), 1.);
+		let mut camera = Camera::default();
+		let mut input = InputState::new();
+		for _ in 0..100 {
+			input.scroll_by(0., 5.);
+			controller.update(&mut camera, &input);
+			input.end_frame();
+		}
+		assert_close(controller.distance, controller.min_distance);
+		assert_close(
+			(camera.position - controller.target).magnitude(),
+			controller.min_distance,
+		);
+	}
+}
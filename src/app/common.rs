@@ -21,6 +21,12 @@ pub static REQUIRED_INSTANCE_EXTENSIONS: &[&CStr] = &[
 pub static REQUIRED_INSTANCE_EXTENSIONS: &[&CStr] =
 	&[vk::KHR_SURFACE_NAME, vk::KHR_WIN32_SURFACE_NAME];
 
+// without a surface, only what enumerating the devices needs
+#[cfg(target_os = "macos")]
+pub static HEADLESS_INSTANCE_EXTENSIONS: &[&CStr] = &[vk::KHR_PORTABILITY_ENUMERATION_NAME];
+#[cfg(not(target_os = "macos"))]
+pub static HEADLESS_INSTANCE_EXTENSIONS: &[&CStr] = &[];
+
 pub static REQUIRED_DEVICE_EXTENSIONS: &[&CStr] = &[
 	vk::KHR_SWAPCHAIN_NAME,
 	vk::KHR_SPIRV_1_4_NAME,
@@ -30,6 +36,31 @@ pub static REQUIRED_DEVICE_EXTENSIONS: &[&CStr] = &[
 	vk::KHR_PORTABILITY_SUBSET_NAME,
 ];
 
+// device extensions that became core, and in which version. required ones are only enabled
+// as extensions on devices older than that
+pub static PROMOTED_DEVICE_EXTENSIONS: &[(&CStr, u32)] = &[
+	(vk::KHR_SPIRV_1_4_NAME, vk::API_VERSION_1_2),
+	(vk::KHR_SYNCHRONIZATION2_NAME, vk::API_VERSION_1_3),
+	(vk::KHR_DYNAMIC_RENDERING_NAME, vk::API_VERSION_1_3),
+	(vk::KHR_SHADER_NON_SEMANTIC_INFO_NAME, vk::API_VERSION_1_3),
+];
+
+// ash 0.38 only has the 1.3 headers, the 1.4 bits we need are declared by hand
+pub const API_VERSION_1_4: u32 = vk::make_api_version(0, 1, 4, 0);
+
+// timeline semaphores, bda and the 1.1/1.2 feature structs need 1.2
+pub static MIN_API_VERSION: u32 = vk::API_VERSION_1_2;
+pub static MAX_API_VERSION: u32 = API_VERSION_1_4;
+
+pub fn version_string(version: u32) -> String {
+	format!(
+		"{}.{}.{}",
+		vk::api_version_major(version),
+		vk::api_version_minor(version),
+		vk::api_version_patch(version)
+	)
+}
+
 pub static FRAMES_IN_FLIGHT: usize = 2;
 
 // the only purpose of this struct is to keep the CString alive as long as the *const c_char
@@ -0,0 +1,122 @@
+use super::{
+	DeviceRequirements, Entry, FrameStatsConfig, GpuSelector, PipelineCacheDir, ValidationConfig,
+	VkError, pipeline_cache::PIPELINE_CACHE_ENV_VAR,
+};
+use std::path::PathBuf;
+
+pub const GPU_ENV_VAR: &str = "VKRS_GPU";
+// "linked", "system" or a path to a loader library
+pub const LOADER_ENV_VAR: &str = "VKRS_LOADER";
+
+// where the vulkan loader comes from
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LoaderSource {
+	// linked in at build time, the binary won't start without libvulkan
+	#[cfg(feature = "linked")]
+	Linked,
+	// dlopen the platform's default loader at runtime
+	System,
+	// dlopen a specific loader library
+	Path(PathBuf),
+}
+
+impl Default for LoaderSource {
+	fn default() -> LoaderSource {
+		#[cfg(feature = "linked")]
+		return LoaderSource::Linked;
+		#[cfg(not(feature = "linked"))]
+		return LoaderSource::System;
+	}
+}
+
+impl LoaderSource {
+	pub fn load(&self) -> Result<Entry, VkError> {
+		let entry = match self {
+			#[cfg(feature = "linked")]
+			LoaderSource::Linked => return Ok(Entry::linked()),
+			LoaderSource::System => unsafe { Entry::load() },
+			LoaderSource::Path(path) => unsafe { Entry::load_from(path) },
+		};
+		entry.map_err(|source| VkError::LoaderUnavailable {
+			loader: self.clone(),
+			source,
+		})
+	}
+}
+
+// everything VkCore needs to know before it starts picking things. set through the builder
+// methods, env vars win over whatever was set in code so CI / a user can force things
+#[derive(Clone, Debug, Default)]
+pub struct VkConfig {
+	pub loader: LoaderSource,
+	pub validation: ValidationConfig,
+	pub gpu: Option<GpuSelector>,
+	pub requirements: DeviceRequirements,
+	pub stats: FrameStatsConfig,
+	pub pipeline_cache: PipelineCacheDir,
+}
+
+impl VkConfig {
+	pub fn with_loader(mut self, loader: LoaderSource) -> VkConfig {
+		self.loader = loader;
+		self
+	}
+
+	pub fn with_validation(mut self, validation: ValidationConfig) -> VkConfig {
+		self.validation = validation;
+		self
+	}
+
+	pub fn with_gpu(mut self, selector: GpuSelector) -> VkConfig {
+		self.gpu = Some(selector);
+		self
+	}
+
+	pub fn with_requirements(mut self, requirements: DeviceRequirements) -> VkConfig {
+		self.requirements = requirements;
+		self
+	}
+
+	pub fn with_stats(mut self, stats: FrameStatsConfig) -> VkConfig {
+		self.stats = stats;
+		self
+	}
+
+	pub fn with_pipeline_cache(mut self, dir: PipelineCacheDir) -> VkConfig {
+		self.pipeline_cache = dir;
+		self
+	}
+
+	pub fn apply_env(mut self) -> Result<VkConfig, VkError> {
+		if let Ok(loader) = std::env::var(LOADER_ENV_VAR) {
+			self.loader = match loader.as_str() {
+				"" => self.loader,
+				#[cfg(feature = "linked")]
+				"linked" => LoaderSource::Linked,
+				// otherwise it would be taken as a library path and fail much later in dlopen
+				#[cfg(not(feature = "linked"))]
+				"linked" => {
+					return Err(VkError::InvalidConfig {
+						var: LOADER_ENV_VAR,
+						value: "linked (built without the `linked` feature)".to_owned(),
+					});
+				}
+				"system" => LoaderSource::System,
+				path => LoaderSource::Path(path.into()),
+			};
+		}
+		self.validation = self.validation.apply_env()?;
+		self.stats = self.stats.apply_env()?;
+		if let Ok(dir) = std::env::var(PIPELINE_CACHE_ENV_VAR)
+			&& !dir.is_empty()
+		{
+			self.pipeline_cache = dir.as_str().into();
+		}
+		if let Ok(selector) = std::env::var(GPU_ENV_VAR)
+			&& !selector.is_empty()
+		{
+			self.gpu = Some(selector.parse()?);
+		}
+		Ok(self)
+	}
+}
@@ -1,19 +1,92 @@
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
-use super::{Device, Instance, InstanceContext, Window, common::*, surface, vk};
-use std::ffi::{c_char, c_void};
+#[cfg(feature = "validation")]
+use super::debug_utils;
+use super::{
+	Device, DeviceReport, DeviceRequirements, DeviceSelectionError, DeviceSelectionReport,
+	EnabledFeatures, GpuSelector, Instance, InstanceContext, PipelineCache, Rejection, SubmitDeps,
+	Timeline, VkConfig, Window, common::*, surface, vk,
+};
+use ash::khr::{dynamic_rendering, synchronization2};
+#[cfg(feature = "validation")]
+use std::ffi::CString;
+use std::ffi::c_char;
+
+// queue families picked for a device. transfer/compute are only set when the device has a
+// family dedicated to them, otherwise that work goes to the graphics family
+#[derive(Clone, Copy, Debug)]
+pub struct QueueFamilies {
+	pub graphics: u32,
+	pub present: u32,
+	pub transfer: Option<u32>,
+	pub compute: Option<u32>,
+}
+
+// from DeviceContext::cmd_label, ends the label on drop
+pub struct CmdLabel<'a> {
+	#[cfg(feature = "validation")]
+	label: Option<(&'a debug_utils::Device, vk::CommandBuffer)>,
+	#[cfg(not(feature = "validation"))]
+	_device: std::marker::PhantomData<&'a DeviceContext>,
+}
+
+impl Drop for CmdLabel<'_> {
+	fn drop(&mut self) {
+		#[cfg(feature = "validation")]
+		if let Some((debug_utils, cmd_buff)) = self.label {
+			unsafe { debug_utils.cmd_end_debug_utils_label(cmd_buff) };
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueueKind {
+	Graphics,
+	Transfer,
+	Compute,
+}
 
 pub struct DeviceContext {
 	pub physical_device: vk::PhysicalDevice,
 	pub device: Device, // logical connection - 'i am running vk on this physical device'
+	pub api_version: u32,
+	// only loaded on 1.2 devices, where the core 1.3 entry points are null
+	dynamic_rendering_khr: Option<dynamic_rendering::Device>,
+	synchronization2_khr: Option<synchronization2::Device>,
+	// object names + cmd labels, only when the instance has debug utils
+	#[cfg(feature = "validation")]
+	debug_utils: Option<debug_utils::Device>,
 
 	pub graphics_index: u32,
 	pub present_index: u32,
 	pub graphics_queue: vk::Queue,
+	pub graphics_timeline: Timeline,
+	pub present_queue: vk::Queue,
+	pub present_timeline: Timeline,
+	// fall back to the graphics family/queue when there's no dedicated one
+	pub transfer_index: u32,
+	pub transfer_queue: vk::Queue,
+	pub transfer_timeline: Timeline,
+	pub compute_index: u32,
+	pub compute_queue: vk::Queue,
+	pub compute_timeline: Timeline,
+
+	pub properties: vk::PhysicalDeviceProperties,
+	pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+	pub enabled_features: EnabledFeatures,
+	// one-off uploads (staging copies, mip generation) go through this instead of the frame cmd buffs
+	pub upload_cmd_pool: vk::CommandPool,
+	// every pipeline should be created through this, saved to disk in VkCore::cleanup
+	pub pipeline_cache: PipelineCache,
+	pub selection_report: DeviceSelectionReport,
 }
 
 impl DeviceContext {
-	pub fn new(instance_ctx: &InstanceContext, window: &Window) -> DeviceContext {
+	pub fn new(
+		instance_ctx: &InstanceContext,
+		window: &Window,
+		config: &VkConfig,
+	) -> Result<DeviceContext, DeviceSelectionError> {
 		// tmp surface for device creation
 		let tmp_surface = unsafe {
 			ash_window::create_surface(
@@ -25,31 +98,134 @@ impl DeviceContext {
 			)
 			.expect("Should have been able to make tmp surface for device creation")
 		};
+		let device_ctx = DeviceContext::create(instance_ctx, Some(tmp_surface), config);
+		unsafe {
+			instance_ctx
+				.surface_loader()
+				.destroy_surface(tmp_surface, None);
+		}
+		device_ctx
+	}
 
-		let physical_device = DeviceContext::pick_physical_device(instance_ctx, tmp_surface);
-		let (graphics_idx, present_idx) =
-			DeviceContext::find_queue_families(instance_ctx, physical_device, tmp_surface);
+	// no surface to present to: no swapchain extension, and present is the graphics family
+	pub fn headless(
+		instance_ctx: &InstanceContext,
+		config: &VkConfig,
+	) -> Result<DeviceContext, DeviceSelectionError> {
+		DeviceContext::create(instance_ctx, None, config)
+	}
 
+	fn create(
+		instance_ctx: &InstanceContext,
+		surface: Option<vk::SurfaceKHR>,
+		config: &VkConfig,
+	) -> Result<DeviceContext, DeviceSelectionError> {
+		let mut requirements = config.requirements.clone();
+		if surface.is_none() {
+			requirements = requirements.without_extension(vk::KHR_SWAPCHAIN_NAME);
+		}
+		if instance_ctx.debug_printf {
+			requirements = requirements.require_extension(vk::KHR_SHADER_NON_SEMANTIC_INFO_NAME);
+		}
+		let (physical_device, enabled_features, selection_report) =
+			DeviceContext::pick_physical_device(
+				instance_ctx,
+				surface,
+				config.gpu.as_ref(),
+				&requirements,
+			)?;
+		let families = DeviceContext::find_queue_families(instance_ctx, physical_device, surface);
+		let graphics_idx = families.graphics;
+		let present_idx = families.present;
+		let transfer_idx = families.transfer.unwrap_or(graphics_idx);
+		let compute_idx = families.compute.unwrap_or(graphics_idx);
+
+		if !enabled_features.optional.is_empty() {
+			log::info!("Enabled optional: {}", enabled_features.optional.join(", "));
+		}
 		let device = DeviceContext::create_logical_device(
 			instance_ctx.instance(),
 			physical_device,
-			graphics_idx,
+			&families,
+			&enabled_features,
 		);
+		let api_version = enabled_features.api_version;
+		log::info!("Using vk {} on the device", version_string(api_version));
+		let (dynamic_rendering_khr, synchronization2_khr) = if api_version < vk::API_VERSION_1_3 {
+			(
+				Some(dynamic_rendering::Device::new(
+					instance_ctx.instance(),
+					&device,
+				)),
+				Some(synchronization2::Device::new(
+					instance_ctx.instance(),
+					&device,
+				)),
+			)
+		} else {
+			(None, None)
+		};
+		#[cfg(feature = "validation")]
+		let debug_utils = instance_ctx
+			.debug_messenger
+			.is_some()
+			.then(|| debug_utils::Device::new(instance_ctx.instance(), &device));
 		let graphics_queue = unsafe { device.get_device_queue(graphics_idx, 0) };
-
-		unsafe {
+		let present_queue = unsafe { device.get_device_queue(present_idx, 0) };
+		let transfer_queue = unsafe { device.get_device_queue(transfer_idx, 0) };
+		let compute_queue = unsafe { device.get_device_queue(compute_idx, 0) };
+		let properties = unsafe {
 			instance_ctx
-				.surface_loader()
-				.destroy_surface(tmp_surface, None);
-		}
+				.instance()
+				.get_physical_device_properties(physical_device)
+		};
+		let memory_properties = unsafe {
+			instance_ctx
+				.instance()
+				.get_physical_device_memory_properties(physical_device)
+		};
+		let graphics_timeline = Timeline::new(&device);
+		let present_timeline = Timeline::new(&device);
+		let transfer_timeline = Timeline::new(&device);
+		let compute_timeline = Timeline::new(&device);
+		let upload_cmd_pool = DeviceContext::create_upload_pool(&device, graphics_idx);
+		let pipeline_cache = PipelineCache::new(&device, &properties, &config.pipeline_cache);
 
-		DeviceContext {
+		let device_ctx = DeviceContext {
 			physical_device,
 			device,
+			api_version,
+			dynamic_rendering_khr,
+			synchronization2_khr,
+			#[cfg(feature = "validation")]
+			debug_utils,
 			graphics_index: graphics_idx,
 			present_index: present_idx,
 			graphics_queue,
-		}
+			graphics_timeline,
+			present_queue,
+			present_timeline,
+			transfer_index: transfer_idx,
+			transfer_queue,
+			transfer_timeline,
+			compute_index: compute_idx,
+			compute_queue,
+			compute_timeline,
+			properties,
+			memory_properties,
+			enabled_features,
+			upload_cmd_pool,
+			pipeline_cache,
+			selection_report,
+		};
+		device_ctx.set_name(device_ctx.graphics_queue, "graphics queue");
+		device_ctx.set_name(device_ctx.graphics_timeline.semaphore, "graphics timeline");
+		device_ctx.set_name(device_ctx.present_timeline.semaphore, "present timeline");
+		device_ctx.set_name(device_ctx.transfer_timeline.semaphore, "transfer timeline");
+		device_ctx.set_name(device_ctx.compute_timeline.semaphore, "compute timeline");
+		device_ctx.set_name(device_ctx.upload_cmd_pool, "upload cmd pool");
+		device_ctx.set_name(device_ctx.pipeline_cache.cache, "pipeline cache");
+		Ok(device_ctx)
 	}
 	pub fn device(&self) -> &Device {
 		&self.device
@@ -58,10 +234,202 @@ impl DeviceContext {
 		self.physical_device
 	}
 
+	// shows up in validation messages and renderdoc instead of the raw handle. no-op without
+	// debug utils
+	pub fn set_name<H: vk::Handle>(&self, handle: H, name: &str) {
+		#[cfg(feature = "validation")]
+		if let Some(debug_utils) = &self.debug_utils {
+			let name = CString::new(name).unwrap_or_default();
+			let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+				.object_handle(handle)
+				.object_name(&name);
+			unsafe {
+				debug_utils
+					.set_debug_utils_object_name(&name_info)
+					.expect("Should have been able to set object name")
+			};
+		}
+		#[cfg(not(feature = "validation"))]
+		let _ = (handle, name);
+	}
+
+	// labels everything recorded until the returned guard is dropped
+	pub fn cmd_label(&self, cmd_buff: vk::CommandBuffer, name: &str) -> CmdLabel<'_> {
+		#[cfg(feature = "validation")]
+		if let Some(debug_utils) = &self.debug_utils {
+			let name = CString::new(name).unwrap_or_default();
+			let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+			unsafe { debug_utils.cmd_begin_debug_utils_label(cmd_buff, &label) };
+			return CmdLabel {
+				label: Some((debug_utils, cmd_buff)),
+			};
+		}
+		#[cfg(not(feature = "validation"))]
+		let _ = (cmd_buff, name);
+		CmdLabel {
+			#[cfg(feature = "validation")]
+			label: None,
+			#[cfg(not(feature = "validation"))]
+			_device: std::marker::PhantomData,
+		}
+	}
+
+	// 1.3 commands that go through the KHR entry points on 1.2 devices. use these instead of
+	// calling them on `device` directly
+	pub fn cmd_pipeline_barrier2(&self, cmd_buff: vk::CommandBuffer, info: &vk::DependencyInfo) {
+		unsafe {
+			match &self.synchronization2_khr {
+				Some(sync2) => sync2.cmd_pipeline_barrier2(cmd_buff, info),
+				None => self.device.cmd_pipeline_barrier2(cmd_buff, info),
+			}
+		}
+	}
+	pub fn queue_submit2(
+		&self,
+		queue: vk::Queue,
+		submits: &[vk::SubmitInfo2],
+		fence: vk::Fence,
+	) -> ash::prelude::VkResult<()> {
+		unsafe {
+			match &self.synchronization2_khr {
+				Some(sync2) => sync2.queue_submit2(queue, submits, fence),
+				None => self.device.queue_submit2(queue, submits, fence),
+			}
+		}
+	}
+	pub fn cmd_begin_rendering(&self, cmd_buff: vk::CommandBuffer, info: &vk::RenderingInfo) {
+		unsafe {
+			match &self.dynamic_rendering_khr {
+				Some(dynamic_rendering) => dynamic_rendering.cmd_begin_rendering(cmd_buff, info),
+				None => self.device.cmd_begin_rendering(cmd_buff, info),
+			}
+		}
+	}
+	pub fn cmd_end_rendering(&self, cmd_buff: vk::CommandBuffer) {
+		unsafe {
+			match &self.dynamic_rendering_khr {
+				Some(dynamic_rendering) => dynamic_rendering.cmd_end_rendering(cmd_buff),
+				None => self.device.cmd_end_rendering(cmd_buff),
+			}
+		}
+	}
+
+	pub fn queue(&self, kind: QueueKind) -> vk::Queue {
+		match kind {
+			QueueKind::Graphics => self.graphics_queue,
+			QueueKind::Transfer => self.transfer_queue,
+			QueueKind::Compute => self.compute_queue,
+		}
+	}
+	pub fn queue_family(&self, kind: QueueKind) -> u32 {
+		match kind {
+			QueueKind::Graphics => self.graphics_index,
+			QueueKind::Transfer => self.transfer_index,
+			QueueKind::Compute => self.compute_index,
+		}
+	}
+	pub fn timeline(&self, kind: QueueKind) -> &Timeline {
+		match kind {
+			QueueKind::Graphics => &self.graphics_timeline,
+			QueueKind::Transfer => &self.transfer_timeline,
+			QueueKind::Compute => &self.compute_timeline,
+		}
+	}
+
+	pub fn find_memory_type(&self, type_filter: u32, mem_props: vk::MemoryPropertyFlags) -> u32 {
+		(0..self.memory_properties.memory_type_count)
+			.find(|&idx| {
+				type_filter & (1 << idx) != 0
+					&& self.memory_properties.memory_types[idx as usize]
+						.property_flags
+						.contains(mem_props)
+			})
+			.expect("Should have been able to find a suitable memory type")
+	}
+
+	pub fn allocate_memory(
+		&self,
+		requirements: vk::MemoryRequirements,
+		mem_props: vk::MemoryPropertyFlags,
+	) -> vk::DeviceMemory {
+		let alloc_info = vk::MemoryAllocateInfo {
+			allocation_size: requirements.size,
+			memory_type_index: self.find_memory_type(requirements.memory_type_bits, mem_props),
+			..Default::default()
+		};
+		unsafe {
+			self.device
+				.allocate_memory(&alloc_info, None)
+				.expect("Should have been able to allocate device memory")
+		}
+	}
+
+	// record with `record`, submit to the graphics queue and block until it's done.
+	// fine for loading, don't use it per frame
+	pub fn immediate_submit<F: FnOnce(vk::CommandBuffer)>(&self, record: F) {
+		let alloc_info = vk::CommandBufferAllocateInfo {
+			command_pool: self.upload_cmd_pool,
+			level: vk::CommandBufferLevel::PRIMARY,
+			command_buffer_count: 1,
+			..Default::default()
+		};
+		let cmd_buff = unsafe {
+			self.device
+				.allocate_command_buffers(&alloc_info)
+				.expect("Should have been able to allocate upload cmd buff")[0]
+		};
+		let begin_info = vk::CommandBufferBeginInfo {
+			flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+			..Default::default()
+		};
+		unsafe {
+			self.device
+				.begin_command_buffer(cmd_buff, &begin_info)
+				.expect("Should have been able to begin upload cmd buff")
+		};
+		record(cmd_buff);
+		unsafe {
+			self.device
+				.end_command_buffer(cmd_buff)
+				.expect("Should have been able to end upload cmd buff")
+		};
+
+		let value = self.graphics_timeline.submit(
+			self,
+			self.graphics_queue,
+			&[cmd_buff],
+			&SubmitDeps::default(),
+		);
+		self.graphics_timeline
+			.wait_for_value(&self.device, value, u64::MAX);
+		unsafe {
+			self.device
+				.free_command_buffers(self.upload_cmd_pool, &[cmd_buff]);
+		}
+	}
+
+	fn create_upload_pool(device: &Device, graphics_idx: u32) -> vk::CommandPool {
+		let pool_info = vk::CommandPoolCreateInfo {
+			flags: vk::CommandPoolCreateFlags::TRANSIENT,
+			queue_family_index: graphics_idx,
+			..Default::default()
+		};
+		unsafe {
+			device
+				.create_command_pool(&pool_info, None)
+				.expect("Should have been able to create upload command pool")
+		}
+	}
+
+	// every device gets a report entry. the selector (if any) narrows things down first, then the
+	// hard requirements, then the highest score among whatever is left wins
 	fn pick_physical_device(
 		instance_ctx: &InstanceContext,
-		tmp_surface: vk::SurfaceKHR,
-	) -> vk::PhysicalDevice {
+		surface: Option<vk::SurfaceKHR>,
+		selector: Option<&GpuSelector>,
+		requirements: &DeviceRequirements,
+	) -> Result<(vk::PhysicalDevice, EnabledFeatures, DeviceSelectionReport), DeviceSelectionError>
+	{
 		let instance = instance_ctx.instance();
 		let surface_loader = instance_ctx.surface_loader();
 
@@ -71,126 +439,151 @@ impl DeviceContext {
 				.expect("Should be able to list physical devices")
 		};
 		if devices.is_empty() {
-			panic!("No vk physical devices to use");
+			return Err(DeviceSelectionError::NoDevices);
 		}
-		let mut candidates: Vec<(u32, vk::PhysicalDevice, String)> = Vec::new();
-		for device in devices.into_iter() {
-			// query device features
-			let mut vk12_features = vk::PhysicalDeviceVulkan12Features::default();
-			let mut vk11_features = vk::PhysicalDeviceVulkan11Features {
-				p_next: &mut vk12_features as *const _ as *mut c_void,
-				..Default::default()
-			};
-			let mut features2 = vk::PhysicalDeviceFeatures2 {
-				p_next: &mut vk11_features as *const _ as *mut c_void,
-				..Default::default()
-			};
+		let mut report = DeviceSelectionReport {
+			selector: selector.cloned(),
+			..Default::default()
+		};
+		let mut enabled_per_device: Vec<Option<EnabledFeatures>> = Vec::new();
+		for (index, &device) in devices.iter().enumerate() {
+			let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+			let mut properties2 =
+				vk::PhysicalDeviceProperties2::default().push_next(&mut id_properties);
 			unsafe {
-				instance.get_physical_device_features2(device, &mut features2);
+				instance.get_physical_device_properties2(device, &mut properties2);
 			}
 
-			let properties = unsafe { instance.get_physical_device_properties(device) };
-			let name = properties
-				.device_name_as_c_str()
-				.unwrap()
-				.to_str()
-				.unwrap()
-				.to_owned();
-			let mut score = 0;
-			log::info!("Checking device {:?}", name);
-			if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-				score += 1000;
-			}
+			let properties = properties2.properties;
+			let mut entry = DeviceReport {
+				index,
+				name: properties
+					.device_name_as_c_str()
+					.unwrap()
+					.to_string_lossy()
+					.into_owned(),
+				device_type: properties.device_type,
+				vendor_id: properties.vendor_id,
+				device_id: properties.device_id,
+				uuid: id_properties.device_uuid,
+				api_version: properties.api_version,
+				driver_version: properties.driver_version,
+				score: None,
+				rejection: None,
+			};
+			log::debug!("Checking device {:?}", entry.name);
 			// requirements
-			if features2.features.geometry_shader != vk::TRUE {
-				log::warn!("device {} does not have a geometry shader", name);
-				continue;
-			}
-			if features2.features.geometry_shader != vk::TRUE {
-				log::warn!("device {:?} has no geometry shader", name);
-				continue;
-			}
-			if vk11_features.shader_draw_parameters == vk::FALSE {
-				log::warn!("Device {} does not support shaderDrawParameters", name);
-				continue;
-			}
-			if vk12_features.buffer_device_address != vk::TRUE {
-				log::warn!("Device {} does not support bufferDeviceAddress ext", name);
-				continue;
-			}
-			if !DeviceContext::has_minimum_queue_families_reqs(
+			let checked = if selector.is_some_and(|s| !s.matches(index, &entry)) {
+				Err(Rejection::NotSelected)
+			} else if !DeviceContext::has_minimum_queue_families_reqs(
 				instance,
 				device,
 				surface_loader,
-				tmp_surface,
+				surface,
 			) {
-				log::warn!("Device {} has no suitable queue families, skipping", name);
-				continue;
+				Err(Rejection::NoSuitableQueueFamilies)
+			} else {
+				// the instance version caps what the device can be used as
+				let api_version = properties.api_version.min(instance_ctx.api_version);
+				requirements.check(instance, device, api_version)
+			};
+			match checked {
+				Ok(enabled) => {
+					let mut score = properties.limits.max_image_dimension2_d;
+					if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
+						score += 1000;
+					}
+					entry.score = Some(score);
+					enabled_per_device.push(Some(enabled));
+				}
+				Err(rejection) => {
+					log::debug!("Rejected device {:?}: {}", entry.name, rejection);
+					entry.rejection = Some(rejection);
+					enabled_per_device.push(None);
+				}
 			}
-
-			score += properties.limits.max_image_dimension2_d;
-			candidates.push((score, device, name));
+			report.devices.push(entry);
 		}
-		if candidates.is_empty() {
-			panic!("Could not find suitable GPU");
+		// max_by_key keeps the last max, so go in reverse for enumeration order on ties
+		report.picked = report
+			.devices
+			.iter()
+			.rev()
+			.filter_map(|d| d.score.map(|score| (score, d.index)))
+			.max_by_key(|&(score, _)| score)
+			.map(|(_, index)| index);
+		match report.picked {
+			Some(index) => {
+				log::info!("Device selection:\n{}", report);
+				let enabled = enabled_per_device[index]
+					.take()
+					.expect("picked device should have passed its requirements");
+				Ok((devices[index], enabled, report))
+			}
+			None => Err(DeviceSelectionError::NoSuitableDevice(report)),
 		}
-		candidates.sort_by(|a, b| b.0.cmp(&a.0));
-		let &(score, device, ref name) = &candidates[0];
-		log::info!("picked device {}: score = {}", name, score);
-		device
 	}
 
 	fn create_logical_device(
 		instance: &Instance,
 		phys_device: vk::PhysicalDevice,
-		graphics_idx: u32,
+		families: &QueueFamilies,
+		enabled: &EnabledFeatures,
 	) -> Device {
-		// queue
+		// one queue per distinct family
 		let prio: f32 = 0.;
-		let device_queue_create_info = vk::DeviceQueueCreateInfo {
-			queue_family_index: graphics_idx,
-			p_queue_priorities: &prio,
-			queue_count: 1,
-			..Default::default()
-		};
-		//features
-		let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures {
-			dynamic_rendering: vk::TRUE,
-			..Default::default()
-		};
-		let mut sync2_features = vk::PhysicalDeviceSynchronization2Features {
-			synchronization2: vk::TRUE,
-			..Default::default()
-		};
-		let mut extended_dynamic_state = vk::PhysicalDeviceExtendedDynamicStateFeaturesEXT {
-			extended_dynamic_state: vk::TRUE,
-			..Default::default()
-		};
-		// device extensions
-		let device_extensions: Vec<*const c_char> = REQUIRED_DEVICE_EXTENSIONS
+		let mut unique_families = vec![families.graphics];
+		let optional_families = [Some(families.present), families.transfer, families.compute];
+		for family in optional_families.into_iter().flatten() {
+			if !unique_families.contains(&family) {
+				unique_families.push(family);
+			}
+		}
+		let queue_create_infos: Vec<vk::DeviceQueueCreateInfo> = unique_families
 			.iter()
-			.map(|ext| ext.as_ptr())
+			.map(|&family| vk::DeviceQueueCreateInfo {
+				queue_family_index: family,
+				p_queue_priorities: &prio,
+				queue_count: 1,
+				..Default::default()
+			})
 			.collect();
-		let mut vk11_features = vk::PhysicalDeviceVulkan11Features {
-			shader_draw_parameters: vk::TRUE,
+		// features/extensions come straight from the requirements check
+		let device_extensions: Vec<*const c_char> =
+			enabled.extensions.iter().map(|ext| ext.as_ptr()).collect();
+		let mut features = enabled.features;
+		let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures {
+			dynamic_rendering: features.vk13.dynamic_rendering,
 			..Default::default()
 		};
-		let mut vk12_features = vk::PhysicalDeviceVulkan12Features {
-			buffer_device_address: vk::TRUE,
+		let mut sync2 = vk::PhysicalDeviceSynchronization2Features {
+			synchronization2: features.vk13.synchronization2,
 			..Default::default()
 		};
-		let device_create_info = vk::DeviceCreateInfo {
-			p_queue_create_infos: &device_queue_create_info,
-			queue_create_info_count: 1,
-			enabled_extension_count: REQUIRED_DEVICE_EXTENSIONS.len() as u32,
+		let mut device_create_info = vk::DeviceCreateInfo {
+			p_queue_create_infos: queue_create_infos.as_ptr(),
+			queue_create_info_count: queue_create_infos.len() as u32,
+			enabled_extension_count: device_extensions.len() as u32,
 			pp_enabled_extension_names: device_extensions.as_ptr(),
+			p_enabled_features: &features.core,
 			..Default::default()
 		}
-		.push_next(&mut dynamic_rendering)
-		.push_next(&mut extended_dynamic_state)
-		.push_next(&mut sync2_features)
-		.push_next(&mut vk11_features)
-		.push_next(&mut vk12_features);
+		.push_next(&mut features.vk11)
+		.push_next(&mut features.vk12);
+		if enabled.api_version >= API_VERSION_1_4 {
+			device_create_info = device_create_info.push_next(&mut features.vk14);
+		}
+		// 1.2 devices get the KHR versions of the 1.3 features we use instead
+		if enabled.api_version >= vk::API_VERSION_1_3 {
+			device_create_info = device_create_info.push_next(&mut features.vk13);
+		} else {
+			if enabled.has_extension(vk::KHR_DYNAMIC_RENDERING_NAME) {
+				device_create_info = device_create_info.push_next(&mut dynamic_rendering);
+			}
+			if enabled.has_extension(vk::KHR_SYNCHRONIZATION2_NAME) {
+				device_create_info = device_create_info.push_next(&mut sync2);
+			}
+		}
 
 		let device = unsafe {
 			instance
@@ -204,19 +597,23 @@ impl DeviceContext {
 		instance: &Instance,
 		device: vk::PhysicalDevice,
 		surface_loader: &surface::Instance,
-		tmp_surface: vk::SurfaceKHR,
+		surface: Option<vk::SurfaceKHR>,
 	) -> bool {
 		let queue_family_properties =
 			unsafe { instance.get_physical_device_queue_family_properties(device) };
 		let supports_graphics = queue_family_properties
 			.iter()
 			.any(|properties| properties.queue_flags.contains(vk::QueueFlags::GRAPHICS));
+		// without a surface there's nothing to present to
+		let Some(surface) = surface else {
+			return supports_graphics;
+		};
 		let supports_present = queue_family_properties
 			.iter()
 			.enumerate()
 			.any(|(idx, _)| unsafe {
 				surface_loader
-					.get_physical_device_surface_support(device, idx as u32, tmp_surface)
+					.get_physical_device_surface_support(device, idx as u32, surface)
 					.expect("Should be able to check for present support")
 			});
 
@@ -226,18 +623,24 @@ impl DeviceContext {
 	fn find_queue_families(
 		instance_ctx: &InstanceContext,
 		phys_device: vk::PhysicalDevice,
-		tmp_surface: vk::SurfaceKHR,
-	) -> (u32, u32) {
+		surface: Option<vk::SurfaceKHR>,
+	) -> QueueFamilies {
 		let instance = instance_ctx.instance();
 		let surface_loader = instance_ctx.surface_loader();
 
 		let queue_family_properties =
 			unsafe { instance.get_physical_device_queue_family_properties(phys_device) };
 
+		// headless, graphics stands in for present
 		let supports_present = |index: usize| -> bool {
+			let Some(surface) = surface else {
+				return queue_family_properties[index]
+					.queue_flags
+					.contains(vk::QueueFlags::GRAPHICS);
+			};
 			unsafe {
 				surface_loader
-					.get_physical_device_surface_support(phys_device, index as u32, tmp_surface)
+					.get_physical_device_surface_support(phys_device, index as u32, surface)
 					.unwrap_or(false)
 			}
 		};
@@ -271,11 +674,42 @@ impl DeviceContext {
 				})
 				.expect("Should have been able to find graphics and present queues")
 		};
+		// dedicated families. for transfer, transfer-only ones are usually the copy engines so
+		// prefer those over sharing the async compute family
+		let compute_idx = queue_family_properties.iter().position(|properties| {
+			properties.queue_flags.contains(vk::QueueFlags::COMPUTE)
+				&& !properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+		});
+		let transfer_idx = queue_family_properties
+			.iter()
+			.position(|properties| {
+				properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
+					&& !properties
+						.queue_flags
+						.intersects(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+			})
+			.or_else(|| {
+				queue_family_properties
+					.iter()
+					.enumerate()
+					.position(|(idx, properties)| {
+						properties.queue_flags.contains(vk::QueueFlags::TRANSFER)
+							&& !properties.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+							&& Some(idx) != compute_idx
+					})
+			});
 		log::info!(
-			"graphics index: [{}], present index: [{}]",
+			"graphics index: [{}], present index: [{}], transfer index: [{:?}], compute index: [{:?}]",
 			graphics_idx,
-			present_idx
+			present_idx,
+			transfer_idx,
+			compute_idx
 		);
-		(graphics_idx as u32, present_idx as u32)
+		QueueFamilies {
+			graphics: graphics_idx as u32,
+			present: present_idx as u32,
+			transfer: transfer_idx.map(|idx| idx as u32),
+			compute: compute_idx.map(|idx| idx as u32),
+		}
 	}
 }
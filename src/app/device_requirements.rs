@@ -0,0 +1,351 @@
+use super::{Instance, Rejection, common::*, vk};
+use std::ffi::{CStr, c_void};
+
+// points at one Bool32 inside DeviceFeatures, see the `device_feature!` macro
+pub type FeatureField = fn(&mut DeviceFeatures) -> &mut vk::Bool32;
+
+// device_feature!(vk12.timeline_semaphore) -> ("vk12.timeline_semaphore", accessor). works for
+// core, vk11, vk12, vk13 and vk14
+#[macro_export]
+macro_rules! device_feature {
+	($version:ident . $field:ident) => {
+		(
+			concat!(stringify!($version), ".", stringify!($field)),
+			(|f: &mut $crate::app::DeviceFeatures| &mut f.$version.$field)
+				as $crate::app::FeatureField,
+		)
+	};
+}
+
+// VkPhysicalDeviceVulkan14Features, missing from ash 0.38. same layout as the registry's
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalDeviceVulkan14Features {
+	pub s_type: vk::StructureType,
+	pub p_next: *mut c_void,
+	pub global_priority_query: vk::Bool32,
+	pub shader_subgroup_rotate: vk::Bool32,
+	pub shader_subgroup_rotate_clustered: vk::Bool32,
+	pub shader_float_controls2: vk::Bool32,
+	pub shader_expect_assume: vk::Bool32,
+	pub rectangular_lines: vk::Bool32,
+	pub bresenham_lines: vk::Bool32,
+	pub smooth_lines: vk::Bool32,
+	pub stippled_rectangular_lines: vk::Bool32,
+	pub stippled_bresenham_lines: vk::Bool32,
+	pub stippled_smooth_lines: vk::Bool32,
+	pub vertex_attribute_instance_rate_divisor: vk::Bool32,
+	pub vertex_attribute_instance_rate_zero_divisor: vk::Bool32,
+	pub index_type_uint8: vk::Bool32,
+	pub dynamic_rendering_local_read: vk::Bool32,
+	pub maintenance5: vk::Bool32,
+	pub maintenance6: vk::Bool32,
+	pub pipeline_protected_access: vk::Bool32,
+	pub pipeline_robustness: vk::Bool32,
+	pub host_image_copy: vk::Bool32,
+	pub push_descriptor: vk::Bool32,
+}
+
+unsafe impl Send for PhysicalDeviceVulkan14Features {}
+unsafe impl Sync for PhysicalDeviceVulkan14Features {}
+
+impl Default for PhysicalDeviceVulkan14Features {
+	fn default() -> PhysicalDeviceVulkan14Features {
+		PhysicalDeviceVulkan14Features {
+			s_type: <Self as vk::TaggedStructure>::STRUCTURE_TYPE,
+			p_next: std::ptr::null_mut(),
+			global_priority_query: vk::FALSE,
+			shader_subgroup_rotate: vk::FALSE,
+			shader_subgroup_rotate_clustered: vk::FALSE,
+			shader_float_controls2: vk::FALSE,
+			shader_expect_assume: vk::FALSE,
+			rectangular_lines: vk::FALSE,
+			bresenham_lines: vk::FALSE,
+			smooth_lines: vk::FALSE,
+			stippled_rectangular_lines: vk::FALSE,
+			stippled_bresenham_lines: vk::FALSE,
+			stippled_smooth_lines: vk::FALSE,
+			vertex_attribute_instance_rate_divisor: vk::FALSE,
+			vertex_attribute_instance_rate_zero_divisor: vk::FALSE,
+			index_type_uint8: vk::FALSE,
+			dynamic_rendering_local_read: vk::FALSE,
+			maintenance5: vk::FALSE,
+			maintenance6: vk::FALSE,
+			pipeline_protected_access: vk::FALSE,
+			pipeline_robustness: vk::FALSE,
+			host_image_copy: vk::FALSE,
+			push_descriptor: vk::FALSE,
+		}
+	}
+}
+
+// VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_VULKAN_1_4_FEATURES
+unsafe impl vk::TaggedStructure for PhysicalDeviceVulkan14Features {
+	const STRUCTURE_TYPE: vk::StructureType = vk::StructureType::from_raw(55);
+}
+unsafe impl vk::ExtendsPhysicalDeviceFeatures2 for PhysicalDeviceVulkan14Features {}
+unsafe impl vk::ExtendsDeviceCreateInfo for PhysicalDeviceVulkan14Features {}
+
+// every feature struct we know about, p_next always null. vk14 only gets queried (and enabled)
+// on 1.4 devices
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DeviceFeatures {
+	pub core: vk::PhysicalDeviceFeatures,
+	pub vk11: vk::PhysicalDeviceVulkan11Features<'static>,
+	pub vk12: vk::PhysicalDeviceVulkan12Features<'static>,
+	pub vk13: vk::PhysicalDeviceVulkan13Features<'static>,
+	pub vk14: PhysicalDeviceVulkan14Features,
+}
+
+impl DeviceFeatures {
+	// below 1.3 there's no Vulkan13Features, dynamic rendering and sync2 come from their KHR
+	// structs (when the extensions are there) and land in the vk13 fields anyway
+	pub fn query(
+		instance: &Instance,
+		device: vk::PhysicalDevice,
+		api_version: u32,
+		has_extension: impl Fn(&CStr) -> bool,
+	) -> DeviceFeatures {
+		let mut features = DeviceFeatures::default();
+		let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+		let mut sync2 = vk::PhysicalDeviceSynchronization2Features::default();
+		let mut features2 = vk::PhysicalDeviceFeatures2::default()
+			.push_next(&mut features.vk11)
+			.push_next(&mut features.vk12);
+		if api_version >= API_VERSION_1_4 {
+			features2 = features2.push_next(&mut features.vk14);
+		}
+		if api_version >= vk::API_VERSION_1_3 {
+			features2 = features2.push_next(&mut features.vk13);
+		} else {
+			if has_extension(vk::KHR_DYNAMIC_RENDERING_NAME) {
+				features2 = features2.push_next(&mut dynamic_rendering);
+			}
+			if has_extension(vk::KHR_SYNCHRONIZATION2_NAME) {
+				features2 = features2.push_next(&mut sync2);
+			}
+		}
+		unsafe {
+			instance.get_physical_device_features2(device, &mut features2);
+		}
+		let core = features2.features;
+		features.core = core;
+		// don't hand out pointers into this stack frame
+		features.vk11.p_next = std::ptr::null_mut();
+		features.vk12.p_next = std::ptr::null_mut();
+		features.vk13.p_next = std::ptr::null_mut();
+		features.vk14.p_next = std::ptr::null_mut();
+		if api_version < vk::API_VERSION_1_3 {
+			features.vk13.dynamic_rendering = dynamic_rendering.dynamic_rendering;
+			features.vk13.synchronization2 = sync2.synchronization2;
+		}
+		features
+	}
+}
+
+// what the app needs from a device. required entries filter devices out in
+// pick_physical_device, optional ones get enabled when they're there
+#[derive(Clone, Debug)]
+pub struct DeviceRequirements {
+	pub required_extensions: Vec<&'static CStr>,
+	pub optional_extensions: Vec<&'static CStr>,
+	pub required_features: Vec<(&'static str, FeatureField)>,
+	pub optional_features: Vec<(&'static str, FeatureField)>,
+}
+
+// what create_logical_device actually turned on for the picked device
+#[derive(Clone, Debug, Default)]
+pub struct EnabledFeatures {
+	// min of the instance and device versions
+	pub api_version: u32,
+	pub features: DeviceFeatures,
+	pub extensions: Vec<&'static CStr>,
+	// names of the optional features/extensions that made it in
+	pub optional: Vec<&'static str>,
+}
+
+impl EnabledFeatures {
+	pub fn has_extension(&self, name: &CStr) -> bool {
+		self.extensions.contains(&name)
+	}
+}
+
+impl Default for DeviceRequirements {
+	// what the renderer itself needs
+	fn default() -> DeviceRequirements {
+		DeviceRequirements::new()
+			.require_feature(device_feature!(core.geometry_shader))
+			.require_feature(device_feature!(vk11.shader_draw_parameters))
+			.require_feature(device_feature!(vk12.buffer_device_address))
+			.require_feature(device_feature!(vk12.timeline_semaphore))
+			.require_feature(device_feature!(vk13.dynamic_rendering))
+			.require_feature(device_feature!(vk13.synchronization2))
+			.optional_feature(device_feature!(core.sampler_anisotropy))
+			.optional_feature(device_feature!(core.pipeline_statistics_query))
+			.optional_feature(device_feature!(core.occlusion_query_precise))
+	}
+}
+
+impl DeviceRequirements {
+	// just the extensions everything needs (swapchain etc.), no features
+	pub fn new() -> DeviceRequirements {
+		DeviceRequirements {
+			required_extensions: REQUIRED_DEVICE_EXTENSIONS.to_vec(),
+			optional_extensions: Vec::new(),
+			required_features: Vec::new(),
+			optional_features: Vec::new(),
+		}
+	}
+
+	pub fn require_extension(mut self, name: &'static CStr) -> DeviceRequirements {
+		self.required_extensions.push(name);
+		self
+	}
+
+	// e.g. swapchain on a device that never presents
+	pub fn without_extension(mut self, name: &CStr) -> DeviceRequirements {
+		self.required_extensions.retain(|&ext| ext != name);
+		self.optional_extensions.retain(|&ext| ext != name);
+		self
+	}
+
+	pub fn optional_extension(mut self, name: &'static CStr) -> DeviceRequirements {
+		self.optional_extensions.push(name);
+		self
+	}
+
+	pub fn require_feature(mut self, feature: (&'static str, FeatureField)) -> DeviceRequirements {
+		self.required_features.push(feature);
+		self
+	}
+
+	pub fn optional_feature(mut self, feature: (&'static str, FeatureField)) -> DeviceRequirements {
+		self.optional_features.push(feature);
+		self
+	}
+
+	// first unmet requirement, or everything that should go into the device create info
+	pub fn check(
+		&self,
+		instance: &Instance,
+		device: vk::PhysicalDevice,
+		api_version: u32,
+	) -> Result<EnabledFeatures, Rejection> {
+		if api_version < MIN_API_VERSION {
+			return Err(Rejection::ApiVersion(api_version));
+		}
+		let available = unsafe {
+			instance
+				.enumerate_device_extension_properties(device)
+				.expect("Should have been able to list device extensions")
+		};
+		let is_available = |name: &CStr| {
+			available
+				.iter()
+				.any(|ext| ext.extension_name_as_c_str() == Ok(name))
+		};
+		let is_core = |name: &CStr| {
+			PROMOTED_DEVICE_EXTENSIONS
+				.iter()
+				.any(|&(ext, version)| ext == name && version <= api_version)
+		};
+		let mut enabled = EnabledFeatures {
+			api_version,
+			..Default::default()
+		};
+		for &name in &self.required_extensions {
+			if is_core(name) {
+				continue;
+			}
+			if !is_available(name) {
+				return Err(Rejection::MissingExtension(
+					name.to_string_lossy().into_owned(),
+				));
+			}
+			enabled.extensions.push(name);
+		}
+		for &name in &self.optional_extensions {
+			if !is_core(name) && is_available(name) && !enabled.extensions.contains(&name) {
+				enabled.extensions.push(name);
+				enabled.optional.push(name.to_str().unwrap_or("?"));
+			}
+		}
+
+		let mut supported = DeviceFeatures::query(instance, device, api_version, is_available);
+		for &(name, field) in &self.required_features {
+			if name.starts_with("vk14.") && api_version < API_VERSION_1_4 {
+				return Err(Rejection::FeatureApiVersion(name, API_VERSION_1_4));
+			}
+			if *field(&mut supported) != vk::TRUE {
+				return Err(Rejection::MissingFeature(name));
+			}
+			*field(&mut enabled.features) = vk::TRUE;
+		}
+		for &(name, field) in &self.optional_features {
+			if *field(&mut supported) == vk::TRUE {
+				*field(&mut enabled.features) = vk::TRUE;
+				enabled.optional.push(name);
+			}
+		}
+		Ok(enabled)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn vulkan14_features_match_the_c_layout() {
+		// sType + pNext, then 22 VkBool32s
+		assert_eq!(
+			std::mem::size_of::<PhysicalDeviceVulkan14Features>(),
+			std::mem::size_of::<vk::BaseOutStructure>() + 22 * std::mem::size_of::<vk::Bool32>()
+		);
+		let features = PhysicalDeviceVulkan14Features::default();
+		assert_eq!(features.s_type, vk::StructureType::from_raw(55));
+		assert!(features.p_next.is_null());
+		assert_eq!(features.push_descriptor, vk::FALSE);
+	}
+
+	#[test]
+	fn feature_macro_reaches_vk14() {
+		let (name, field) = device_feature!(vk14.maintenance5);
+		assert_eq!(name, "vk14.maintenance5");
+		let mut features = DeviceFeatures::default();
+		*field(&mut features) = vk::TRUE;
+		assert_eq!(features.vk14.maintenance5, vk::TRUE);
+		assert_eq!(features.vk13.maintenance4, vk::FALSE);
+	}
+
+	#[test]
+	fn version_rejection_names_the_feature() {
+		let rejection = Rejection::FeatureApiVersion("vk14.push_descriptor", API_VERSION_1_4);
+		assert_eq!(
+			rejection.to_string(),
+			"feature vk14.push_descriptor needs vk 1.4.0"
+		);
+	}
+
+	#[test]
+	fn headless_requirements_drop_the_swapchain() {
+		let requirements = DeviceRequirements::default()
+			.optional_extension(vk::KHR_SWAPCHAIN_NAME)
+			.without_extension(vk::KHR_SWAPCHAIN_NAME);
+		assert!(
+			!requirements
+				.required_extensions
+				.contains(&vk::KHR_SWAPCHAIN_NAME)
+		);
+		assert!(
+			!requirements
+				.optional_extensions
+				.contains(&vk::KHR_SWAPCHAIN_NAME)
+		);
+		assert!(
+			requirements
+				.required_extensions
+				.contains(&vk::KHR_DYNAMIC_RENDERING_NAME)
+		);
+	}
+}
@@ -0,0 +1,244 @@
+use super::{common::*, vk};
+use std::fmt;
+use std::str::FromStr;
+
+// forces a specific physical device instead of letting the scorer pick. parsed from VKRS_GPU:
+//   name:<substring>          case insensitive match on the device name (a bare string does the same)
+//   index:<n>                 position in vkEnumeratePhysicalDevices
+//   id:<vendor>[:<device>]    pci ids in hex, e.g. id:10de:2684
+//   uuid:<hex>                deviceUUID, dashes optional
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GpuSelector {
+	Name(String),
+	Index(usize),
+	VendorDevice {
+		vendor_id: u32,
+		device_id: Option<u32>,
+	},
+	Uuid([u8; vk::UUID_SIZE]),
+}
+
+impl GpuSelector {
+	pub fn matches(&self, index: usize, report: &DeviceReport) -> bool {
+		match self {
+			GpuSelector::Name(name) => report.name.to_lowercase().contains(&name.to_lowercase()),
+			GpuSelector::Index(i) => *i == index,
+			GpuSelector::VendorDevice {
+				vendor_id,
+				device_id,
+			} => {
+				*vendor_id == report.vendor_id
+					&& device_id.is_none_or(|device_id| device_id == report.device_id)
+			}
+			GpuSelector::Uuid(uuid) => *uuid == report.uuid,
+		}
+	}
+}
+
+impl FromStr for GpuSelector {
+	type Err = DeviceSelectionError;
+
+	fn from_str(s: &str) -> Result<GpuSelector, DeviceSelectionError> {
+		let invalid = |reason: &str| DeviceSelectionError::InvalidSelector {
+			selector: s.to_owned(),
+			reason: reason.to_owned(),
+		};
+		let hex = |v: &str| u32::from_str_radix(v.trim_start_matches("0x"), 16);
+		let (kind, value) = s.split_once(':').unwrap_or(("name", s));
+		match kind {
+			"name" if !value.is_empty() => Ok(GpuSelector::Name(value.to_owned())),
+			"name" => Err(invalid("empty name")),
+			"index" => value
+				.parse()
+				.map(GpuSelector::Index)
+				.map_err(|_| invalid("index should be a number")),
+			"id" => {
+				let (vendor, device) = match value.split_once(':') {
+					Some((vendor, device)) => (vendor, Some(device)),
+					None => (value, None),
+				};
+				let vendor_id = hex(vendor).map_err(|_| invalid("vendor id should be hex"))?;
+				let device_id = device
+					.map(hex)
+					.transpose()
+					.map_err(|_| invalid("device id should be hex"))?;
+				Ok(GpuSelector::VendorDevice {
+					vendor_id,
+					device_id,
+				})
+			}
+			"uuid" => {
+				let digits: String = value.chars().filter(|c| *c != '-').collect();
+				// checked before slicing, a multi byte char would split mid character
+				if digits.len() != vk::UUID_SIZE * 2
+					|| !digits.bytes().all(|b| b.is_ascii_hexdigit())
+				{
+					return Err(invalid("uuid should be 32 hex digits"));
+				}
+				let mut uuid = [0u8; vk::UUID_SIZE];
+				for (i, byte) in uuid.iter_mut().enumerate() {
+					*byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+						.map_err(|_| invalid("uuid should be 32 hex digits"))?;
+				}
+				Ok(GpuSelector::Uuid(uuid))
+			}
+			_ => Err(invalid("expected name:, index:, id: or uuid:")),
+		}
+	}
+}
+
+// first requirement a device failed, in the order they're checked
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Rejection {
+	NotSelected,
+	// usable version (min of instance and device) is below MIN_API_VERSION
+	ApiVersion(u32),
+	MissingExtension(String),
+	MissingFeature(&'static str),
+	// the feature lives in a newer feature struct than the usable version has
+	FeatureApiVersion(&'static str, u32),
+	NoSuitableQueueFamilies,
+}
+
+impl fmt::Display for Rejection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Rejection::NotSelected => write!(f, "doesn't match the gpu selector"),
+			Rejection::ApiVersion(version) => write!(
+				f,
+				"vk {} is older than the required {}",
+				version_string(*version),
+				version_string(MIN_API_VERSION)
+			),
+			Rejection::MissingExtension(ext) => write!(f, "missing extension {}", ext),
+			Rejection::MissingFeature(feature) => write!(f, "missing feature {}", feature),
+			Rejection::FeatureApiVersion(feature, version) => write!(
+				f,
+				"feature {} needs vk {}",
+				feature,
+				version_string(*version)
+			),
+			Rejection::NoSuitableQueueFamilies => write!(f, "no graphics + present queue families"),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceReport {
+	pub index: usize,
+	pub name: String,
+	pub device_type: vk::PhysicalDeviceType,
+	pub vendor_id: u32,
+	pub device_id: u32,
+	pub uuid: [u8; vk::UUID_SIZE],
+	pub api_version: u32,
+	pub driver_version: u32,
+	// only set for devices that passed every requirement
+	pub score: Option<u32>,
+	pub rejection: Option<Rejection>,
+}
+
+// every enumerated device and what happened to it. logged on success, handed back in the
+// error when nothing qualifies
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSelectionReport {
+	pub selector: Option<GpuSelector>,
+	pub devices: Vec<DeviceReport>,
+	pub picked: Option<usize>,
+}
+
+impl DeviceSelectionReport {
+	pub fn picked(&self) -> Option<&DeviceReport> {
+		self.picked.and_then(|i| self.devices.get(i))
+	}
+}
+
+impl fmt::Display for DeviceSelectionReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.selector {
+			Some(selector) => writeln!(f, "gpu selector: {:?}", selector)?,
+			None => writeln!(f, "gpu selector: none (highest score wins)")?,
+		}
+		for device in &self.devices {
+			write!(
+				f,
+				"  [{}] {} ({:?}, {:04x}:{:04x}, vk {}): ",
+				device.index,
+				device.name,
+				device.device_type,
+				device.vendor_id,
+				device.device_id,
+				version_string(device.api_version),
+			)?;
+			match (&device.rejection, device.score) {
+				(Some(rejection), _) => writeln!(f, "rejected, {}", rejection)?,
+				(None, Some(score)) if self.picked == Some(device.index) => {
+					writeln!(f, "picked, score {}", score)?
+				}
+				(None, Some(score)) => writeln!(f, "suitable, score {}", score)?,
+				(None, None) => writeln!(f, "not checked")?,
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeviceSelectionError {
+	#[error("no vulkan physical devices found")]
+	NoDevices,
+	#[error("no suitable gpu found\n{0}")]
+	NoSuitableDevice(DeviceSelectionReport),
+	#[error("invalid gpu selector {selector:?}: {reason}")]
+	InvalidSelector { selector: String, reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_selector_kinds() {
+		assert_eq!(
+			"RTX".parse::<GpuSelector>().unwrap(),
+			GpuSelector::Name("RTX".to_owned())
+		);
+		assert_eq!(
+			"index:2".parse::<GpuSelector>().unwrap(),
+			GpuSelector::Index(2)
+		);
+		assert_eq!(
+			"id:10de:2684".parse::<GpuSelector>().unwrap(),
+			GpuSelector::VendorDevice {
+				vendor_id: 0x10de,
+				device_id: Some(0x2684),
+			}
+		);
+	}
+
+	#[test]
+	fn parses_uuid_with_dashes() {
+		let selector: GpuSelector = "uuid:00112233-4455-6677-8899-aabbccddeeff".parse().unwrap();
+		let expected = [
+			0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd,
+			0xee, 0xff,
+		];
+		assert_eq!(selector, GpuSelector::Uuid(expected));
+	}
+
+	#[test]
+	fn rejects_bad_uuids() {
+		// 16 two byte chars are 32 bytes, the length check alone would let this through
+		let multi_byte = format!("uuid:{}", "\u{e9}".repeat(16));
+		for uuid in [
+			multi_byte.as_str(),
+			"uuid:0011",
+			"uuid:0011223344556677889900aabbccddzz",
+		] {
+			assert!(matches!(
+				uuid.parse::<GpuSelector>(),
+				Err(DeviceSelectionError::InvalidSelector { .. })
+			));
+		}
+	}
+}
@@ -0,0 +1,19 @@
+use super::{DeviceSelectionError, LoaderSource, vk};
+
+// setup failures worth reporting to the user instead of panicking on. anything past setup
+// still panics through `.expect`
+#[derive(Debug, thiserror::Error)]
+pub enum VkError {
+	#[error("vulkan is not available, couldn't load the loader ({loader:?}): {source}")]
+	LoaderUnavailable {
+		loader: LoaderSource,
+		source: ash::LoadingError,
+	},
+	// usually means there's a loader but no driver (ERROR_INCOMPATIBLE_DRIVER)
+	#[error("couldn't create a vulkan instance: {0}")]
+	InstanceCreation(vk::Result),
+	#[error("invalid value {value:?} for {var}")]
+	InvalidConfig { var: &'static str, value: String },
+	#[error(transparent)]
+	DeviceSelection(#[from] DeviceSelectionError),
+}
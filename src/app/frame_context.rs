@@ -0,0 +1,32 @@
+use super::{
+	Camera, CameraUniforms, DeviceContext, FrameData, ImageHandle, RenderGraph, VkCore, vk,
+};
+
+// what App::render gets every frame. passes go into `graph`, writes to the swapchain image have
+// to put the handle they get back into `swapchain` so the runner exports the latest version
+pub struct FrameContext<'a> {
+	pub vk: &'a VkCore,
+	pub frame: &'a FrameData,
+	// index into VkSwap::frames, 0..FRAMES_IN_FLIGHT
+	pub frame_index: u32,
+	// Tick::alpha, how far between the previous and the current simulation state to draw
+	pub alpha: f32,
+	pub extent: vk::Extent2D,
+	pub swapchain_format: vk::Format,
+	pub graph: RenderGraph<'a>,
+	pub swapchain: ImageHandle,
+}
+
+impl FrameContext<'_> {
+	pub fn device_ctx(&self) -> &DeviceContext {
+		&self.vk.device_ctx
+	}
+
+	// uploads what frame.camera_set points at, bind that at set 0 to use it
+	pub fn set_camera(&self, camera: &Camera) {
+		self.frame.camera_buffer.write(
+			self.vk.device_ctx.device(),
+			CameraUniforms::new(camera).as_bytes(),
+		);
+	}
+}
@@ -1,42 +1,101 @@
-use super::{Device, vk};
+use super::{
+	Buffer, CameraUniforms, Device, DeviceContext, FrameQueries, FrameTimestamps, TransientPool, vk,
+};
+use std::cell::{Cell, RefCell};
 
 pub struct FrameData {
 	pub cmd_buff: vk::CommandBuffer, // 1 cmd buff per frame, allocated from cmd_pool in vkSwap
 	pub img_available: vk::Semaphore,
-	pub render_finished: vk::Semaphore,
-	pub draw_fence: vk::Fence,
+	// graphics timeline value signalled by this frame's last submit
+	pub timeline_value: Cell<u64>,
+	// render graph transients, per frame since the previous frame may still be reading them
+	pub transient_pool: RefCell<TransientPool>,
+	// read back once timeline_value is reached again
+	pub timestamps: FrameTimestamps,
+	pub queries: FrameQueries,
+	// CameraUniforms, written while recording so it can't race the previous use of this frame
+	pub camera_buffer: Buffer,
+	// set 0, camera_buffer at binding 0
+	pub camera_set: vk::DescriptorSet,
 }
 
 impl FrameData {
-	pub fn new(device: &Device, command_pool: vk::CommandPool) -> FrameData {
+	pub fn new(
+		device_ctx: &DeviceContext,
+		command_pool: vk::CommandPool,
+		timestamp_valid_bits: u32,
+		descriptor_pool: vk::DescriptorPool,
+		camera_set_layout: vk::DescriptorSetLayout,
+	) -> FrameData {
+		let device = device_ctx.device();
 		let cmd_buff = FrameData::create_command_buff(device, command_pool);
 		let img_available = unsafe {
 			device
 				.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
 				.expect("Should have been able to create present_finished semaphore")
 		};
-		let render_finished = unsafe {
-			device
-				.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
-				.expect("Should have been able to create render_ready semaphore")
-		};
-		let draw_fence = unsafe {
-			device
-				.create_fence(
-					&vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED),
-					None,
-				)
-				.expect("Should have been able to create draw_fence")
-		};
+
+		let camera_buffer = Buffer::new(
+			device_ctx,
+			size_of::<CameraUniforms>() as vk::DeviceSize,
+			vk::BufferUsageFlags::UNIFORM_BUFFER,
+			vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+		);
+		let camera_set = FrameData::create_camera_set(
+			device,
+			descriptor_pool,
+			camera_set_layout,
+			&camera_buffer,
+		);
 
 		FrameData {
 			cmd_buff,
 			img_available,
-			render_finished,
-			draw_fence,
+			timeline_value: Cell::new(0),
+			transient_pool: RefCell::new(TransientPool::new()),
+			timestamps: FrameTimestamps::new(device_ctx, timestamp_valid_bits),
+			queries: FrameQueries::new(device_ctx),
+			camera_buffer,
+			camera_set,
 		}
 	}
 
+	// also for rendering outside VkSwap, e.g. headless
+	pub fn create_camera_set(
+		device: &Device,
+		descriptor_pool: vk::DescriptorPool,
+		camera_set_layout: vk::DescriptorSetLayout,
+		camera_buffer: &Buffer,
+	) -> vk::DescriptorSet {
+		let alloc_info = vk::DescriptorSetAllocateInfo {
+			descriptor_pool,
+			descriptor_set_count: 1,
+			p_set_layouts: &camera_set_layout,
+			..Default::default()
+		};
+		let camera_set = unsafe {
+			device
+				.allocate_descriptor_sets(&alloc_info)
+				.expect("Should have been able to allocate camera descriptor set")[0]
+		};
+		let buffer_info = vk::DescriptorBufferInfo {
+			buffer: camera_buffer.buffer,
+			offset: 0,
+			range: camera_buffer.size,
+		};
+		let write = vk::WriteDescriptorSet {
+			dst_set: camera_set,
+			dst_binding: 0,
+			descriptor_count: 1,
+			descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+			p_buffer_info: &buffer_info,
+			..Default::default()
+		};
+		unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+		camera_set
+	}
+
 	fn create_command_buff(device: &Device, command_pool: vk::CommandPool) -> vk::CommandBuffer {
 		let alloc_info = vk::CommandBufferAllocateInfo {
 			command_pool: command_pool,
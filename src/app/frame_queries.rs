@@ -0,0 +1,335 @@
+use super::{Device, DeviceContext, vk};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+pub const MAX_STATISTICS_SCOPES: u32 = 32;
+pub const MAX_OCCLUSION_QUERIES: u32 = 256;
+// VkSwap logs the report (at debug) this often
+pub const QUERY_REPORT_LOG_INTERVAL: u64 = 600;
+
+// what gets counted, results come back in bit order so this has to match PipelineStatistics
+const STATISTICS_FLAGS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+	vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+		| vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+		| vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+		| vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+		| vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+		| vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw(),
+);
+const STATISTICS_COUNT: usize = 6;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct PipelineStatistics {
+	pub input_assembly_vertices: u64,
+	pub input_assembly_primitives: u64,
+	pub vertex_shader_invocations: u64,
+	// primitives that reached clipping, and what came out of it
+	pub clipping_invocations: u64,
+	pub clipping_primitives: u64,
+	pub fragment_shader_invocations: u64,
+}
+
+impl PipelineStatistics {
+	fn from_raw(raw: [u64; STATISTICS_COUNT]) -> PipelineStatistics {
+		PipelineStatistics {
+			input_assembly_vertices: raw[0],
+			input_assembly_primitives: raw[1],
+			vertex_shader_invocations: raw[2],
+			clipping_invocations: raw[3],
+			clipping_primitives: raw[4],
+			fragment_shader_invocations: raw[5],
+		}
+	}
+}
+
+// everything a frame's queries produced
+#[derive(Clone, Debug, Default)]
+pub struct FrameQueryResults {
+	pub pipeline_statistics: Vec<(String, PipelineStatistics)>,
+	// occlusion query id -> samples that passed (0/1 when the queries weren't precise)
+	pub occlusion: Vec<(u64, u64)>,
+}
+
+// pipeline statistics + occlusion query pools of one FrameData. like FrameTimestamps they're
+// only read once the frame's timeline value is reached, so nothing waits on them
+pub struct FrameQueries {
+	pub statistics_pool: vk::QueryPool,
+	pub occlusion_pool: vk::QueryPool,
+	precise_occlusion: bool,
+	statistics_scopes: RefCell<Vec<String>>,
+	occlusion_ids: RefCell<Vec<u64>>,
+	// only one query of each type can be active at a time
+	statistics_active: Cell<bool>,
+	occlusion_active: Cell<bool>,
+}
+
+impl FrameQueries {
+	// no statistics pool (statistics scopes are no-ops) without the pipeline_statistics_query
+	// feature, occlusion queries are always there
+	pub fn new(device_ctx: &DeviceContext) -> FrameQueries {
+		let features = &device_ctx.enabled_features.features.core;
+		let create_pool = |pool_info: &vk::QueryPoolCreateInfo, name: &str| {
+			let pool = unsafe {
+				device_ctx
+					.device()
+					.create_query_pool(pool_info, None)
+					.expect("Should have been able to create query pool")
+			};
+			device_ctx.set_name(pool, name);
+			pool
+		};
+		let statistics_pool = if features.pipeline_statistics_query == vk::TRUE {
+			let pool_info = vk::QueryPoolCreateInfo {
+				query_type: vk::QueryType::PIPELINE_STATISTICS,
+				query_count: MAX_STATISTICS_SCOPES,
+				pipeline_statistics: STATISTICS_FLAGS,
+				..Default::default()
+			};
+			create_pool(&pool_info, "pipeline statistics queries")
+		} else {
+			vk::QueryPool::null()
+		};
+		let pool_info = vk::QueryPoolCreateInfo {
+			query_type: vk::QueryType::OCCLUSION,
+			query_count: MAX_OCCLUSION_QUERIES,
+			..Default::default()
+		};
+		let occlusion_pool = create_pool(&pool_info, "occlusion queries");
+		FrameQueries {
+			statistics_pool,
+			occlusion_pool,
+			precise_occlusion: features.occlusion_query_precise == vk::TRUE,
+			statistics_scopes: RefCell::new(Vec::new()),
+			occlusion_ids: RefCell::new(Vec::new()),
+			statistics_active: Cell::new(false),
+			occlusion_active: Cell::new(false),
+		}
+	}
+
+	pub fn has_pipeline_statistics(&self) -> bool {
+		self.statistics_pool != vk::QueryPool::null()
+	}
+
+	// has to be recorded before any query of the frame, outside of rendering
+	pub fn begin_frame(&self, device: &Device, cmd_buff: vk::CommandBuffer) {
+		self.statistics_scopes.borrow_mut().clear();
+		self.occlusion_ids.borrow_mut().clear();
+		unsafe {
+			if self.has_pipeline_statistics() {
+				device.cmd_reset_query_pool(
+					cmd_buff,
+					self.statistics_pool,
+					0,
+					MAX_STATISTICS_SCOPES,
+				);
+			}
+			device.cmd_reset_query_pool(cmd_buff, self.occlusion_pool, 0, MAX_OCCLUSION_QUERIES);
+		}
+	}
+
+	// counts everything drawn until the guard is dropped. statistics scopes don't nest, an inner
+	// one is a no-op
+	pub fn pipeline_statistics<'a>(
+		&'a self,
+		device: &'a Device,
+		cmd_buff: vk::CommandBuffer,
+		name: &str,
+	) -> QueryScope<'a> {
+		let mut scopes = self.statistics_scopes.borrow_mut();
+		let query = scopes.len() as u32;
+		if !self.has_pipeline_statistics()
+			|| self.statistics_active.get()
+			|| query >= MAX_STATISTICS_SCOPES
+		{
+			return QueryScope::inactive(device, cmd_buff);
+		}
+		scopes.push(name.to_owned());
+		unsafe {
+			device.cmd_begin_query(
+				cmd_buff,
+				self.statistics_pool,
+				query,
+				vk::QueryControlFlags::empty(),
+			)
+		};
+		self.statistics_active.set(true);
+		QueryScope {
+			device,
+			cmd_buff,
+			active: Some((self.statistics_pool, query, &self.statistics_active)),
+		}
+	}
+
+	// samples passing the depth/stencil tests for whatever is drawn until the guard is dropped,
+	// `id` is whatever the caller uses to look the result up again (an object index etc.)
+	pub fn occlusion<'a>(
+		&'a self,
+		device: &'a Device,
+		cmd_buff: vk::CommandBuffer,
+		id: u64,
+	) -> QueryScope<'a> {
+		let mut ids = self.occlusion_ids.borrow_mut();
+		let query = ids.len() as u32;
+		if self.occlusion_active.get() || query >= MAX_OCCLUSION_QUERIES {
+			return QueryScope::inactive(device, cmd_buff);
+		}
+		ids.push(id);
+		let flags = if self.precise_occlusion {
+			vk::QueryControlFlags::PRECISE
+		} else {
+			vk::QueryControlFlags::empty()
+		};
+		unsafe { device.cmd_begin_query(cmd_buff, self.occlusion_pool, query, flags) };
+		self.occlusion_active.set(true);
+		QueryScope {
+			device,
+			cmd_buff,
+			active: Some((self.occlusion_pool, query, &self.occlusion_active)),
+		}
+	}
+
+	// None when nothing was recorded or the results aren't available yet
+	pub fn read_results(&self, device: &Device) -> Option<FrameQueryResults> {
+		let scopes = self.statistics_scopes.borrow();
+		let ids = self.occlusion_ids.borrow();
+		if scopes.is_empty() && ids.is_empty() {
+			return None;
+		}
+		let mut results = FrameQueryResults::default();
+		if !scopes.is_empty() {
+			let mut raw = vec![[0u64; STATISTICS_COUNT]; scopes.len()];
+			unsafe {
+				device.get_query_pool_results(
+					self.statistics_pool,
+					0,
+					&mut raw,
+					vk::QueryResultFlags::TYPE_64,
+				)
+			}
+			.ok()?;
+			results.pipeline_statistics = scopes
+				.iter()
+				.cloned()
+				.zip(raw.into_iter().map(PipelineStatistics::from_raw))
+				.collect();
+		}
+		if !ids.is_empty() {
+			let mut samples = vec![0u64; ids.len()];
+			unsafe {
+				device.get_query_pool_results(
+					self.occlusion_pool,
+					0,
+					&mut samples,
+					vk::QueryResultFlags::TYPE_64,
+				)
+			}
+			.ok()?;
+			results.occlusion = ids.iter().copied().zip(samples).collect();
+		}
+		Some(results)
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		unsafe {
+			if self.has_pipeline_statistics() {
+				device.destroy_query_pool(self.statistics_pool, None);
+			}
+			device.destroy_query_pool(self.occlusion_pool, None);
+		}
+	}
+}
+
+// from FrameQueries::pipeline_statistics / occlusion, ends the query on drop. has to be dropped
+// inside the same rendering scope it was started in
+pub struct QueryScope<'a> {
+	device: &'a Device,
+	cmd_buff: vk::CommandBuffer,
+	active: Option<(vk::QueryPool, u32, &'a Cell<bool>)>,
+}
+
+impl<'a> QueryScope<'a> {
+	fn inactive(device: &'a Device, cmd_buff: vk::CommandBuffer) -> QueryScope<'a> {
+		QueryScope {
+			device,
+			cmd_buff,
+			active: None,
+		}
+	}
+}
+
+impl Drop for QueryScope<'_> {
+	fn drop(&mut self) {
+		if let Some((pool, query, active)) = self.active {
+			unsafe { self.device.cmd_end_query(self.cmd_buff, pool, query) };
+			active.set(false);
+		}
+	}
+}
+
+// latest resolved results, what draw code checks for occlusion based visibility
+#[derive(Default)]
+pub struct QueryReport {
+	frames_resolved: u64,
+	pipeline_statistics: Vec<(String, PipelineStatistics)>,
+	occlusion: HashMap<u64, u64>,
+}
+
+impl QueryReport {
+	pub fn new() -> QueryReport {
+		QueryReport::default()
+	}
+
+	pub fn frames_resolved(&self) -> u64 {
+		self.frames_resolved
+	}
+
+	pub fn record(&mut self, results: FrameQueryResults) {
+		self.pipeline_statistics = results.pipeline_statistics;
+		self.occlusion = results.occlusion.into_iter().collect();
+		self.frames_resolved += 1;
+	}
+
+	pub fn pipeline_statistics(&self, name: &str) -> Option<PipelineStatistics> {
+		self.pipeline_statistics
+			.iter()
+			.find(|(scope, _)| scope == name)
+			.map(|&(_, stats)| stats)
+	}
+
+	pub fn samples_passed(&self, id: u64) -> Option<u64> {
+		self.occlusion.get(&id).copied()
+	}
+
+	// None when `id` wasn't queried in the last resolved frame, callers should treat that as
+	// visible so new objects get drawn (and queried) at least once
+	pub fn is_visible(&self, id: u64) -> Option<bool> {
+		self.samples_passed(id).map(|samples| samples > 0)
+	}
+
+	pub fn log_table(&self, level: log::Level) {
+		let mut out = format!(
+			"pipeline statistics:\n  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+			"scope", "ia verts", "ia prims", "vs invocs", "clip in", "clip out", "fs invocs"
+		);
+		for (name, stats) in &self.pipeline_statistics {
+			let _ = write!(
+				out,
+				"\n  {:<24} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+				name,
+				stats.input_assembly_vertices,
+				stats.input_assembly_primitives,
+				stats.vertex_shader_invocations,
+				stats.clipping_invocations,
+				stats.clipping_primitives,
+				stats.fragment_shader_invocations
+			);
+		}
+		let mut occlusion: Vec<(&u64, &u64)> = self.occlusion.iter().collect();
+		occlusion.sort();
+		for (id, samples) in occlusion {
+			let _ = write!(out, "\n  occlusion {}: {} samples", id, samples);
+		}
+		log::log!(level, "{}", out);
+	}
+}
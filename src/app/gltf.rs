@@ -0,0 +1,940 @@
+use super::{MeshData, SamplerDesc, Vertex, vk};
+use ::gltf::Semantic;
+use ::gltf::accessor::{Accessor, DataType, Dimensions};
+use ::gltf::buffer;
+use ::gltf::texture::{MagFilter, MinFilter, WrappingMode};
+use cgmath::{Matrix4, SquareMatrix, Vector4};
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GltfError {
+	#[error("failed to read gltf file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("failed to load gltf: {0}")]
+	Gltf(#[from] ::gltf::Error),
+	#[error("invalid gltf: {0}")]
+	Invalid(String),
+	#[error("unsupported gltf: {0}")]
+	Unsupported(String),
+}
+
+fn invalid(message: impl Into<String>) -> GltfError {
+	GltfError::Invalid(message.into())
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+	Opaque,
+	// discard below the cutoff
+	Mask(f32),
+	Blend,
+}
+
+// a texture slot of a material, `tex_coord` is which TEXCOORD_n set it samples
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextureRef {
+	pub texture: usize,
+	pub tex_coord: u32,
+}
+
+// pbr metallic-roughness, defaults are the spec's
+#[derive(Clone, PartialEq, Debug)]
+pub struct Material {
+	pub name: Option<String>,
+	pub base_color_factor: [f32; 4],
+	pub base_color_texture: Option<TextureRef>,
+	pub metallic_factor: f32,
+	pub roughness_factor: f32,
+	// roughness in g, metallic in b
+	pub metallic_roughness_texture: Option<TextureRef>,
+	pub normal_texture: Option<TextureRef>,
+	pub normal_scale: f32,
+	pub occlusion_texture: Option<TextureRef>,
+	pub occlusion_strength: f32,
+	pub emissive_texture: Option<TextureRef>,
+	pub emissive_factor: [f32; 3],
+	pub alpha_mode: AlphaMode,
+	pub double_sided: bool,
+}
+
+impl Default for Material {
+	fn default() -> Material {
+		Material {
+			name: None,
+			base_color_factor: [1.; 4],
+			base_color_texture: None,
+			metallic_factor: 1.,
+			roughness_factor: 1.,
+			metallic_roughness_texture: None,
+			normal_texture: None,
+			normal_scale: 1.,
+			occlusion_texture: None,
+			occlusion_strength: 1.,
+			emissive_texture: None,
+			emissive_factor: [0.; 3],
+			alpha_mode: AlphaMode::Opaque,
+			double_sided: false,
+		}
+	}
+}
+
+// an image plus how to sample it. whether it's srgb depends on the material slot using it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GltfTexture {
+	pub image: usize,
+	pub sampler: SamplerDesc,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Primitive {
+	pub data: MeshData,
+	// None means the default material
+	pub material: Option<usize>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GltfMesh {
+	pub name: Option<String>,
+	pub primitives: Vec<Primitive>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct GltfNode {
+	pub name: Option<String>,
+	// relative to the parent
+	pub transform: Matrix4<f32>,
+	pub children: Vec<usize>,
+	pub mesh: Option<usize>,
+}
+
+// everything a .gltf/.glb holds that we render, decoded on the cpu. Scene uploads it
+pub struct Gltf {
+	pub nodes: Vec<GltfNode>,
+	// root nodes of the default scene (or the first one)
+	pub roots: Vec<usize>,
+	pub meshes: Vec<GltfMesh>,
+	pub materials: Vec<Material>,
+	pub textures: Vec<GltfTexture>,
+	pub images: Vec<image::RgbaImage>,
+}
+
+impl Gltf {
+	// .glb or .gltf with its .bin and images next to it (or embedded as data uris)
+	pub fn load(path: impl AsRef<Path>) -> Result<Gltf, GltfError> {
+		let path = path.as_ref();
+		let bytes = std::fs::read(path)?;
+		Gltf::from_slice(&bytes, path.parent().unwrap_or(Path::new(".")))
+	}
+
+	// .glb or .gltf bytes, external buffers and images are looked up in `base_dir`
+	pub fn from_slice(bytes: &[u8], base_dir: &Path) -> Result<Gltf, GltfError> {
+		let ::gltf::Gltf { document, blob } = ::gltf::Gltf::from_slice_without_validation(bytes)?;
+		let json = document.into_json();
+		// the crate's validation indexes POSITION accessors without checking them
+		let accessor_count = json.accessors.len();
+		for mesh in &json.meshes {
+			for primitive in &mesh.primitives {
+				for (semantic, accessor) in &primitive.attributes {
+					if accessor.value() >= accessor_count {
+						return Err(invalid(format!(
+							"{} accessor {} out of range",
+							semantic.to_string(),
+							accessor.value()
+						)));
+					}
+				}
+			}
+		}
+		let document = ::gltf::Document::from_json(json)?;
+		let version = &document.as_json().asset.version;
+		if !version.starts_with("2.") {
+			return Err(GltfError::Unsupported(format!("version {}", version)));
+		}
+		if let Some(required) = document.extensions_required().next() {
+			return Err(GltfError::Unsupported(format!(
+				"required extension {}",
+				required
+			)));
+		}
+
+		for buffer in document.buffers() {
+			if let buffer::Source::Uri(uri) = buffer.source() {
+				check_uri(uri)?;
+			}
+		}
+		let buffers = ::gltf::import_buffers(&document, Some(base_dir), blob)?;
+		// the crate slices views without checking them
+		for view in document.views() {
+			let fits = view
+				.offset()
+				.checked_add(view.length())
+				.is_some_and(|end| end <= buffers[view.buffer().index()].len());
+			if !fits {
+				return Err(invalid(format!(
+					"bufferView {} runs past its buffer",
+					view.index()
+				)));
+			}
+		}
+
+		let images = document
+			.images()
+			.map(|image| {
+				if let ::gltf::image::Source::Uri { uri, .. } = image.source() {
+					check_uri(uri)?;
+				}
+				rgba(::gltf::image::Data::from_source(
+					image.source(),
+					Some(base_dir),
+					&buffers,
+				)?)
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		let textures = document
+			.textures()
+			.map(|texture| GltfTexture {
+				image: texture.source().index(),
+				sampler: match texture.sampler().index() {
+					Some(_) => sampler(&texture.sampler()),
+					// spec says repeat + implementation defined filtering
+					None => SamplerDesc::default(),
+				},
+			})
+			.collect();
+		let materials = document.materials().map(|m| material(&m)).collect();
+		let meshes = document
+			.meshes()
+			.map(|m| mesh(&m, &buffers))
+			.collect::<Result<Vec<_>, _>>()?;
+		let nodes: Vec<GltfNode> = document
+			.nodes()
+			.map(|node| GltfNode {
+				name: node.name().map(str::to_owned),
+				// column major, same as cgmath
+				transform: Matrix4::from(node.transform().matrix()),
+				children: node.children().map(|child| child.index()).collect(),
+				mesh: node.mesh().map(|mesh| mesh.index()),
+			})
+			.collect();
+		let roots = roots(&document, &nodes)?;
+
+		Ok(Gltf {
+			nodes,
+			roots,
+			meshes,
+			materials,
+			textures,
+			images,
+		})
+	}
+
+	// (world transform, mesh) for every node with a mesh, depth first from the roots
+	pub fn mesh_instances(&self) -> Vec<(Matrix4<f32>, usize)> {
+		let mut instances = Vec::new();
+		let mut stack: Vec<(usize, Matrix4<f32>)> = self
+			.roots
+			.iter()
+			.rev()
+			.map(|&root| (root, Matrix4::identity()))
+			.collect();
+		while let Some((node, parent)) = stack.pop() {
+			let node = &self.nodes[node];
+			let world = parent * node.transform;
+			if let Some(mesh) = node.mesh {
+				instances.push((world, mesh));
+			}
+			stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+		}
+		instances
+	}
+
+	// world space (min, max) over every instanced mesh, None if nothing gets drawn
+	pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+		let mut bounds: Option<([f32; 3], [f32; 3])> = None;
+		for (world, mesh) in self.mesh_instances() {
+			for primitive in &self.meshes[mesh].primitives {
+				for vertex in &primitive.data.vertices {
+					let [x, y, z] = vertex.position;
+					let p = world * Vector4::new(x, y, z, 1.);
+					let p = [p.x, p.y, p.z];
+					bounds = Some(match bounds {
+						None => (p, p),
+						Some((min, max)) => (
+							[0, 1, 2].map(|i| min[i].min(p[i])),
+							[0, 1, 2].map(|i| max[i].max(p[i])),
+						),
+					});
+				}
+			}
+		}
+		bounds
+	}
+}
+
+// the crate unwraps the percent decoding of relative uris
+fn check_uri(uri: &str) -> Result<(), GltfError> {
+	if !uri.contains(':') && urlencoding::decode(uri).is_err() {
+		return Err(invalid(format!("uri {} is not utf8 once decoded", uri)));
+	}
+	Ok(())
+}
+
+// decoded pixels come back in whatever format the file had
+fn rgba(data: ::gltf::image::Data) -> Result<image::RgbaImage, GltfError> {
+	use ::gltf::image::Format;
+	use image::{DynamicImage, ImageBuffer};
+	let (width, height) = (data.width, data.height);
+	if width == 0 || height == 0 {
+		return Err(invalid("image has no pixels"));
+	}
+	// 16 and 32 bit formats are native endian bytes
+	let u16s = |pixels: &[u8]| -> Vec<u16> {
+		pixels
+			.chunks_exact(2)
+			.map(|b| u16::from_ne_bytes([b[0], b[1]]))
+			.collect()
+	};
+	let f32s = |pixels: &[u8]| -> Vec<f32> {
+		pixels
+			.chunks_exact(4)
+			.map(|b| f32::from_ne_bytes(b.try_into().unwrap()))
+			.collect()
+	};
+	let pixels = data.pixels;
+	let image = match data.format {
+		Format::R8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+		Format::R8G8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+		Format::R8G8B8 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+		Format::R8G8B8A8 => {
+			ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+		}
+		Format::R16 => {
+			ImageBuffer::from_raw(width, height, u16s(&pixels)).map(DynamicImage::ImageLuma16)
+		}
+		Format::R16G16 => {
+			ImageBuffer::from_raw(width, height, u16s(&pixels)).map(DynamicImage::ImageLumaA16)
+		}
+		Format::R16G16B16 => {
+			ImageBuffer::from_raw(width, height, u16s(&pixels)).map(DynamicImage::ImageRgb16)
+		}
+		Format::R16G16B16A16 => {
+			ImageBuffer::from_raw(width, height, u16s(&pixels)).map(DynamicImage::ImageRgba16)
+		}
+		Format::R32G32B32FLOAT => {
+			ImageBuffer::from_raw(width, height, f32s(&pixels)).map(DynamicImage::ImageRgb32F)
+		}
+		Format::R32G32B32A32FLOAT => {
+			ImageBuffer::from_raw(width, height, f32s(&pixels)).map(DynamicImage::ImageRgba32F)
+		}
+	};
+	image
+		.map(|image| image.to_rgba8())
+		.ok_or_else(|| invalid("image pixels don't match its size"))
+}
+
+fn sampler(sampler: &::gltf::texture::Sampler) -> SamplerDesc {
+	let filter = |nearest| match nearest {
+		true => vk::Filter::NEAREST,
+		false => vk::Filter::LINEAR,
+	};
+	let min_filter = sampler.min_filter();
+	let mipmap_mode = match min_filter {
+		Some(MinFilter::NearestMipmapNearest | MinFilter::LinearMipmapNearest) => {
+			vk::SamplerMipmapMode::NEAREST
+		}
+		_ => vk::SamplerMipmapMode::LINEAR,
+	};
+	let wrap = |mode| match mode {
+		WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+		WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+		WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+	};
+	SamplerDesc {
+		mag_filter: filter(sampler.mag_filter() == Some(MagFilter::Nearest)),
+		min_filter: filter(matches!(
+			min_filter,
+			Some(
+				MinFilter::Nearest
+					| MinFilter::NearestMipmapNearest
+					| MinFilter::NearestMipmapLinear
+			)
+		)),
+		mipmap_mode,
+		address_mode_u: wrap(sampler.wrap_s()),
+		address_mode_v: wrap(sampler.wrap_t()),
+		..SamplerDesc::default()
+	}
+}
+
+fn texture_ref(info: Option<::gltf::texture::Info>) -> Option<TextureRef> {
+	info.map(|info| TextureRef {
+		texture: info.texture().index(),
+		tex_coord: info.tex_coord(),
+	})
+}
+
+fn material(material: &::gltf::Material) -> Material {
+	let pbr = material.pbr_metallic_roughness();
+	let normal = material.normal_texture();
+	let occlusion = material.occlusion_texture();
+	Material {
+		name: material.name().map(str::to_owned),
+		base_color_factor: pbr.base_color_factor(),
+		base_color_texture: texture_ref(pbr.base_color_texture()),
+		metallic_factor: pbr.metallic_factor(),
+		roughness_factor: pbr.roughness_factor(),
+		metallic_roughness_texture: texture_ref(pbr.metallic_roughness_texture()),
+		normal_texture: normal.as_ref().map(|info| TextureRef {
+			texture: info.texture().index(),
+			tex_coord: info.tex_coord(),
+		}),
+		normal_scale: normal.as_ref().map_or(1., |info| info.scale()),
+		occlusion_texture: occlusion.as_ref().map(|info| TextureRef {
+			texture: info.texture().index(),
+			tex_coord: info.tex_coord(),
+		}),
+		occlusion_strength: occlusion.as_ref().map_or(1., |info| info.strength()),
+		emissive_texture: texture_ref(material.emissive_texture()),
+		emissive_factor: material.emissive_factor(),
+		alpha_mode: match material.alpha_mode() {
+			::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+			::gltf::material::AlphaMode::Mask => {
+				AlphaMode::Mask(material.alpha_cutoff().unwrap_or(0.5))
+			}
+			::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+		},
+		double_sided: material.double_sided(),
+	}
+}
+
+fn mesh(mesh: &::gltf::Mesh, buffers: &[buffer::Data]) -> Result<GltfMesh, GltfError> {
+	let mut primitives = Vec::new();
+	for primitive in mesh.primitives() {
+		if primitive.mode() != ::gltf::mesh::Mode::Triangles {
+			log::warn!(
+				"Skipping primitive of mesh {:?} with mode {:?}, only triangles are drawn",
+				mesh.name(),
+				primitive.mode()
+			);
+			continue;
+		}
+		primitives.push(Primitive {
+			data: mesh_data(&primitive, buffers)?,
+			material: primitive.material().index(),
+		});
+	}
+	Ok(GltfMesh {
+		name: mesh.name().map(str::to_owned),
+		primitives,
+	})
+}
+
+// the crate's reader trusts accessors: it underflows on empty ones, overflows on huge
+// offsets and hits unreachable!() on component types an attribute can't have
+fn check_accessor(
+	accessor: &Accessor,
+	dimensions: Dimensions,
+	data_types: &[DataType],
+) -> Result<(), GltfError> {
+	let index = accessor.index();
+	if accessor.sparse().is_some() {
+		return Err(GltfError::Unsupported("sparse accessors".to_owned()));
+	}
+	if accessor.dimensions() != dimensions || !data_types.contains(&accessor.data_type()) {
+		return Err(invalid(format!(
+			"accessor {} is {:?} {:?}, expected {:?} {:?}",
+			index,
+			accessor.dimensions(),
+			accessor.data_type(),
+			dimensions,
+			data_types
+		)));
+	}
+	let view = accessor
+		.view()
+		.ok_or_else(|| invalid(format!("accessor {} has no bufferView", index)))?;
+	let count = accessor.count();
+	if count == 0 {
+		return Err(invalid(format!("accessor {} is empty", index)));
+	}
+	let size = accessor.size();
+	let stride = view.stride().unwrap_or(size);
+	// elements can't overlap
+	if stride < size {
+		return Err(invalid(format!(
+			"accessor {} has a byteStride below its element size",
+			index
+		)));
+	}
+	let end = stride
+		.checked_mul(count - 1)
+		.and_then(|last| last.checked_add(accessor.offset()))
+		.and_then(|last| last.checked_add(size));
+	if end.is_none_or(|end| end > view.length()) {
+		return Err(invalid(format!(
+			"accessor {} runs past its bufferView",
+			index
+		)));
+	}
+	Ok(())
+}
+
+fn mesh_data(
+	primitive: &::gltf::Primitive,
+	buffers: &[buffer::Data],
+) -> Result<MeshData, GltfError> {
+	let positions = primitive
+		.get(&Semantic::Positions)
+		.ok_or_else(|| invalid("primitive without POSITION"))?;
+	check_accessor(&positions, Dimensions::Vec3, &[DataType::F32])?;
+	let attributes = [
+		(
+			"NORMAL",
+			Semantic::Normals,
+			Dimensions::Vec3,
+			&[DataType::F32][..],
+		),
+		(
+			"TANGENT",
+			Semantic::Tangents,
+			Dimensions::Vec4,
+			&[DataType::F32],
+		),
+		(
+			"TEXCOORD_0",
+			Semantic::TexCoords(0),
+			Dimensions::Vec2,
+			&[DataType::U8, DataType::U16, DataType::F32],
+		),
+	];
+	for (name, semantic, dimensions, data_types) in attributes {
+		if let Some(accessor) = primitive.get(&semantic) {
+			check_accessor(&accessor, dimensions, data_types)?;
+			if accessor.count() != positions.count() {
+				return Err(invalid(format!("{} count doesn't match POSITION", name)));
+			}
+		}
+	}
+	if let Some(accessor) = primitive.indices() {
+		check_accessor(
+			&accessor,
+			Dimensions::Scalar,
+			&[DataType::U8, DataType::U16, DataType::U32],
+		)?;
+	}
+
+	let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+	let unreadable = || invalid("primitive attribute could not be read");
+	let mut vertices: Vec<Vertex> = reader
+		.read_positions()
+		.ok_or_else(unreadable)?
+		.map(|position| Vertex {
+			position,
+			..Default::default()
+		})
+		.collect();
+	let indices: Vec<u32> = match reader.read_indices() {
+		Some(indices) => indices.into_u32().collect(),
+		None if primitive.indices().is_some() => return Err(unreadable()),
+		None => (0..vertices.len() as u32).collect(),
+	};
+	if let Some(bad) = indices.iter().find(|&&i| i as usize >= vertices.len()) {
+		return Err(invalid(format!("index {} out of range", bad)));
+	}
+	if let Some(uvs) = reader.read_tex_coords(0) {
+		vertices
+			.iter_mut()
+			.zip(uvs.into_f32())
+			.for_each(|(v, uv)| v.uv = uv);
+	}
+	let mut data = MeshData { vertices, indices };
+	match reader.read_normals() {
+		Some(normals) => data
+			.vertices
+			.iter_mut()
+			.zip(normals)
+			.for_each(|(v, normal)| v.normal = normal),
+		None => data.generate_normals(),
+	}
+	match reader.read_tangents() {
+		Some(tangents) => data
+			.vertices
+			.iter_mut()
+			.zip(tangents)
+			.for_each(|(v, tangent)| v.tangent = tangent),
+		None => data.generate_tangents(),
+	}
+	Ok(data)
+}
+
+// the default scene's roots. without any scenes, every node nobody claims as a child
+fn roots(document: &::gltf::Document, nodes: &[GltfNode]) -> Result<Vec<usize>, GltfError> {
+	let mut is_child = vec![false; nodes.len()];
+	for node in nodes {
+		for &child in &node.children {
+			// the node hierarchy has to be a forest, which also rules out cycles
+			// reachable from a root
+			if is_child[child] {
+				return Err(invalid(format!("node {} has more than one parent", child)));
+			}
+			is_child[child] = true;
+		}
+	}
+	let Some(scene) = document
+		.default_scene()
+		.or_else(|| document.scenes().next())
+	else {
+		return Ok((0..nodes.len()).filter(|&i| !is_child[i]).collect());
+	};
+	let roots: Vec<usize> = scene.nodes().map(|node| node.index()).collect();
+	if let Some(bad) = roots.iter().find(|&&root| is_child[root]) {
+		return Err(invalid(format!("scene root {} is not a root node", bad)));
+	}
+	Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const GLB_MAGIC: &[u8; 4] = b"glTF";
+	const GLB_CHUNK_JSON: u32 = 0x4e4f534a;
+	const GLB_CHUNK_BIN: u32 = 0x004e4942;
+
+	// one triangle: 3 f32 vec3 positions, then 3 u16 indices
+	fn triangle_bin() -> Vec<u8> {
+		let positions: [f32; 9] = [0., 0., 0., 1., 0., 0., 0., 1., 0.];
+		let mut bin: Vec<u8> = positions.iter().flat_map(|p| p.to_le_bytes()).collect();
+		bin.extend([0u16, 1, 2].iter().flat_map(|i| i.to_le_bytes()));
+		bin
+	}
+
+	// the triangle's document with the given accessors, nodes and scenes arrays
+	fn triangle_json(accessors: &str, nodes: &str, scenes: &str) -> String {
+		format!(
+			r#"{{
+				"asset": {{"version": "2.0"}},
+				"buffers": [{{"byteLength": 42}}],
+				"bufferViews": [
+					{{"buffer": 0, "byteLength": 36}},
+					{{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+				],
+				"accessors": {},
+				"meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1}}]}}],
+				"nodes": {},
+				"scenes": {}
+			}}"#,
+			accessors, nodes, scenes
+		)
+	}
+
+	// POSITION accessors need their bounds
+	const BOUNDS: &str = r#""min": [0, 0, 0], "max": [1, 1, 0]"#;
+	const INDEX_ACCESSOR: &str =
+		r#"{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}"#;
+
+	fn accessors(position_fields: &str) -> String {
+		format!(
+			r#"[{{"componentType": 5126, "type": "VEC3", {}, {}}}, {}]"#,
+			BOUNDS, position_fields, INDEX_ACCESSOR
+		)
+	}
+
+	fn triangle(nodes: &str, scenes: &str) -> String {
+		triangle_json(&accessors(r#""bufferView": 0, "count": 3"#), nodes, scenes)
+	}
+
+	fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+		let mut json = json.as_bytes().to_vec();
+		json.resize(json.len().next_multiple_of(4), b' ');
+		let mut bin = bin.to_vec();
+		bin.resize(bin.len().next_multiple_of(4), 0);
+		let total = 12 + 8 + json.len() + 8 + bin.len();
+		let mut out = GLB_MAGIC.to_vec();
+		out.extend(2u32.to_le_bytes());
+		out.extend((total as u32).to_le_bytes());
+		out.extend((json.len() as u32).to_le_bytes());
+		out.extend(GLB_CHUNK_JSON.to_le_bytes());
+		out.extend(json);
+		out.extend((bin.len() as u32).to_le_bytes());
+		out.extend(GLB_CHUNK_BIN.to_le_bytes());
+		out.extend(bin);
+		out
+	}
+
+	fn load(json: &str) -> Result<Gltf, GltfError> {
+		Gltf::from_slice(&glb(json, &triangle_bin()), Path::new("."))
+	}
+
+	fn invalid_message(result: Result<Gltf, GltfError>) -> String {
+		match result {
+			Err(GltfError::Invalid(message)) => message,
+			Err(e) => panic!("expected an invalid gltf, got {}", e),
+			Ok(_) => panic!("expected an invalid gltf, it loaded"),
+		}
+	}
+
+	fn is_crate_error(result: Result<Gltf, GltfError>) -> bool {
+		matches!(result, Err(GltfError::Gltf(_)))
+	}
+
+	#[test]
+	fn loads_triangle() {
+		let gltf = load(&triangle(r#"[{"mesh": 0}]"#, r#"[{"nodes": [0]}]"#))
+			.expect("Should have been able to load triangle");
+		assert_eq!(gltf.roots, [0]);
+		let data = &gltf.meshes[0].primitives[0].data;
+		assert_eq!(data.indices, [0, 1, 2]);
+		assert_eq!(data.vertices[1].position, [1., 0., 0.]);
+		// no NORMAL, so they get generated
+		assert_eq!(data.vertices[0].normal, [0., 0., 1.]);
+		assert_eq!(gltf.bounds(), Some(([0.; 3], [1., 1., 0.])));
+		// no scenes, so every parentless node is a root
+		let gltf = load(&triangle(r#"[{"mesh": 0}]"#, "[]"))
+			.expect("Should have been able to load triangle without scenes");
+		assert_eq!(gltf.mesh_instances().len(), 1);
+	}
+
+	#[test]
+	fn loads_gltf_with_external_buffer() {
+		let dir = std::env::temp_dir().join(format!("lvkrs-gltf-{}", std::process::id()));
+		std::fs::create_dir_all(&dir).expect("Should have been able to create temp dir");
+		std::fs::write(dir.join("tri angle.bin"), triangle_bin())
+			.expect("Should have been able to write buffer");
+		let json = triangle(r#"[{"mesh": 0}]"#, "[]").replace(
+			r#"{"byteLength": 42}"#,
+			r#"{"byteLength": 42, "uri": "tri%20angle.bin"}"#,
+		);
+		std::fs::write(dir.join("triangle.gltf"), json)
+			.expect("Should have been able to write gltf");
+		let gltf = Gltf::load(dir.join("triangle.gltf"));
+		std::fs::remove_dir_all(&dir).expect("Should have been able to remove temp dir");
+		let gltf = gltf.expect("Should have been able to load gltf");
+		assert_eq!(gltf.meshes[0].primitives[0].data.indices, [0, 1, 2]);
+	}
+
+	#[test]
+	fn converts_materials_and_samplers() {
+		// a 1x1 png after the triangle's 42 bytes, padded to 44
+		let mut png = Vec::new();
+		image::RgbImage::from_pixel(1, 1, image::Rgb([255, 0, 0]))
+			.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+			.expect("Should have been able to encode png");
+		let mut bin = triangle_bin();
+		bin.resize(44, 0);
+		bin.extend(&png);
+		let json = triangle(r#"[{"mesh": 0}]"#, "[]")
+			.replace(
+				r#""byteLength": 42}"#,
+				&format!(r#""byteLength": {}}}"#, bin.len()),
+			)
+			.replace(
+				r#"{"buffer": 0, "byteOffset": 36, "byteLength": 6}"#,
+				&format!(
+					r#"{{"buffer": 0, "byteOffset": 36, "byteLength": 6}},
+					{{"buffer": 0, "byteOffset": 44, "byteLength": {}}}"#,
+					png.len()
+				),
+			)
+			.replace(r#""indices": 1}"#, r#""indices": 1, "material": 0}"#)
+			.replace(
+				r#""nodes":"#,
+				r#""images": [{"bufferView": 2, "mimeType": "image/png"}],
+				"samplers": [{"magFilter": 9728, "minFilter": 9985, "wrapS": 33071, "wrapT": 33648}],
+				"textures": [{"source": 0, "sampler": 0}, {"source": 0}],
+				"materials": [{
+					"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}, "metallicFactor": 0.5},
+					"normalTexture": {"index": 1, "texCoord": 1, "scale": 2},
+					"alphaMode": "MASK",
+					"doubleSided": true
+				}],
+				"nodes":"#,
+			);
+		let gltf = Gltf::from_slice(&glb(&json, &bin), Path::new("."))
+			.expect("Should have been able to load textured triangle");
+		assert_eq!(gltf.images[0].get_pixel(0, 0).0, [255, 0, 0, 255]);
+		assert_eq!(
+			gltf.textures[0].sampler,
+			SamplerDesc {
+				mag_filter: vk::Filter::NEAREST,
+				min_filter: vk::Filter::LINEAR,
+				mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+				address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+				address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
+				..SamplerDesc::default()
+			}
+		);
+		assert_eq!(gltf.textures[1].sampler, SamplerDesc::default());
+		let material = &gltf.materials[0];
+		assert_eq!(
+			material.base_color_texture,
+			Some(TextureRef {
+				texture: 0,
+				tex_coord: 0
+			})
+		);
+		assert_eq!(
+			material.normal_texture,
+			Some(TextureRef {
+				texture: 1,
+				tex_coord: 1
+			})
+		);
+		assert_eq!(material.normal_scale, 2.);
+		assert_eq!(material.metallic_factor, 0.5);
+		assert_eq!(material.roughness_factor, 1.);
+		assert_eq!(material.alpha_mode, AlphaMode::Mask(0.5));
+		assert!(material.double_sided);
+		assert_eq!(gltf.meshes[0].primitives[0].material, Some(0));
+	}
+
+	#[test]
+	fn rejects_truncated_glb() {
+		let bytes = glb(&triangle(r#"[{"mesh": 0}]"#, "[]"), &triangle_bin());
+		for len in [2, 6, 12, 40, bytes.len() - 8] {
+			assert!(
+				is_crate_error(Gltf::from_slice(&bytes[..len], Path::new("."))),
+				"{} bytes",
+				len
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_out_of_range_indices() {
+		// the crate's validation catches these
+		let cases = [
+			triangle(r#"[{"mesh": 1}]"#, "[]"),
+			triangle(r#"[{"mesh": 0, "children": [4]}]"#, "[]"),
+			triangle(r#"[{"mesh": 0}]"#, r#"[{"nodes": [3]}]"#),
+			triangle_json(&accessors(r#""bufferView": 7, "count": 3"#), "[]", "[]"),
+		];
+		for json in cases {
+			assert!(is_crate_error(load(&json)), "{}", json);
+		}
+	}
+
+	#[test]
+	fn rejects_out_of_range_attribute_accessor() {
+		// checked before the crate's validation, which would index past the accessors
+		let json = triangle_json("[]", r#"[{"mesh": 0}]"#, "[]");
+		assert_eq!(
+			invalid_message(load(&json)),
+			"POSITION accessor 0 out of range"
+		);
+	}
+
+	#[test]
+	fn rejects_out_of_range_vertex_index() {
+		let mut bin = triangle_bin();
+		bin[40] = 9;
+		let json = triangle("[]", "[]");
+		let result = Gltf::from_slice(&glb(&json, &bin), Path::new("."));
+		assert_eq!(invalid_message(result), "index 9 out of range");
+	}
+
+	#[test]
+	fn rejects_multi_parent_nodes() {
+		let nodes = r#"[{"children": [2]}, {"children": [2]}, {"mesh": 0}]"#;
+		assert_eq!(
+			invalid_message(load(&triangle(nodes, "[]"))),
+			"node 2 has more than one parent"
+		);
+		// a cycle makes its nodes children of each other, so none of them can be a root
+		let nodes = r#"[{"children": [1]}, {"children": [0], "mesh": 0}]"#;
+		assert_eq!(
+			invalid_message(load(&triangle(nodes, r#"[{"nodes": [0]}]"#))),
+			"scene root 0 is not a root node"
+		);
+	}
+
+	#[test]
+	fn rejects_accessors_past_their_view() {
+		let past_end =
+			|fields: &str| invalid_message(load(&triangle_json(&accessors(fields), "[]", "[]")));
+		assert_eq!(
+			past_end(r#""bufferView": 0, "count": 4"#),
+			"accessor 0 runs past its bufferView"
+		);
+		assert_eq!(
+			past_end(r#""bufferView": 0, "count": 3, "byteOffset": 4"#),
+			"accessor 0 runs past its bufferView"
+		);
+		// would overflow usize in the bounds check
+		assert_eq!(
+			past_end(r#""bufferView": 0, "count": 3, "byteOffset": 18446744073709549568"#),
+			"accessor 0 runs past its bufferView"
+		);
+		assert_eq!(
+			past_end(r#""bufferView": 0, "count": 4611686018427387904"#),
+			"accessor 0 runs past its bufferView"
+		);
+		// the reader would underflow on count - 1
+		assert_eq!(
+			past_end(r#""bufferView": 0, "count": 0"#),
+			"accessor 0 is empty"
+		);
+		// without a bufferView or sparse storage the crate rejects it
+		let json = triangle_json(&accessors(r#""count": 3"#), "[]", "[]");
+		assert!(is_crate_error(load(&json)));
+	}
+
+	#[test]
+	fn rejects_attributes_of_the_wrong_type() {
+		// float indices would hit the reader's unreachable!()
+		let json = triangle("[]", "[]").replace(
+			INDEX_ACCESSOR,
+			r#"{"bufferView": 1, "componentType": 5126, "count": 1, "type": "SCALAR"}"#,
+		);
+		assert_eq!(
+			invalid_message(load(&json)),
+			"accessor 1 is Scalar F32, expected Scalar [U8, U16, U32]"
+		);
+		let json = triangle_json(
+			&accessors(r#""bufferView": 0, "count": 3"#).replace("VEC3", "VEC4"),
+			"[]",
+			"[]",
+		);
+		assert_eq!(
+			invalid_message(load(&json)),
+			"accessor 0 is Vec4 F32, expected Vec3 [F32]"
+		);
+	}
+
+	#[test]
+	fn rejects_views_past_their_buffer() {
+		let json = triangle("[]", "[]").replace(
+			r#""byteOffset": 36, "byteLength": 6"#,
+			r#""byteOffset": 18446744073709551615, "byteLength": 6"#,
+		);
+		assert_eq!(
+			invalid_message(load(&json)),
+			"bufferView 1 runs past its buffer"
+		);
+	}
+
+	#[test]
+	fn rejects_small_stride() {
+		let json = triangle("[]", "[]").replace(
+			r#"{"buffer": 0, "byteLength": 36}"#,
+			r#"{"buffer": 0, "byteLength": 36, "byteStride": 8}"#,
+		);
+		assert_eq!(
+			invalid_message(load(&json)),
+			"accessor 0 has a byteStride below its element size"
+		);
+	}
+
+	#[test]
+	fn rejects_uris_that_decode_to_invalid_utf8() {
+		let json = triangle("[]", "[]").replace(
+			r#"{"byteLength": 42}"#,
+			r#"{"byteLength": 42, "uri": "%FF.bin"}"#,
+		);
+		let result = Gltf::from_slice(json.as_bytes(), Path::new("."));
+		assert_eq!(
+			invalid_message(result),
+			"uri %FF.bin is not utf8 once decoded"
+		);
+	}
+}
@@ -0,0 +1,433 @@
+use super::{Device, DeviceContext, RollingStats, vk};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::Path;
+
+// two queries per scope
+pub const MAX_GPU_SCOPES: u32 = 64;
+// frames the stats table and the chrome trace look back over
+pub const GPU_PROFILER_HISTORY: usize = 240;
+// VkSwap logs the table (at debug) this often
+pub const GPU_PROFILER_LOG_INTERVAL: u64 = 600;
+// path to write the chrome trace to on shutdown
+pub const GPU_TRACE_ENV_VAR: &str = "VKRS_GPU_TRACE";
+
+struct PendingScope {
+	name: String,
+	depth: u32,
+	begin_query: u32,
+	end_query: Option<u32>,
+}
+
+// one resolved scope, timestamps in ns on the gpu's clock
+#[derive(Clone, Debug)]
+pub struct GpuScopeTiming {
+	pub name: String,
+	pub depth: u32,
+	pub begin_ns: f64,
+	pub end_ns: f64,
+}
+
+impl GpuScopeTiming {
+	pub fn duration_ms(&self) -> f64 {
+		(self.end_ns - self.begin_ns) / 1_000_000.
+	}
+}
+
+// the timestamp query pool of one FrameData. only read back once the frame's timeline value
+// is reached, i.e. FRAMES_IN_FLIGHT frames later, so nothing ever waits on it
+pub struct FrameTimestamps {
+	pub query_pool: vk::QueryPool,
+	scopes: RefCell<Vec<PendingScope>>,
+	next_query: Cell<u32>,
+	depth: Cell<u32>,
+	// ns per tick and the bits of a timestamp that mean anything
+	period: f64,
+	valid_mask: u64,
+}
+
+impl FrameTimestamps {
+	// null pool (every scope is a no-op) when the graphics queue can't do timestamps
+	pub fn new(device_ctx: &DeviceContext, timestamp_valid_bits: u32) -> FrameTimestamps {
+		let query_pool = if timestamp_valid_bits == 0 {
+			vk::QueryPool::null()
+		} else {
+			let pool_info = vk::QueryPoolCreateInfo {
+				query_type: vk::QueryType::TIMESTAMP,
+				query_count: MAX_GPU_SCOPES * 2,
+				..Default::default()
+			};
+			unsafe {
+				device_ctx
+					.device()
+					.create_query_pool(&pool_info, None)
+					.expect("Should have been able to create timestamp query pool")
+			}
+		};
+		FrameTimestamps {
+			query_pool,
+			scopes: RefCell::new(Vec::new()),
+			next_query: Cell::new(0),
+			depth: Cell::new(0),
+			period: device_ctx.properties.limits.timestamp_period as f64,
+			valid_mask: if timestamp_valid_bits >= 64 {
+				u64::MAX
+			} else {
+				(1u64 << timestamp_valid_bits) - 1
+			},
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.query_pool != vk::QueryPool::null()
+	}
+
+	// has to be recorded before any scope of the frame, outside of rendering
+	pub fn begin_frame(&self, device: &Device, cmd_buff: vk::CommandBuffer) {
+		self.scopes.borrow_mut().clear();
+		self.next_query.set(0);
+		self.depth.set(0);
+		if self.is_enabled() {
+			unsafe {
+				device.cmd_reset_query_pool(cmd_buff, self.query_pool, 0, MAX_GPU_SCOPES * 2)
+			};
+		}
+	}
+
+	// times everything recorded until the guard is dropped
+	pub fn profile_scope<'a>(
+		&'a self,
+		device: &'a Device,
+		cmd_buff: vk::CommandBuffer,
+		name: &str,
+	) -> ProfileScope<'a> {
+		let Some(query) = self.reserve_begin() else {
+			return ProfileScope {
+				timestamps: self,
+				device,
+				cmd_buff,
+				scope: None,
+			};
+		};
+		unsafe {
+			device.cmd_write_timestamp(
+				cmd_buff,
+				vk::PipelineStageFlags::TOP_OF_PIPE,
+				self.query_pool,
+				query,
+			)
+		};
+		let mut scopes = self.scopes.borrow_mut();
+		scopes.push(PendingScope {
+			name: name.to_owned(),
+			depth: self.depth.get() - 1,
+			begin_query: query,
+			end_query: None,
+		});
+		ProfileScope {
+			timestamps: self,
+			device,
+			cmd_buff,
+			scope: Some(scopes.len() - 1),
+		}
+	}
+
+	// a new scope needs its begin, its end and the end of every scope that's still open
+	fn reserve_begin(&self) -> Option<u32> {
+		let query = self.next_query.get();
+		let depth = self.depth.get();
+		if !self.is_enabled() || query + 2 + depth > MAX_GPU_SCOPES * 2 {
+			return None;
+		}
+		self.next_query.set(query + 1);
+		self.depth.set(depth + 1);
+		Some(query)
+	}
+
+	// reserve_begin keeps room for this, the check is just so a bug can't write out of the pool
+	fn reserve_end(&self) -> Option<u32> {
+		self.depth.set(self.depth.get() - 1);
+		let query = self.next_query.get();
+		if query >= MAX_GPU_SCOPES * 2 {
+			return None;
+		}
+		self.next_query.set(query + 1);
+		Some(query)
+	}
+
+	// None if nothing was recorded or the gpu isn't done with it (shouldn't happen once the
+	// frame's timeline value is reached)
+	pub fn read_results(&self, device: &Device) -> Option<Vec<GpuScopeTiming>> {
+		let scopes = self.scopes.borrow();
+		let query_count = self.next_query.get();
+		if scopes.is_empty() || query_count == 0 {
+			return None;
+		}
+		let mut ticks = vec![0u64; query_count as usize];
+		let result = unsafe {
+			device.get_query_pool_results(
+				self.query_pool,
+				0,
+				&mut ticks,
+				vk::QueryResultFlags::TYPE_64,
+			)
+		};
+		if result.is_err() {
+			return None;
+		}
+		let to_ns = |query: u32| (ticks[query as usize] & self.valid_mask) as f64 * self.period;
+		Some(
+			scopes
+				.iter()
+				.filter_map(|scope| {
+					Some(GpuScopeTiming {
+						name: scope.name.clone(),
+						depth: scope.depth,
+						begin_ns: to_ns(scope.begin_query),
+						end_ns: to_ns(scope.end_query?),
+					})
+				})
+				.collect(),
+		)
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		if self.is_enabled() {
+			unsafe { device.destroy_query_pool(self.query_pool, None) };
+		}
+	}
+}
+
+// from FrameTimestamps::profile_scope, writes the end timestamp on drop
+pub struct ProfileScope<'a> {
+	timestamps: &'a FrameTimestamps,
+	device: &'a Device,
+	cmd_buff: vk::CommandBuffer,
+	scope: Option<usize>,
+}
+
+impl Drop for ProfileScope<'_> {
+	fn drop(&mut self) {
+		let Some(scope) = self.scope else {
+			return;
+		};
+		let Some(query) = self.timestamps.reserve_end() else {
+			log::warn!("gpu profiler: no query left for the end of a scope");
+			return;
+		};
+		unsafe {
+			self.device.cmd_write_timestamp(
+				self.cmd_buff,
+				vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+				self.timestamps.query_pool,
+				query,
+			)
+		};
+		self.timestamps.scopes.borrow_mut()[scope].end_query = Some(query);
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct GpuScopeStats {
+	pub name: String,
+	pub last_ms: f64,
+	pub avg_ms: f64,
+	pub min_ms: f64,
+	pub max_ms: f64,
+}
+
+// rolling per scope stats over the frames FrameTimestamps hands back
+pub struct GpuProfiler {
+	frames_recorded: u64,
+	// scope names in first seen order, so the table doesn't jump around
+	order: Vec<String>,
+	stats: HashMap<String, RollingStats>,
+	history: VecDeque<(u64, Vec<GpuScopeTiming>)>,
+}
+
+impl Default for GpuProfiler {
+	fn default() -> GpuProfiler {
+		GpuProfiler::new()
+	}
+}
+
+impl GpuProfiler {
+	pub fn new() -> GpuProfiler {
+		GpuProfiler {
+			frames_recorded: 0,
+			order: Vec::new(),
+			stats: HashMap::new(),
+			history: VecDeque::with_capacity(GPU_PROFILER_HISTORY),
+		}
+	}
+
+	pub fn frames_recorded(&self) -> u64 {
+		self.frames_recorded
+	}
+
+	pub fn record(&mut self, timings: Vec<GpuScopeTiming>) {
+		for timing in &timings {
+			if !self.stats.contains_key(&timing.name) {
+				self.order.push(timing.name.clone());
+			}
+			self.stats
+				.entry(timing.name.clone())
+				.or_insert_with(|| RollingStats::new(GPU_PROFILER_HISTORY))
+				.push(timing.duration_ms());
+		}
+		if self.history.len() == GPU_PROFILER_HISTORY {
+			self.history.pop_front();
+		}
+		self.history.push_back((self.frames_recorded, timings));
+		self.frames_recorded += 1;
+	}
+
+	pub fn table(&self) -> Vec<GpuScopeStats> {
+		self.order
+			.iter()
+			.map(|name| {
+				let stats = &self.stats[name];
+				GpuScopeStats {
+					name: name.clone(),
+					last_ms: stats.last(),
+					avg_ms: stats.avg(),
+					min_ms: stats.min(),
+					max_ms: stats.max(),
+				}
+			})
+			.collect()
+	}
+
+	pub fn log_table(&self, level: log::Level) {
+		let mut out = format!(
+			"gpu timings over {} frames (ms):\n  {:<24} {:>8} {:>8} {:>8} {:>8}",
+			self.history.len(),
+			"scope",
+			"last",
+			"avg",
+			"min",
+			"max"
+		);
+		for row in self.table() {
+			let _ = write!(
+				out,
+				"\n  {:<24} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+				row.name, row.last_ms, row.avg_ms, row.min_ms, row.max_ms
+			);
+		}
+		log::log!(level, "{}", out);
+	}
+
+	// chrome://tracing / perfetto "trace event" json of the frames still in the history
+	pub fn chrome_trace_json(&self) -> String {
+		let origin = self
+			.history
+			.iter()
+			.flat_map(|(_, timings)| timings.iter().map(|t| t.begin_ns))
+			.reduce(f64::min)
+			.unwrap_or(0.);
+		let mut events: Vec<String> = Vec::new();
+		for (frame, timings) in &self.history {
+			for timing in timings {
+				events.push(format!(
+					"{{\"name\":\"{}\",\"cat\":\"gpu\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":0,\"tid\":0,\"args\":{{\"frame\":{},\"depth\":{}}}}}",
+					timing.name.replace('\\', "\\\\").replace('"', "\\\""),
+					(timing.begin_ns - origin) / 1000.,
+					(timing.end_ns - timing.begin_ns) / 1000.,
+					frame,
+					timing.depth
+				));
+			}
+		}
+		format!("{{\"traceEvents\":[{}]}}", events.join(","))
+	}
+
+	pub fn write_chrome_trace(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+		std::fs::write(path, self.chrome_trace_json())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ash::vk::Handle;
+
+	fn timestamps() -> FrameTimestamps {
+		FrameTimestamps {
+			query_pool: vk::QueryPool::from_raw(1),
+			scopes: RefCell::new(Vec::new()),
+			next_query: Cell::new(0),
+			depth: Cell::new(0),
+			period: 1.,
+			valid_mask: u64::MAX,
+		}
+	}
+
+	#[test]
+	fn flat_scopes_use_every_query() {
+		let timestamps = timestamps();
+		for _ in 0..MAX_GPU_SCOPES {
+			assert!(timestamps.reserve_begin().is_some());
+			assert!(timestamps.reserve_end().is_some());
+		}
+		assert_eq!(timestamps.reserve_begin(), None);
+		assert_eq!(timestamps.next_query.get(), MAX_GPU_SCOPES * 2);
+	}
+
+	#[test]
+	fn nested_scopes_keep_room_for_their_ends() {
+		let timestamps = timestamps();
+		// open scopes until one gets refused, then close them all
+		let mut opened = 0;
+		while timestamps.reserve_begin().is_some() {
+			opened += 1;
+		}
+		assert_eq!(opened, MAX_GPU_SCOPES);
+		let ends: Vec<Option<u32>> = (0..opened).map(|_| timestamps.reserve_end()).collect();
+		assert!(ends.iter().all(Option::is_some));
+		assert!(
+			ends.iter()
+				.flatten()
+				.all(|&query| query < MAX_GPU_SCOPES * 2)
+		);
+		assert_eq!(timestamps.depth.get(), 0);
+	}
+
+	#[test]
+	fn refused_begin_inside_open_scopes() {
+		let timestamps = timestamps();
+		// fill up with flat scopes, leaving 4 queries
+		for _ in 0..MAX_GPU_SCOPES - 2 {
+			timestamps.reserve_begin();
+			timestamps.reserve_end();
+		}
+		let last = MAX_GPU_SCOPES * 2 - 1;
+		assert_eq!(timestamps.reserve_begin(), Some(last - 3));
+		assert_eq!(timestamps.reserve_begin(), Some(last - 2));
+		// 2 left, both already spoken for by the open scopes' ends
+		assert_eq!(timestamps.reserve_begin(), None);
+		assert_eq!(timestamps.reserve_end(), Some(last - 1));
+		assert_eq!(timestamps.reserve_begin(), None);
+		assert_eq!(timestamps.reserve_end(), Some(last));
+		assert_eq!(timestamps.depth.get(), 0);
+	}
+
+	#[test]
+	fn end_never_writes_past_the_pool() {
+		let timestamps = timestamps();
+		timestamps.next_query.set(MAX_GPU_SCOPES * 2);
+		timestamps.depth.set(1);
+		assert_eq!(timestamps.reserve_end(), None);
+		assert_eq!(timestamps.next_query.get(), MAX_GPU_SCOPES * 2);
+	}
+
+	#[test]
+	fn disabled_pool_reserves_nothing() {
+		let timestamps = FrameTimestamps {
+			query_pool: vk::QueryPool::null(),
+			..timestamps()
+		};
+		assert_eq!(timestamps.reserve_begin(), None);
+	}
+}
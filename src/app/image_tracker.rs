@@ -0,0 +1,537 @@
+use super::{DeviceContext, vk};
+use std::collections::HashMap;
+
+// what an image is about to be used for. each usage maps to the layout/access/stage
+// the image has to be in, the tracker works out the barrier from whatever came before
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageUsage {
+	ColorAttachment,
+	DepthAttachment,
+	DepthReadOnly,
+	SampledFragment,
+	SampledCompute,
+	StorageCompute,
+	TransferSrc,
+	TransferDst,
+	Present,
+}
+
+impl ImageUsage {
+	pub fn state(self) -> ImageState {
+		use vk::{AccessFlags2 as A, ImageLayout as L, PipelineStageFlags2 as S};
+		let (layout, access, stage) = match self {
+			ImageUsage::ColorAttachment => (
+				L::COLOR_ATTACHMENT_OPTIMAL,
+				A::COLOR_ATTACHMENT_READ | A::COLOR_ATTACHMENT_WRITE,
+				S::COLOR_ATTACHMENT_OUTPUT,
+			),
+			ImageUsage::DepthAttachment => (
+				L::DEPTH_ATTACHMENT_OPTIMAL,
+				A::DEPTH_STENCIL_ATTACHMENT_READ | A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+				S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS,
+			),
+			ImageUsage::DepthReadOnly => (
+				L::DEPTH_READ_ONLY_OPTIMAL,
+				A::DEPTH_STENCIL_ATTACHMENT_READ | A::SHADER_SAMPLED_READ,
+				S::EARLY_FRAGMENT_TESTS | S::LATE_FRAGMENT_TESTS | S::FRAGMENT_SHADER,
+			),
+			ImageUsage::SampledFragment => (
+				L::SHADER_READ_ONLY_OPTIMAL,
+				A::SHADER_SAMPLED_READ,
+				S::FRAGMENT_SHADER,
+			),
+			ImageUsage::SampledCompute => (
+				L::SHADER_READ_ONLY_OPTIMAL,
+				A::SHADER_SAMPLED_READ,
+				S::COMPUTE_SHADER,
+			),
+			ImageUsage::StorageCompute => (
+				L::GENERAL,
+				A::SHADER_STORAGE_READ | A::SHADER_STORAGE_WRITE,
+				S::COMPUTE_SHADER,
+			),
+			ImageUsage::TransferSrc => (L::TRANSFER_SRC_OPTIMAL, A::TRANSFER_READ, S::TRANSFER),
+			ImageUsage::TransferDst => (L::TRANSFER_DST_OPTIMAL, A::TRANSFER_WRITE, S::TRANSFER),
+			// the present semaphore does the rest
+			ImageUsage::Present => (L::PRESENT_SRC_KHR, A::NONE, S::NONE),
+		};
+		ImageState {
+			layout,
+			access,
+			stage,
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ImageState {
+	pub layout: vk::ImageLayout,
+	pub access: vk::AccessFlags2,
+	pub stage: vk::PipelineStageFlags2,
+}
+
+impl ImageState {
+	pub const UNDEFINED: ImageState = ImageState {
+		layout: vk::ImageLayout::UNDEFINED,
+		access: vk::AccessFlags2::NONE,
+		stage: vk::PipelineStageFlags2::NONE,
+	};
+
+	fn writes(&self) -> bool {
+		self.access.intersects(
+			vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+				| vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+				| vk::AccessFlags2::SHADER_STORAGE_WRITE
+				| vk::AccessFlags2::SHADER_WRITE
+				| vk::AccessFlags2::TRANSFER_WRITE
+				| vk::AccessFlags2::HOST_WRITE
+				| vk::AccessFlags2::MEMORY_WRITE,
+		)
+	}
+}
+
+struct TrackedImage {
+	aspect_mask: vk::ImageAspectFlags,
+	mip_levels: u32,
+	array_layers: u32,
+	// one entry per (layer, mip), layer major
+	states: Vec<ImageState>,
+}
+
+impl TrackedImage {
+	fn index(&self, mip: u32, layer: u32) -> usize {
+		(layer * self.mip_levels + mip) as usize
+	}
+}
+
+// records where every subresource of every registered image currently is, so callers only
+// say what they want to do next. barriers queue up until `flush` puts them all in one
+// cmd_pipeline_barrier2
+#[derive(Default)]
+pub struct ImageTracker {
+	images: HashMap<vk::Image, TrackedImage>,
+	pending: Vec<vk::ImageMemoryBarrier2<'static>>,
+}
+
+impl ImageTracker {
+	pub fn new() -> ImageTracker {
+		ImageTracker::default()
+	}
+
+	pub fn register(
+		&mut self,
+		image: vk::Image,
+		aspect_mask: vk::ImageAspectFlags,
+		mip_levels: u32,
+		array_layers: u32,
+	) {
+		self.images.insert(
+			image,
+			TrackedImage {
+				aspect_mask,
+				mip_levels,
+				array_layers,
+				states: vec![ImageState::UNDEFINED; (mip_levels * array_layers) as usize],
+			},
+		);
+	}
+
+	pub fn unregister(&mut self, image: vk::Image) {
+		self.images.remove(&image);
+	}
+
+	pub fn is_tracked(&self, image: vk::Image) -> bool {
+		self.images.contains_key(&image)
+	}
+
+	pub fn state(&self, image: vk::Image, mip: u32, layer: u32) -> Option<ImageState> {
+		self.images
+			.get(&image)
+			.map(|tracked| tracked.states[tracked.index(mip, layer)])
+	}
+
+	// contents don't matter anymore (e.g. freshly acquired swapchain image). the next transition
+	// comes from UNDEFINED but still waits on `stage`, which is how the acquire semaphore wait
+	// gets chained into the layout transition
+	pub fn discard(&mut self, image: vk::Image, stage: vk::PipelineStageFlags2) {
+		let tracked = self
+			.images
+			.get_mut(&image)
+			.expect("Should only discard registered images");
+		tracked.states.fill(ImageState {
+			layout: vk::ImageLayout::UNDEFINED,
+			access: vk::AccessFlags2::NONE,
+			stage,
+		});
+	}
+
+	// memory is being reused by `image` after another (aliased) image was last in `prev`.
+	// contents are garbage, but the previous owner's accesses still have to finish first
+	pub fn alias(&mut self, image: vk::Image, prev: ImageState) {
+		let tracked = self
+			.images
+			.get_mut(&image)
+			.expect("Should only alias registered images");
+		tracked.states.fill(ImageState {
+			layout: vk::ImageLayout::UNDEFINED,
+			..prev
+		});
+	}
+
+	pub fn use_image(&mut self, image: vk::Image, usage: ImageUsage) {
+		let tracked = self
+			.images
+			.get(&image)
+			.expect("Should only use registered images");
+		let range = vk::ImageSubresourceRange {
+			aspect_mask: tracked.aspect_mask,
+			base_mip_level: 0,
+			level_count: tracked.mip_levels,
+			base_array_layer: 0,
+			layer_count: tracked.array_layers,
+		};
+		self.use_subresource(image, range, usage);
+	}
+
+	pub fn use_subresource(
+		&mut self,
+		image: vk::Image,
+		range: vk::ImageSubresourceRange,
+		usage: ImageUsage,
+	) {
+		let next = usage.state();
+		let tracked = self
+			.images
+			.get_mut(&image)
+			.expect("Should only use registered images");
+		let level_count = if range.level_count == vk::REMAINING_MIP_LEVELS {
+			tracked.mip_levels - range.base_mip_level
+		} else {
+			range.level_count
+		};
+		let layer_count = if range.layer_count == vk::REMAINING_ARRAY_LAYERS {
+			tracked.array_layers - range.base_array_layer
+		} else {
+			range.layer_count
+		};
+
+		for layer in range.base_array_layer..range.base_array_layer + layer_count {
+			// walk the mips, merging runs that come from the same state into a single barrier
+			let mut run_start = range.base_mip_level;
+			let mut run_state: Option<ImageState> = None;
+			for mip in range.base_mip_level..=range.base_mip_level + level_count {
+				let prev = (mip < range.base_mip_level + level_count)
+					.then(|| tracked.states[tracked.index(mip, layer)]);
+				if prev != run_state {
+					if let Some(old) = run_state
+						&& let Some(barrier) = ImageTracker::barrier_between(
+							image,
+							vk::ImageSubresourceRange {
+								aspect_mask: range.aspect_mask,
+								base_mip_level: run_start,
+								level_count: mip - run_start,
+								base_array_layer: layer,
+								layer_count: 1,
+							},
+							old,
+							next,
+						) {
+						self.pending.push(barrier);
+					}
+					run_start = mip;
+					run_state = prev;
+				}
+			}
+			for mip in range.base_mip_level..range.base_mip_level + level_count {
+				let idx = tracked.index(mip, layer);
+				let prev = tracked.states[idx];
+				// read after read in the same layout needs no barrier, but a later write
+				// still has to wait on both readers
+				tracked.states[idx] =
+					if prev.layout == next.layout && !prev.writes() && !next.writes() {
+						ImageState {
+							layout: next.layout,
+							access: prev.access | next.access,
+							stage: prev.stage | next.stage,
+						}
+					} else {
+						next
+					};
+			}
+		}
+	}
+
+	fn barrier_between(
+		image: vk::Image,
+		subresource_range: vk::ImageSubresourceRange,
+		prev: ImageState,
+		next: ImageState,
+	) -> Option<vk::ImageMemoryBarrier2<'static>> {
+		let needs_barrier = prev.layout != next.layout || prev.writes() || next.writes();
+		if !needs_barrier {
+			return None;
+		}
+		Some(vk::ImageMemoryBarrier2 {
+			src_stage_mask: prev.stage,
+			// write after read only needs the execution dependency, nothing to make available
+			src_access_mask: if prev.writes() {
+				prev.access
+			} else {
+				vk::AccessFlags2::NONE
+			},
+			dst_stage_mask: next.stage,
+			dst_access_mask: next.access,
+			old_layout: prev.layout,
+			new_layout: next.layout,
+			src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+			image,
+			subresource_range,
+			..Default::default()
+		})
+	}
+
+	pub fn has_pending(&self) -> bool {
+		!self.pending.is_empty()
+	}
+
+	// for callers that want to put buffer barriers in the same cmd_pipeline_barrier2
+	pub fn take_pending(&mut self) -> Vec<vk::ImageMemoryBarrier2<'static>> {
+		std::mem::take(&mut self.pending)
+	}
+
+	pub fn flush(&mut self, device_ctx: &DeviceContext, cmd_buff: vk::CommandBuffer) {
+		if self.pending.is_empty() {
+			return;
+		}
+		let deps_info = vk::DependencyInfo::default().image_memory_barriers(&self.pending);
+		device_ctx.cmd_pipeline_barrier2(cmd_buff, &deps_info);
+		self.pending.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ash::vk::Handle;
+
+	fn image() -> vk::Image {
+		vk::Image::from_raw(1)
+	}
+
+	fn tracker(mip_levels: u32, array_layers: u32) -> ImageTracker {
+		let mut tracker = ImageTracker::new();
+		tracker.register(
+			image(),
+			vk::ImageAspectFlags::COLOR,
+			mip_levels,
+			array_layers,
+		);
+		tracker
+	}
+
+	fn mips(base_mip_level: u32, level_count: u32) -> vk::ImageSubresourceRange {
+		vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			base_mip_level,
+			level_count,
+			base_array_layer: 0,
+			layer_count: 1,
+		}
+	}
+
+	// (first mip, mip count, old layout, new layout) of each pending barrier
+	fn barriers(tracker: &mut ImageTracker) -> Vec<(u32, u32, vk::ImageLayout, vk::ImageLayout)> {
+		tracker
+			.take_pending()
+			.iter()
+			.map(|b| {
+				(
+					b.subresource_range.base_mip_level,
+					b.subresource_range.level_count,
+					b.old_layout,
+					b.new_layout,
+				)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn first_use_transitions_from_undefined() {
+		let mut tracker = tracker(1, 1);
+		tracker.use_image(image(), ImageUsage::TransferDst);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].old_layout, vk::ImageLayout::UNDEFINED);
+		assert_eq!(pending[0].new_layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL);
+		assert_eq!(pending[0].dst_access_mask, vk::AccessFlags2::TRANSFER_WRITE);
+		assert!(!tracker.has_pending());
+		assert_eq!(
+			tracker.state(image(), 0, 0),
+			Some(ImageUsage::TransferDst.state())
+		);
+	}
+
+	#[test]
+	fn splits_and_merges_mip_runs() {
+		use vk::ImageLayout as L;
+		let mut tracker = tracker(5, 1);
+		tracker.use_image(image(), ImageUsage::TransferDst);
+		tracker.take_pending();
+		// mipmap generation style: mip 1 and 3 move on, the rest stay
+		tracker.use_subresource(image(), mips(1, 1), ImageUsage::TransferSrc);
+		tracker.use_subresource(image(), mips(3, 1), ImageUsage::TransferSrc);
+		assert_eq!(
+			barriers(&mut tracker),
+			[
+				(1, 1, L::TRANSFER_DST_OPTIMAL, L::TRANSFER_SRC_OPTIMAL),
+				(3, 1, L::TRANSFER_DST_OPTIMAL, L::TRANSFER_SRC_OPTIMAL),
+			]
+		);
+		// one barrier per run of equal states: 0, 1, 2, 3, 4 alternate
+		tracker.use_image(image(), ImageUsage::SampledFragment);
+		assert_eq!(
+			barriers(&mut tracker),
+			[
+				(0, 1, L::TRANSFER_DST_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL),
+				(1, 1, L::TRANSFER_SRC_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL),
+				(2, 1, L::TRANSFER_DST_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL),
+				(3, 1, L::TRANSFER_SRC_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL),
+				(4, 1, L::TRANSFER_DST_OPTIMAL, L::SHADER_READ_ONLY_OPTIMAL),
+			]
+		);
+		// now all the same again, so one barrier covers every mip
+		tracker.use_image(image(), ImageUsage::ColorAttachment);
+		assert_eq!(
+			barriers(&mut tracker),
+			[(
+				0,
+				5,
+				L::SHADER_READ_ONLY_OPTIMAL,
+				L::COLOR_ATTACHMENT_OPTIMAL
+			)]
+		);
+	}
+
+	#[test]
+	fn remaining_counts_cover_the_rest() {
+		let mut tracker = tracker(4, 2);
+		let range = vk::ImageSubresourceRange {
+			aspect_mask: vk::ImageAspectFlags::COLOR,
+			base_mip_level: 1,
+			level_count: vk::REMAINING_MIP_LEVELS,
+			base_array_layer: 1,
+			layer_count: vk::REMAINING_ARRAY_LAYERS,
+		};
+		tracker.use_subresource(image(), range, ImageUsage::TransferDst);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].subresource_range.base_array_layer, 1);
+		assert_eq!(pending[0].subresource_range.base_mip_level, 1);
+		assert_eq!(pending[0].subresource_range.level_count, 3);
+		assert_eq!(tracker.state(image(), 0, 1), Some(ImageState::UNDEFINED));
+		assert_eq!(tracker.state(image(), 0, 0), Some(ImageState::UNDEFINED));
+		assert_eq!(
+			tracker.state(image(), 3, 1),
+			Some(ImageUsage::TransferDst.state())
+		);
+	}
+
+	#[test]
+	fn read_after_read_needs_no_barrier() {
+		let mut tracker = tracker(1, 1);
+		tracker.use_image(image(), ImageUsage::SampledFragment);
+		tracker.take_pending();
+		tracker.use_image(image(), ImageUsage::SampledCompute);
+		assert!(!tracker.has_pending());
+		// both readers are remembered for whatever writes next
+		let state = tracker.state(image(), 0, 0).unwrap();
+		assert_eq!(
+			state.stage,
+			vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER
+		);
+		assert_eq!(state.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+	}
+
+	#[test]
+	fn write_after_read_waits_on_every_reader() {
+		let mut tracker = tracker(1, 1);
+		tracker.use_image(image(), ImageUsage::SampledFragment);
+		tracker.use_image(image(), ImageUsage::SampledCompute);
+		tracker.take_pending();
+		tracker.use_image(image(), ImageUsage::StorageCompute);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		let barrier = pending[0];
+		assert_eq!(
+			barrier.src_stage_mask,
+			vk::PipelineStageFlags2::FRAGMENT_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER
+		);
+		// nothing was written, so there's nothing to make available
+		assert_eq!(barrier.src_access_mask, vk::AccessFlags2::NONE);
+		assert_eq!(
+			barrier.old_layout,
+			vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+		);
+		assert_eq!(barrier.new_layout, vk::ImageLayout::GENERAL);
+	}
+
+	#[test]
+	fn write_after_write_in_same_layout_still_barriers() {
+		let mut tracker = tracker(1, 1);
+		tracker.use_image(image(), ImageUsage::StorageCompute);
+		tracker.take_pending();
+		tracker.use_image(image(), ImageUsage::StorageCompute);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].old_layout, pending[0].new_layout);
+		assert_eq!(
+			pending[0].src_access_mask,
+			vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
+		);
+	}
+
+	#[test]
+	fn discard_comes_from_undefined_but_waits_on_stage() {
+		let mut tracker = tracker(1, 1);
+		tracker.use_image(image(), ImageUsage::Present);
+		tracker.take_pending();
+		tracker.discard(image(), vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+		tracker.use_image(image(), ImageUsage::ColorAttachment);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].old_layout, vk::ImageLayout::UNDEFINED);
+		assert_eq!(
+			pending[0].src_stage_mask,
+			vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT
+		);
+		assert_eq!(pending[0].src_access_mask, vk::AccessFlags2::NONE);
+	}
+
+	#[test]
+	fn alias_waits_on_previous_owner() {
+		let mut tracker = tracker(1, 1);
+		let prev = ImageUsage::ColorAttachment.state();
+		tracker.alias(image(), prev);
+		assert_eq!(
+			tracker.state(image(), 0, 0).map(|state| state.layout),
+			Some(vk::ImageLayout::UNDEFINED)
+		);
+		tracker.use_image(image(), ImageUsage::DepthAttachment);
+		let pending = tracker.take_pending();
+		assert_eq!(pending.len(), 1);
+		assert_eq!(pending[0].old_layout, vk::ImageLayout::UNDEFINED);
+		assert_eq!(pending[0].src_stage_mask, prev.stage);
+		// the previous owner wrote, those writes have to finish first
+		assert_eq!(pending[0].src_access_mask, prev.access);
+	}
+
+	#[test]
+	fn unregister_forgets_image() {
+		let mut tracker = tracker(1, 1);
+		assert!(tracker.is_tracked(image()));
+		tracker.unregister(image());
+		assert!(!tracker.is_tracked(image()));
+		assert_eq!(tracker.state(image(), 0, 0), None);
+	}
+}
@@ -0,0 +1,701 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::path::Path;
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{Key, KeyCode, ModifiersState, PhysicalKey, SmolStr};
+
+// touchpads scroll in pixels, wheels in lines. pixels get converted so both use lines
+const PIXELS_PER_LINE: f32 = 20.;
+
+// held / pressed this frame / released this frame
+#[derive(Clone, Debug)]
+struct ButtonSet<T> {
+	held: HashSet<T>,
+	pressed: HashSet<T>,
+	released: HashSet<T>,
+}
+
+impl<T> Default for ButtonSet<T> {
+	fn default() -> ButtonSet<T> {
+		ButtonSet {
+			held: HashSet::new(),
+			pressed: HashSet::new(),
+			released: HashSet::new(),
+		}
+	}
+}
+
+impl<T: Copy + Eq + Hash> ButtonSet<T> {
+	fn press(&mut self, button: T) {
+		// key repeat shows up as more presses, only the first one counts
+		if self.held.insert(button) {
+			self.pressed.insert(button);
+		}
+	}
+
+	fn release(&mut self, button: T) {
+		if self.held.remove(&button) {
+			self.released.insert(button);
+		}
+	}
+
+	fn release_all(&mut self) {
+		self.released.extend(self.held.drain());
+	}
+
+	fn end_frame(&mut self) {
+		self.pressed.clear();
+		self.released.clear();
+	}
+}
+
+// everything the keyboard and mouse did, fed from WindowEvent / DeviceEvent (or the synthetic
+// methods below). the just_* queries and the deltas cover everything since the last end_frame
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+	keys: ButtonSet<KeyCode>,
+	// logical keys follow the layout, keyed by the physical key that produced them so the
+	// release still matches when the modifiers changed in between
+	logical: HashMap<KeyCode, Key>,
+	logical_pressed: Vec<Key>,
+	logical_released: Vec<Key>,
+	mouse: ButtonSet<MouseButton>,
+	cursor: Option<(f64, f64)>,
+	cursor_delta: (f64, f64),
+	// raw device motion, keeps going when the cursor is grabbed or hits the screen edge
+	mouse_motion: (f64, f64),
+	scroll: (f32, f32),
+	modifiers: ModifiersState,
+}
+
+impl InputState {
+	pub fn new() -> InputState {
+		InputState::default()
+	}
+
+	pub fn handle_window_event(&mut self, event: &WindowEvent) {
+		match event {
+			WindowEvent::KeyboardInput { event, .. } => {
+				let PhysicalKey::Code(code) = event.physical_key else {
+					return;
+				};
+				match event.state {
+					ElementState::Pressed => self.press_key(code, Some(event.logical_key.clone())),
+					ElementState::Released => self.release_key(code),
+				}
+			}
+			WindowEvent::MouseInput { state, button, .. } => match state {
+				ElementState::Pressed => self.press_mouse(*button),
+				ElementState::Released => self.release_mouse(*button),
+			},
+			WindowEvent::CursorMoved { position, .. } => self.move_cursor(position.x, position.y),
+			WindowEvent::CursorLeft { .. } => self.cursor = None,
+			WindowEvent::MouseWheel { delta, .. } => match *delta {
+				MouseScrollDelta::LineDelta(x, y) => self.scroll_by(x, y),
+				MouseScrollDelta::PixelDelta(position) => self.scroll_by(
+					position.x as f32 / PIXELS_PER_LINE,
+					position.y as f32 / PIXELS_PER_LINE,
+				),
+			},
+			WindowEvent::ModifiersChanged(modifiers) => self.set_modifiers(modifiers.state()),
+			// releases never arrive for keys let go while unfocused
+			WindowEvent::Focused(false) => self.release_all(),
+			_ => {}
+		}
+	}
+
+	pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+		if let DeviceEvent::MouseMotion { delta } = event {
+			self.move_mouse(delta.0, delta.1);
+		}
+	}
+
+	// clears the just_* state and the deltas, call once whatever reads them has run
+	pub fn end_frame(&mut self) {
+		self.keys.end_frame();
+		self.mouse.end_frame();
+		self.logical_pressed.clear();
+		self.logical_released.clear();
+		self.cursor_delta = (0., 0.);
+		self.mouse_motion = (0., 0.);
+		self.scroll = (0., 0.);
+	}
+
+	// synthetic input, what handle_window_event boils down to. for tests and replays
+	pub fn press_key(&mut self, code: KeyCode, logical: Option<Key>) {
+		if self.keys.held.contains(&code) {
+			return;
+		}
+		self.keys.press(code);
+		if let Some(logical) = logical {
+			self.logical_pressed.push(logical.clone());
+			self.logical.insert(code, logical);
+		}
+	}
+
+	pub fn release_key(&mut self, code: KeyCode) {
+		self.keys.release(code);
+		if let Some(logical) = self.logical.remove(&code) {
+			self.logical_released.push(logical);
+		}
+	}
+
+	pub fn press_mouse(&mut self, button: MouseButton) {
+		self.mouse.press(button);
+	}
+
+	pub fn release_mouse(&mut self, button: MouseButton) {
+		self.mouse.release(button);
+	}
+
+	pub fn move_cursor(&mut self, x: f64, y: f64) {
+		if let Some((old_x, old_y)) = self.cursor {
+			self.cursor_delta.0 += x - old_x;
+			self.cursor_delta.1 += y - old_y;
+		}
+		self.cursor = Some((x, y));
+	}
+
+	pub fn move_mouse(&mut self, dx: f64, dy: f64) {
+		self.mouse_motion.0 += dx;
+		self.mouse_motion.1 += dy;
+	}
+
+	pub fn scroll_by(&mut self, x: f32, y: f32) {
+		self.scroll.0 += x;
+		self.scroll.1 += y;
+	}
+
+	pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+		self.modifiers = modifiers;
+	}
+
+	pub fn release_all(&mut self) {
+		self.keys.release_all();
+		self.mouse.release_all();
+		self.logical_released
+			.extend(self.logical.drain().map(|(_, key)| key));
+	}
+
+	pub fn pressed(&self, code: KeyCode) -> bool {
+		self.keys.held.contains(&code)
+	}
+
+	pub fn just_pressed(&self, code: KeyCode) -> bool {
+		self.keys.pressed.contains(&code)
+	}
+
+	pub fn just_released(&self, code: KeyCode) -> bool {
+		self.keys.released.contains(&code)
+	}
+
+	pub fn logical_pressed(&self, key: &Key) -> bool {
+		self.logical.values().any(|held| held == key)
+	}
+
+	pub fn logical_just_pressed(&self, key: &Key) -> bool {
+		self.logical_pressed.contains(key)
+	}
+
+	pub fn logical_just_released(&self, key: &Key) -> bool {
+		self.logical_released.contains(key)
+	}
+
+	// the character a held key produces with the current layout, e.g. for "?" bindings
+	pub fn char_pressed(&self, c: &str) -> bool {
+		self.logical_pressed(&Key::Character(SmolStr::new(c)))
+	}
+
+	pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+		self.mouse.held.contains(&button)
+	}
+
+	pub fn mouse_just_pressed(&self, button: MouseButton) -> bool {
+		self.mouse.pressed.contains(&button)
+	}
+
+	pub fn mouse_just_released(&self, button: MouseButton) -> bool {
+		self.mouse.released.contains(&button)
+	}
+
+	// None while the cursor is outside the window
+	pub fn cursor_position(&self) -> Option<(f64, f64)> {
+		self.cursor
+	}
+
+	pub fn cursor_delta(&self) -> (f64, f64) {
+		self.cursor_delta
+	}
+
+	pub fn mouse_motion(&self) -> (f64, f64) {
+		self.mouse_motion
+	}
+
+	// in lines, positive y is away from the user
+	pub fn scroll(&self) -> (f32, f32) {
+		self.scroll
+	}
+
+	pub fn modifiers(&self) -> ModifiersState {
+		self.modifiers
+	}
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InputConfigError {
+	#[error("failed to read input config: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("line {line}: {message}")]
+	Parse { line: usize, message: String },
+}
+
+// what an action can be bound to
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Binding {
+	Key(KeyCode),
+	// a character from the current layout
+	Char(SmolStr),
+	Mouse(MouseButton),
+}
+
+impl Binding {
+	// KeyW, Space, char:?, mouse:left, mouse:4
+	pub fn parse(s: &str) -> Option<Binding> {
+		if let Some(c) = s.strip_prefix("char:") {
+			return (!c.is_empty()).then(|| Binding::Char(SmolStr::new(c)));
+		}
+		if let Some(button) = s.strip_prefix("mouse:") {
+			return Some(Binding::Mouse(match button.to_lowercase().as_str() {
+				"left" => MouseButton::Left,
+				"right" => MouseButton::Right,
+				"middle" => MouseButton::Middle,
+				"back" => MouseButton::Back,
+				"forward" => MouseButton::Forward,
+				other => MouseButton::Other(other.parse().ok()?),
+			}));
+		}
+		KEY_CODES
+			.iter()
+			.find(|(name, _)| *name == s)
+			.map(|&(_, code)| Binding::Key(code))
+	}
+
+	fn pressed(&self, input: &InputState) -> bool {
+		match self {
+			Binding::Key(code) => input.pressed(*code),
+			Binding::Char(c) => input.logical_pressed(&Key::Character(c.clone())),
+			Binding::Mouse(button) => input.mouse_pressed(*button),
+		}
+	}
+
+	fn just_pressed(&self, input: &InputState) -> bool {
+		match self {
+			Binding::Key(code) => input.just_pressed(*code),
+			Binding::Char(c) => input.logical_just_pressed(&Key::Character(c.clone())),
+			Binding::Mouse(button) => input.mouse_just_pressed(*button),
+		}
+	}
+
+	fn just_released(&self, input: &InputState) -> bool {
+		match self {
+			Binding::Key(code) => input.just_released(*code),
+			Binding::Char(c) => input.logical_just_released(&Key::Character(c.clone())),
+			Binding::Mouse(button) => input.mouse_just_released(*button),
+		}
+	}
+}
+
+// named actions -> bindings, so gameplay code asks for "jump" instead of Space. the config
+// file is one action per line, bindings comma separated. # starts a comment at the start of a
+// line or after whitespace, so char:# still binds:
+//   move_forward = KeyW, ArrowUp # and the arrows
+//   fire = mouse:left
+//   help = char:?
+//   cheat = char:#
+#[derive(Clone, Debug, Default)]
+pub struct ActionMap {
+	bindings: BTreeMap<String, Vec<Binding>>,
+}
+
+impl ActionMap {
+	pub fn new() -> ActionMap {
+		ActionMap::default()
+	}
+
+	pub fn bind(mut self, action: &str, binding: Binding) -> ActionMap {
+		self.bindings
+			.entry(action.to_owned())
+			.or_default()
+			.push(binding);
+		self
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> Result<ActionMap, InputConfigError> {
+		ActionMap::parse(&std::fs::read_to_string(path)?)
+	}
+
+	pub fn parse(config: &str) -> Result<ActionMap, InputConfigError> {
+		let mut map = ActionMap::new();
+		for (i, line) in config.lines().enumerate() {
+			let error = |message: String| InputConfigError::Parse {
+				line: i + 1,
+				message,
+			};
+			let line = strip_comment(line).trim();
+			if line.is_empty() {
+				continue;
+			}
+			let Some((action, bindings)) = line.split_once('=') else {
+				return Err(error("expected `action = binding, ...`".to_owned()));
+			};
+			let action = action.trim();
+			if action.is_empty() {
+				return Err(error("empty action name".to_owned()));
+			}
+			for binding in bindings.split(',').map(str::trim) {
+				let binding = Binding::parse(binding)
+					.ok_or_else(|| error(format!("unknown binding {:?}", binding)))?;
+				map = map.bind(action, binding);
+			}
+		}
+		Ok(map)
+	}
+
+	pub fn bindings(&self, action: &str) -> &[Binding] {
+		self.bindings.get(action).map_or(&[], Vec::as_slice)
+	}
+
+	pub fn actions(&self) -> impl Iterator<Item = &str> {
+		self.bindings.keys().map(String::as_str)
+	}
+
+	pub fn pressed(&self, input: &InputState, action: &str) -> bool {
+		self.bindings(action).iter().any(|b| b.pressed(input))
+	}
+
+	pub fn just_pressed(&self, input: &InputState, action: &str) -> bool {
+		self.bindings(action).iter().any(|b| b.just_pressed(input))
+	}
+
+	pub fn just_released(&self, input: &InputState, action: &str) -> bool {
+		self.bindings(action).iter().any(|b| b.just_released(input))
+	}
+}
+
+fn strip_comment(line: &str) -> &str {
+	let mut after_space = true;
+	for (idx, c) in line.char_indices() {
+		if c == '#' && after_space {
+			return &line[..idx];
+		}
+		after_space = c.is_whitespace();
+	}
+	line
+}
+
+// key names as they appear in config files, same spelling as KeyCode's variants
+macro_rules! key_codes {
+	($($key:ident),* $(,)?) => {
+		&[$((stringify!($key), KeyCode::$key)),*]
+	};
+}
+
+const KEY_CODES: &[(&str, KeyCode)] = key_codes!(
+	KeyA,
+	KeyB,
+	KeyC,
+	KeyD,
+	KeyE,
+	KeyF,
+	KeyG,
+	KeyH,
+	KeyI,
+	KeyJ,
+	KeyK,
+	KeyL,
+	KeyM,
+	KeyN,
+	KeyO,
+	KeyP,
+	KeyQ,
+	KeyR,
+	KeyS,
+	KeyT,
+	KeyU,
+	KeyV,
+	KeyW,
+	KeyX,
+	KeyY,
+	KeyZ,
+	Digit0,
+	Digit1,
+	Digit2,
+	Digit3,
+	Digit4,
+	Digit5,
+	Digit6,
+	Digit7,
+	Digit8,
+	Digit9,
+	F1,
+	F2,
+	F3,
+	F4,
+	F5,
+	F6,
+	F7,
+	F8,
+	F9,
+	F10,
+	F11,
+	F12,
+	ArrowUp,
+	ArrowDown,
+	ArrowLeft,
+	ArrowRight,
+	Space,
+	Enter,
+	Escape,
+	Tab,
+	Backspace,
+	Delete,
+	Insert,
+	Home,
+	End,
+	PageUp,
+	PageDown,
+	ShiftLeft,
+	ShiftRight,
+	ControlLeft,
+	ControlRight,
+	AltLeft,
+	AltRight,
+	SuperLeft,
+	SuperRight,
+	CapsLock,
+	Backquote,
+	Minus,
+	Equal,
+	BracketLeft,
+	BracketRight,
+	Backslash,
+	Semicolon,
+	Quote,
+	Comma,
+	Period,
+	Slash,
+	Numpad0,
+	Numpad1,
+	Numpad2,
+	Numpad3,
+	Numpad4,
+	Numpad5,
+	Numpad6,
+	Numpad7,
+	Numpad8,
+	Numpad9,
+	NumpadAdd,
+	NumpadSubtract,
+	NumpadMultiply,
+	NumpadDivide,
+	NumpadEnter,
+	NumpadDecimal,
+);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn char_key(c: &str) -> Key {
+		Key::Character(SmolStr::new(c))
+	}
+
+	#[test]
+	fn just_pressed_and_released_last_one_frame() {
+		let mut input = InputState::new();
+		input.press_key(KeyCode::KeyW, None);
+		assert!(input.pressed(KeyCode::KeyW));
+		assert!(input.just_pressed(KeyCode::KeyW));
+		input.end_frame();
+		assert!(input.pressed(KeyCode::KeyW));
+		assert!(!input.just_pressed(KeyCode::KeyW));
+		input.release_key(KeyCode::KeyW);
+		assert!(!input.pressed(KeyCode::KeyW));
+		assert!(input.just_released(KeyCode::KeyW));
+		input.end_frame();
+		assert!(!input.just_released(KeyCode::KeyW));
+	}
+
+	#[test]
+	fn press_and_release_in_one_frame_shows_both_edges() {
+		let mut input = InputState::new();
+		input.press_mouse(MouseButton::Left);
+		input.release_mouse(MouseButton::Left);
+		assert!(!input.mouse_pressed(MouseButton::Left));
+		assert!(input.mouse_just_pressed(MouseButton::Left));
+		assert!(input.mouse_just_released(MouseButton::Left));
+	}
+
+	#[test]
+	fn key_repeat_is_ignored() {
+		let mut input = InputState::new();
+		input.press_key(KeyCode::KeyA, Some(char_key("a")));
+		input.end_frame();
+		input.press_key(KeyCode::KeyA, Some(char_key("a")));
+		assert!(input.pressed(KeyCode::KeyA));
+		assert!(!input.just_pressed(KeyCode::KeyA));
+		assert!(!input.logical_just_pressed(&char_key("a")));
+		// releasing a key that isn't held isn't an edge either
+		input.release_key(KeyCode::KeyB);
+		assert!(!input.just_released(KeyCode::KeyB));
+	}
+
+	#[test]
+	fn focus_loss_releases_everything() {
+		let mut input = InputState::new();
+		input.press_key(KeyCode::ShiftLeft, None);
+		input.press_key(KeyCode::KeyQ, Some(char_key("Q")));
+		input.press_mouse(MouseButton::Right);
+		input.end_frame();
+		input.handle_window_event(&WindowEvent::Focused(false));
+		assert!(!input.pressed(KeyCode::ShiftLeft));
+		assert!(!input.pressed(KeyCode::KeyQ));
+		assert!(!input.mouse_pressed(MouseButton::Right));
+		assert!(!input.logical_pressed(&char_key("Q")));
+		assert!(input.just_released(KeyCode::ShiftLeft));
+		assert!(input.just_released(KeyCode::KeyQ));
+		assert!(input.mouse_just_released(MouseButton::Right));
+		assert!(input.logical_just_released(&char_key("Q")));
+	}
+
+	#[test]
+	fn logical_release_survives_modifier_change() {
+		let mut input = InputState::new();
+		input.set_modifiers(ModifiersState::SHIFT);
+		input.press_key(KeyCode::Digit1, Some(char_key("!")));
+		assert!(input.char_pressed("!"));
+		input.end_frame();
+		// shift let go first, the release of the 1 key would now say "1"
+		input.set_modifiers(ModifiersState::empty());
+		input.release_key(KeyCode::Digit1);
+		assert!(!input.char_pressed("!"));
+		assert!(input.logical_just_released(&char_key("!")));
+		assert!(!input.logical_just_released(&char_key("1")));
+	}
+
+	#[test]
+	fn deltas_reset_each_frame() {
+		let mut input = InputState::new();
+		input.move_cursor(10., 10.);
+		assert_eq!(input.cursor_delta(), (0., 0.));
+		input.move_cursor(13., 6.);
+		input.move_mouse(2., 1.);
+		input.scroll_by(0., 1.);
+		input.scroll_by(0., 2.);
+		assert_eq!(input.cursor_delta(), (3., -4.));
+		assert_eq!(input.mouse_motion(), (2., 1.));
+		assert_eq!(input.scroll(), (0., 3.));
+		input.end_frame();
+		assert_eq!(input.cursor_position(), Some((13., 6.)));
+		assert_eq!(input.cursor_delta(), (0., 0.));
+		assert_eq!(input.mouse_motion(), (0., 0.));
+		assert_eq!(input.scroll(), (0., 0.));
+	}
+
+	#[test]
+	fn parses_bindings() {
+		assert_eq!(Binding::parse("KeyW"), Some(Binding::Key(KeyCode::KeyW)));
+		assert_eq!(Binding::parse("char:?"), Some(Binding::Char("?".into())));
+		assert_eq!(Binding::parse("char:"), None);
+		assert_eq!(
+			Binding::parse("mouse:Left"),
+			Some(Binding::Mouse(MouseButton::Left))
+		);
+		assert_eq!(
+			Binding::parse("mouse:4"),
+			Some(Binding::Mouse(MouseButton::Other(4)))
+		);
+		assert_eq!(Binding::parse("mouse:wheel"), None);
+		assert_eq!(Binding::parse("keyw"), None);
+	}
+
+	#[test]
+	fn parses_action_map() {
+		let map = ActionMap::parse(
+			"# movement\n\
+			 move_forward = KeyW, ArrowUp # and the arrows\n\
+			 \n\
+			 fire=mouse:left\n\
+			 help = char:?\n\
+			 cheat = char:#\n\
+			 fire = Space\n",
+		)
+		.expect("Should have been able to parse action map");
+		assert_eq!(
+			map.actions().collect::<Vec<_>>(),
+			["cheat", "fire", "help", "move_forward"]
+		);
+		assert_eq!(
+			map.bindings("move_forward"),
+			[Binding::Key(KeyCode::KeyW), Binding::Key(KeyCode::ArrowUp)]
+		);
+		assert_eq!(
+			map.bindings("fire"),
+			[
+				Binding::Mouse(MouseButton::Left),
+				Binding::Key(KeyCode::Space)
+			]
+		);
+		assert_eq!(map.bindings("help"), [Binding::Char("?".into())]);
+		assert_eq!(map.bindings("cheat"), [Binding::Char("#".into())]);
+		assert_eq!(map.bindings("missing"), []);
+	}
+
+	#[test]
+	fn action_map_errors_have_line_numbers() {
+		let cases = [
+			("jump = Space\njump Space\n", 2, "expected"),
+			("\n\n = Space", 3, "empty action"),
+			(
+				"# fine\nfire = mouse:wheel",
+				2,
+				"unknown binding \"mouse:wheel\"",
+			),
+			("fire = Space,", 1, "unknown binding \"\""),
+			(
+				"fire = KeyF# no space before the comment",
+				1,
+				"unknown binding",
+			),
+		];
+		for (config, expected_line, expected_message) in cases {
+			match ActionMap::parse(config) {
+				Err(InputConfigError::Parse { line, message }) => {
+					assert_eq!(line, expected_line, "{:?}", config);
+					assert!(
+						message.contains(expected_message),
+						"{:?}: {}",
+						config,
+						message
+					);
+				}
+				other => panic!("{:?} parsed to {:?}", config, other),
+			}
+		}
+	}
+
+	#[test]
+	fn actions_follow_bindings() {
+		let map = ActionMap::new()
+			.bind("jump", Binding::Key(KeyCode::Space))
+			.bind("jump", Binding::Char("j".into()));
+		let mut input = InputState::new();
+		input.press_key(KeyCode::KeyJ, Some(char_key("j")));
+		assert!(map.pressed(&input, "jump"));
+		assert!(map.just_pressed(&input, "jump"));
+		input.end_frame();
+		input.release_key(KeyCode::KeyJ);
+		assert!(!map.pressed(&input, "jump"));
+		assert!(map.just_released(&input, "jump"));
+	}
+}
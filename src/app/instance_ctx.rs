@@ -1,21 +1,51 @@
-use super::{CStringArray, Entry, Instance, common::*, debug_utils, surface, vk};
-use std::ffi::{CStr, CString, c_char, c_void};
+use super::{
+	CStringArray, DebugMessenger, Entry, Instance, ValidationSink, VkConfig, VkError, common::*,
+	surface, vk,
+};
+use std::ffi::{CStr, CString, c_char};
 
 pub struct InstanceContext {
 	pub entry: Entry,
 	pub instance: Instance,
 	pub surface_loader: surface::Instance,
-	#[cfg(feature = "validation")]
-	pub debug_utils_loader: debug_utils::Instance,
-	#[cfg(feature = "validation")]
-	pub debug_messenger: vk::DebugUtilsMessengerEXT,
+	// what the loader supports vs what the instance was created with
+	pub loader_version: u32,
+	pub api_version: u32,
+	// None when validation is off (or the layer isn't installed)
+	pub debug_messenger: Option<DebugMessenger>,
+	// stays empty without a messenger
+	pub validation_sink: ValidationSink,
+	pub strict_validation: bool,
+	// devices need VK_KHR_shader_non_semantic_info for it
+	pub debug_printf: bool,
 }
 
 impl InstanceContext {
-	pub fn new() -> InstanceContext {
-		let entry = Entry::linked();
-		let required_layers = InstanceContext::get_required_layers(&entry);
-		let required_extensions: Vec<*const c_char> = InstanceContext::get_required_extensions();
+	pub fn new(config: &VkConfig) -> Result<InstanceContext, VkError> {
+		InstanceContext::create(config, REQUIRED_INSTANCE_EXTENSIONS)
+	}
+	// no surface extensions, for rendering without a window
+	pub fn headless(config: &VkConfig) -> Result<InstanceContext, VkError> {
+		InstanceContext::create(config, HEADLESS_INSTANCE_EXTENSIONS)
+	}
+	fn create(config: &VkConfig, extensions: &[&CStr]) -> Result<InstanceContext, VkError> {
+		let entry = config.loader.load()?;
+		let mut validation = config.validation.clone();
+		if validation.enabled && !InstanceContext::has_validation_layer(&entry) {
+			log::warn!("Validation requested but the validation layer isn't installed, skipping");
+			validation.enabled = false;
+		}
+		let layer_features = if validation.enabled {
+			validation.layer_features()
+		} else {
+			Vec::new()
+		};
+		let required_layers = InstanceContext::get_required_layers(&entry, validation.enabled);
+		let required_extensions: Vec<*const c_char> = InstanceContext::get_required_extensions(
+			extensions,
+			validation.enabled,
+			!layer_features.is_empty(),
+		);
 		#[cfg(debug_assertions)]
 		{
 			// query all extensions
@@ -31,12 +61,27 @@ impl InstanceContext {
 			println!("");
 		}
 
+		// a 1.0 loader doesn't have vkEnumerateInstanceVersion at all
+		let loader_version = unsafe {
+			entry
+				.try_enumerate_instance_version()
+				.expect("Should have been able to query instance version")
+				.unwrap_or(vk::API_VERSION_1_0)
+		};
+		let api_version = loader_version.min(MAX_API_VERSION);
+		log::info!(
+			"Loader supports vk {}, creating a {} instance",
+			version_string(loader_version),
+			version_string(api_version)
+		);
 		let app_info = vk::ApplicationInfo {
 			application_version: vk::make_api_version(0, 1, 0, 0),
-			api_version: vk::make_api_version(0, 1, 4, 0),
+			api_version,
 			..Default::default()
 		};
-		let create_info = vk::InstanceCreateInfo {
+		let mut validation_features =
+			vk::ValidationFeaturesEXT::default().enabled_validation_features(&layer_features);
+		let mut create_info = vk::InstanceCreateInfo {
 			p_application_info: &app_info,
 			enabled_layer_count: required_layers.len() as u32,
 			pp_enabled_layer_names: required_layers.as_ptr(),
@@ -47,23 +92,39 @@ impl InstanceContext {
 			..Default::default()
 		};
 
-		let instance = unsafe {
-			entry
-				.create_instance(&create_info, None)
-				.expect("Should have been able to create instance")
-		};
-		#[cfg(feature = "validation")]
-		let (debug_loader, debug_messenger) = InstanceContext::setup_debug_messenger(&instance, &entry);
+		if !layer_features.is_empty() {
+			log::info!("Enabling validation features {:?}", layer_features);
+			create_info = create_info.push_next(&mut validation_features);
+		}
+		// the messenger only exists once the instance does, this one covers vkCreateInstance and
+		// vkDestroyInstance with the same callback, sink and filters
+		let validation_sink = ValidationSink::default();
+		let messenger_state = validation
+			.enabled
+			.then(|| DebugMessenger::state(&validation, validation_sink.clone()));
+		let mut instance_messenger_info = messenger_state
+			.as_ref()
+			.map(|state| DebugMessenger::create_info(&validation, state));
+		if let Some(messenger_info) = &mut instance_messenger_info {
+			create_info = create_info.push_next(messenger_info);
+		}
+
+		let instance = unsafe { entry.create_instance(&create_info, None) }
+			.map_err(VkError::InstanceCreation)?;
+		let debug_messenger =
+			messenger_state.map(|state| DebugMessenger::new(&entry, &instance, &validation, state));
 		let surface_loader = surface::Instance::new(&entry, &instance);
-		InstanceContext {
+		Ok(InstanceContext {
 			entry: entry,
 			instance: instance,
 			surface_loader: surface_loader,
-			#[cfg(feature = "validation")]
-			debug_utils_loader: debug_loader,
-			#[cfg(feature = "validation")]
-			debug_messenger: debug_messenger,
-		}
+			loader_version,
+			api_version,
+			debug_messenger,
+			validation_sink,
+			strict_validation: validation.enabled && validation.strict,
+			debug_printf: validation.enabled && validation.debug_printf,
+		})
 	}
 	pub fn entry(&self) -> &Entry {
 		&self.entry
@@ -74,37 +135,54 @@ impl InstanceContext {
 	pub fn surface_loader(&self) -> &surface::Instance {
 		&self.surface_loader
 	}
+	// strict mode: panic with any validation error since the last check. the callback can't
+	// panic itself, so this runs after each frame instead
+	pub fn check_validation(&self) {
+		if !self.strict_validation {
+			return;
+		}
+		if let Some(error) = self.validation_sink.take_new_errors().first() {
+			panic!("Validation error in strict mode: {}", error);
+		}
+	}
+	// device has to be gone before this
+	pub fn cleanup(&self) {
+		if let Some(debug_messenger) = &self.debug_messenger {
+			debug_messenger.cleanup();
+		}
+		unsafe {
+			self.instance.destroy_instance(None);
+		}
+		// whatever vkDestroyInstance reported (leaked objects etc.)
+		self.check_validation();
+	}
 
-	fn setup_debug_messenger(
-		instance: &Instance,
-		entry: &Entry,
-	) -> (debug_utils::Instance, vk::DebugUtilsMessengerEXT) {
-		let debug_utils_loader = debug_utils::Instance::new(entry, instance);
-		let severity_flags = vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-			| vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-			| vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
-		let message_type_flags = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-			| vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-			| vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
-		let messenger_create_info = vk::DebugUtilsMessengerCreateInfoEXT {
-			message_severity: severity_flags,
-			message_type: message_type_flags,
-			pfn_user_callback: Some(InstanceContext::debug_callback),
-			..Default::default()
-		};
-		let messenger = unsafe {
-			debug_utils_loader
-				.create_debug_utils_messenger(&messenger_create_info, None)
-				.expect("Should have been able to create debug messenger")
+	fn has_validation_layer(entry: &Entry) -> bool {
+		let layer_properties = unsafe {
+			entry
+				.enumerate_instance_layer_properties()
+				.expect("Should have been able to get layer properties from entry")
 		};
-		(debug_utils_loader, messenger)
+		VALIDATION_LAYERS.iter().all(|layer| {
+			layer_properties
+				.iter()
+				.any(|property| property.layer_name_as_c_str().unwrap().to_str() == Ok(*layer))
+		})
 	}
 
-	fn get_required_extensions() -> Vec<*const c_char> {
-		let mut extension_names = REQUIRED_INSTANCE_EXTENSIONS.to_vec();
-		if ENABLE_VALIDATION_LAYERS {
+	fn get_required_extensions(
+		extensions: &[&CStr],
+		validation: bool,
+		layer_features: bool,
+	) -> Vec<*const c_char> {
+		let mut extension_names = extensions.to_vec();
+		if validation {
 			extension_names.push(vk::EXT_DEBUG_UTILS_NAME);
 		}
+		// provided by the validation layer itself
+		if layer_features {
+			extension_names.push(vk::EXT_VALIDATION_FEATURES_NAME);
+		}
 		log::info!("{} required extensions:", extension_names.len());
 		extension_names
 			.iter()
@@ -113,7 +191,7 @@ impl InstanceContext {
 		extension_names.iter().map(|cstr| cstr.as_ptr()).collect()
 	}
 
-	fn get_required_layers(entry: &Entry) -> CStringArray {
+	fn get_required_layers(entry: &Entry, validation: bool) -> CStringArray {
 		// query layers
 		let layer_properties = unsafe {
 			entry
@@ -131,7 +209,7 @@ impl InstanceContext {
 
 		// make required layers
 		let mut required_layers: Vec<&str> = Vec::new();
-		if ENABLE_VALIDATION_LAYERS {
+		if validation {
 			required_layers.extend(VALIDATION_LAYERS.iter());
 		}
 		if required_layers.iter().any(|required_layer| {
@@ -153,29 +231,4 @@ impl InstanceContext {
 			.collect();
 		CStringArray::new(required_layer_names, required_layer_names_ptrs)
 	}
-
-	pub unsafe extern "system" fn debug_callback(
-		msg_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
-		msg_type: vk::DebugUtilsMessageTypeFlagsEXT,
-		p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-		_p_user_data: *mut c_void,
-	) -> vk::Bool32 {
-		let callback_data = unsafe { *p_callback_data };
-		let msg = unsafe { CStr::from_ptr(callback_data.p_message) }
-			.to_str()
-			.expect("Should have been able to parse message");
-		match msg_severity {
-			vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
-				log::error!("-- Validation layer -- [{:?}]: {}", msg_type, msg);
-			}
-			vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
-				log::warn!("-- Validation layer -- [{:?}]: {}", msg_type, msg);
-			}
-			vk::DebugUtilsMessageSeverityFlagsEXT::INFO => {
-				log::info!("-- Validation layer -- [{:?}], {}", msg_type, msg);
-			}
-			_ => {}
-		}
-		vk::FALSE
-	}
 }
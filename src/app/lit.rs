@@ -0,0 +1,170 @@
+use super::{
+	Device, DeviceContext, Mesh, Obj, ObjMaterial, PipelineContext, PipelineDesc,
+	SCENE_DEPTH_FORMAT, Vertex, vk,
+};
+use cgmath::{Matrix, Matrix4, SquareMatrix};
+
+// blinn-phong colors, what the lit pipeline shades a mesh with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LitMaterial {
+	pub diffuse: [f32; 3],
+	pub specular: [f32; 3],
+	pub shininess: f32,
+}
+
+impl Default for LitMaterial {
+	fn default() -> LitMaterial {
+		LitMaterial {
+			diffuse: [0.8; 3],
+			specular: [0.; 3],
+			shininess: 1.,
+		}
+	}
+}
+
+impl From<&ObjMaterial> for LitMaterial {
+	fn from(material: &ObjMaterial) -> LitMaterial {
+		LitMaterial {
+			diffuse: material.diffuse,
+			specular: material.specular,
+			shininess: material.shininess,
+		}
+	}
+}
+
+// push constants for both stages, exactly the guaranteed 128 bytes
+#[repr(C)]
+struct LitConstants {
+	model: [[f32; 4]; 4],
+	// columns of the inverse transpose of model's upper 3x3. the w of each column is free, so
+	// they carry the specular color
+	normal: [[f32; 4]; 3],
+	// rgb diffuse, a shininess
+	diffuse: [f32; 4],
+}
+
+// meshes and their materials, ready to draw with a LitRenderer
+pub struct LitModel {
+	pub parts: Vec<(Mesh, LitMaterial)>,
+}
+
+impl LitModel {
+	// one part per obj mesh, meshes without a material get LitMaterial::default
+	pub fn from_obj(device_ctx: &DeviceContext, obj: &Obj) -> LitModel {
+		let parts = obj
+			.meshes
+			.iter()
+			.filter(|mesh| !mesh.data.indices.is_empty())
+			.enumerate()
+			.map(|(i, obj_mesh)| {
+				let mesh = Mesh::new(device_ctx, &obj_mesh.data);
+				let name = obj_mesh.name.as_deref().unwrap_or("obj mesh");
+				mesh.set_name(device_ctx, &format!("{} {}", name, i));
+				let material = obj_mesh
+					.material
+					.map_or_else(LitMaterial::default, |m| (&obj.materials[m]).into());
+				(mesh, material)
+			})
+			.collect();
+		LitModel { parts }
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		for (mesh, _) in &self.parts {
+			mesh.cleanup(device);
+		}
+	}
+}
+
+// untextured forward pipeline: one directional light, blinn-phong. set 0 is the camera
+// (VkSwap's layout), everything else comes in push constants
+pub struct LitRenderer {
+	pub pipeline: PipelineContext,
+}
+
+impl LitRenderer {
+	// `shader_code` is spirv with vertMain and fragMain, see shaders/lit.slang
+	pub fn new(
+		device_ctx: &DeviceContext,
+		shader_code: &[u8],
+		color_format: vk::Format,
+		camera_set_layout: vk::DescriptorSetLayout,
+	) -> LitRenderer {
+		let set_layouts = [camera_set_layout];
+		let push_constants = [vk::PushConstantRange {
+			stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+			offset: 0,
+			size: size_of::<LitConstants>() as u32,
+		}];
+		let bindings = [Vertex::binding_description()];
+		// position and normal only
+		let attributes = &Vertex::attribute_descriptions()[..2];
+		let desc = PipelineDesc::new(color_format)
+			.with_depth(SCENE_DEPTH_FORMAT, true)
+			.with_set_layouts(&set_layouts)
+			.with_push_constants(&push_constants)
+			.with_vertex_input(&bindings, attributes);
+
+		LitRenderer {
+			pipeline: PipelineContext::with_desc(device_ctx, shader_code, &desc, "lit pipeline"),
+		}
+	}
+
+	// inside a pass with a color and a SCENE_DEPTH_FORMAT depth attachment
+	pub fn draw(
+		&self,
+		device: &Device,
+		cmd_buff: vk::CommandBuffer,
+		model: &LitModel,
+		transform: Matrix4<f32>,
+		camera_set: vk::DescriptorSet,
+	) {
+		let layout = self.pipeline.pipeline_layout;
+		let normal = transform.invert().unwrap_or(transform).transpose();
+		unsafe {
+			device.cmd_bind_pipeline(
+				cmd_buff,
+				vk::PipelineBindPoint::GRAPHICS,
+				self.pipeline.graphics_pipeline,
+			);
+			device.cmd_bind_descriptor_sets(
+				cmd_buff,
+				vk::PipelineBindPoint::GRAPHICS,
+				layout,
+				0,
+				&[camera_set],
+				&[],
+			);
+		}
+		for (mesh, material) in &model.parts {
+			let [sr, sg, sb] = material.specular;
+			let [dr, dg, db] = material.diffuse;
+			let constants = LitConstants {
+				model: transform.into(),
+				normal: [
+					normal.x.truncate().extend(sr).into(),
+					normal.y.truncate().extend(sg).into(),
+					normal.z.truncate().extend(sb).into(),
+				],
+				diffuse: [dr, dg, db, material.shininess],
+			};
+			unsafe {
+				device.cmd_push_constants(
+					cmd_buff,
+					layout,
+					vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+					0,
+					std::slice::from_raw_parts(
+						&constants as *const LitConstants as *const u8,
+						size_of::<LitConstants>(),
+					),
+				);
+			}
+			mesh.draw(device, cmd_buff);
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		self.pipeline.cleanup(device);
+	}
+}
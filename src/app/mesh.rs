@@ -0,0 +1,208 @@
+use super::{Buffer, Device, DeviceContext, vk};
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use std::mem::offset_of;
+
+// the one vertex layout every mesh pipeline takes, loaders fill in whatever the file lacks
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Vertex {
+	pub position: [f32; 3],
+	pub normal: [f32; 3],
+	// xyz tangent, w the bitangent sign (glTF convention)
+	pub tangent: [f32; 4],
+	pub uv: [f32; 2],
+}
+
+impl Vertex {
+	pub fn binding_description() -> vk::VertexInputBindingDescription {
+		vk::VertexInputBindingDescription {
+			binding: 0,
+			stride: size_of::<Vertex>() as u32,
+			input_rate: vk::VertexInputRate::VERTEX,
+		}
+	}
+
+	// locations 0..4: position, normal, tangent, uv
+	pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+		let attribute = |location, format, offset: usize| vk::VertexInputAttributeDescription {
+			location,
+			binding: 0,
+			format,
+			offset: offset as u32,
+		};
+		[
+			attribute(
+				0,
+				vk::Format::R32G32B32_SFLOAT,
+				offset_of!(Vertex, position),
+			),
+			attribute(1, vk::Format::R32G32B32_SFLOAT, offset_of!(Vertex, normal)),
+			attribute(
+				2,
+				vk::Format::R32G32B32A32_SFLOAT,
+				offset_of!(Vertex, tangent),
+			),
+			attribute(3, vk::Format::R32G32_SFLOAT, offset_of!(Vertex, uv)),
+		]
+	}
+}
+
+// cpu side indexed triangle list, what loaders produce and Mesh uploads
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MeshData {
+	pub vertices: Vec<Vertex>,
+	pub indices: Vec<u32>,
+}
+
+impl MeshData {
+	// area weighted smooth normals, overwrites whatever was there
+	pub fn generate_normals(&mut self) {
+		let mut normals = vec![Vector3::zero(); self.vertices.len()];
+		for tri in self.indices.chunks_exact(3) {
+			let [a, b, c] =
+				[0, 1, 2].map(|i| Vector3::from(self.vertices[tri[i] as usize].position));
+			// not normalized, so bigger triangles count for more
+			let normal = (b - a).cross(c - a);
+			for &i in tri {
+				normals[i as usize] += normal;
+			}
+		}
+		for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+			vertex.normal = if normal.magnitude2() > 0. {
+				normal.normalize().into()
+			} else {
+				[0., 1., 0.]
+			};
+		}
+	}
+
+	// per triangle uv derivatives accumulated per vertex, then made orthogonal to the normal.
+	// needs normals and uvs, degenerate uvs fall back to any vector orthogonal to the normal
+	pub fn generate_tangents(&mut self) {
+		let len = self.vertices.len();
+		let mut tangents = vec![Vector3::zero(); len];
+		let mut bitangents = vec![Vector3::zero(); len];
+		for tri in self.indices.chunks_exact(3) {
+			let [v0, v1, v2] = [0, 1, 2].map(|i| &self.vertices[tri[i] as usize]);
+			let [p0, p1, p2] = [v0, v1, v2].map(|v| Vector3::from(v.position));
+			let [uv0, uv1, uv2] = [v0, v1, v2].map(|v| Vector2::from(v.uv));
+			let (e1, e2) = (p1 - p0, p2 - p0);
+			let (d1, d2) = (uv1 - uv0, uv2 - uv0);
+			let det = d1.x * d2.y - d2.x * d1.y;
+			if det.abs() < f32::EPSILON {
+				continue;
+			}
+			let tangent = (e1 * d2.y - e2 * d1.y) / det;
+			let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+			for &i in tri {
+				tangents[i as usize] += tangent;
+				bitangents[i as usize] += bitangent;
+			}
+		}
+		for (i, vertex) in self.vertices.iter_mut().enumerate() {
+			let normal = Vector3::from(vertex.normal);
+			let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+			if tangent.magnitude2() < f32::EPSILON {
+				// whichever axis is least parallel to the normal
+				let axis = if normal.x.abs() < 0.9 {
+					Vector3::unit_x()
+				} else {
+					Vector3::unit_y()
+				};
+				tangent = axis - normal * normal.dot(axis);
+			}
+			let tangent = tangent.normalize();
+			let sign = if normal.cross(tangent).dot(bitangents[i]) < 0. {
+				-1.
+			} else {
+				1.
+			};
+			vertex.tangent = [tangent.x, tangent.y, tangent.z, sign];
+		}
+	}
+
+	// axis aligned (min, max) of the positions, None for an empty mesh
+	pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+		let first = self.vertices.first()?.position;
+		Some(self.vertices.iter().fold((first, first), |(min, max), v| {
+			(
+				[0, 1, 2].map(|i| min[i].min(v.position[i])),
+				[0, 1, 2].map(|i| max[i].max(v.position[i])),
+			)
+		}))
+	}
+
+	pub fn vertex_bytes(&self) -> &[u8] {
+		// Vertex is repr(C) f32s only, no padding
+		unsafe {
+			std::slice::from_raw_parts(
+				self.vertices.as_ptr() as *const u8,
+				std::mem::size_of_val(self.vertices.as_slice()),
+			)
+		}
+	}
+
+	pub fn index_bytes(&self) -> &[u8] {
+		unsafe {
+			std::slice::from_raw_parts(
+				self.indices.as_ptr() as *const u8,
+				std::mem::size_of_val(self.indices.as_slice()),
+			)
+		}
+	}
+}
+
+// MeshData uploaded into device local vertex/index buffers
+pub struct Mesh {
+	pub vertex_buffer: Buffer,
+	pub index_buffer: Buffer,
+	pub index_count: u32,
+}
+
+impl Mesh {
+	pub fn new(device_ctx: &DeviceContext, data: &MeshData) -> Mesh {
+		assert!(
+			!data.vertices.is_empty() && !data.indices.is_empty(),
+			"Should not upload an empty mesh"
+		);
+		Mesh {
+			vertex_buffer: Buffer::device_local(
+				device_ctx,
+				data.vertex_bytes(),
+				vk::BufferUsageFlags::VERTEX_BUFFER,
+			),
+			index_buffer: Buffer::device_local(
+				device_ctx,
+				data.index_bytes(),
+				vk::BufferUsageFlags::INDEX_BUFFER,
+			),
+			index_count: data.indices.len() as u32,
+		}
+	}
+
+	pub fn set_name(&self, device_ctx: &DeviceContext, name: &str) {
+		self.vertex_buffer
+			.set_name(device_ctx, &format!("{} vertices", name));
+		self.index_buffer
+			.set_name(device_ctx, &format!("{} indices", name));
+	}
+
+	// binds both buffers and draws every index, pipeline and descriptors are up to the caller
+	pub fn draw(&self, device: &Device, cmd_buff: vk::CommandBuffer) {
+		unsafe {
+			device.cmd_bind_vertex_buffers(cmd_buff, 0, &[self.vertex_buffer.buffer], &[0]);
+			device.cmd_bind_index_buffer(
+				cmd_buff,
+				self.index_buffer.buffer,
+				0,
+				vk::IndexType::UINT32,
+			);
+			device.cmd_draw_indexed(cmd_buff, self.index_count, 1, 0, 0, 0);
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		self.vertex_buffer.cleanup(device);
+		self.index_buffer.cleanup(device);
+	}
+}
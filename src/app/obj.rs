@@ -0,0 +1,677 @@
+use super::{MeshData, Vertex};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ObjError {
+	#[error("failed to read obj file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("{file} line {line}: {message}")]
+	Parse {
+		file: String,
+		line: usize,
+		message: String,
+	},
+}
+
+// one newmtl block. only the classic phong statements are read, pbr extensions and map
+// options are skipped
+#[derive(Clone, PartialEq, Debug)]
+pub struct ObjMaterial {
+	pub name: String,
+	// Ka, Kd, Ks, Ke
+	pub ambient: [f32; 3],
+	pub diffuse: [f32; 3],
+	pub specular: [f32; 3],
+	pub emissive: [f32; 3],
+	// Ns
+	pub shininess: f32,
+	// d (or 1 - Tr), 1 is opaque
+	pub dissolve: f32,
+	pub illum: u32,
+	// map_Kd and map_Bump/bump/norm, relative to the mtl file unless loaded through Obj::load
+	pub diffuse_map: Option<PathBuf>,
+	pub normal_map: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+	pub fn new(name: impl Into<String>) -> ObjMaterial {
+		ObjMaterial {
+			name: name.into(),
+			ambient: [0.; 3],
+			diffuse: [0.8; 3],
+			specular: [0.; 3],
+			emissive: [0.; 3],
+			shininess: 1.,
+			dissolve: 1.,
+			illum: 2,
+			diffuse_map: None,
+			normal_map: None,
+		}
+	}
+}
+
+// the faces of one object/group drawn with one material, as an indexed triangle list
+#[derive(Clone, PartialEq, Debug)]
+pub struct ObjMesh {
+	// from the last `o` or `g` statement before the faces
+	pub name: Option<String>,
+	// index into Obj::materials, None for no usemtl or one the mtl files don't define
+	pub material: Option<usize>,
+	pub data: MeshData,
+}
+
+// a parsed Wavefront obj. faces are fan triangulated, every distinct v/vt/vn triple becomes one
+// vertex, and meshes whose faces don't all carry normals get smooth generated ones
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Obj {
+	pub meshes: Vec<ObjMesh>,
+	pub materials: Vec<ObjMaterial>,
+}
+
+impl Obj {
+	// mtllib files are looked up next to the obj, a missing one is only a warning
+	pub fn load(path: impl AsRef<Path>) -> Result<Obj, ObjError> {
+		let path = path.as_ref();
+		let dir = path.parent().unwrap_or(Path::new(""));
+		let text = std::fs::read_to_string(path)?;
+		Obj::parse_with(&text, &path.display().to_string(), |mtl| {
+			let mtl_path = dir.join(mtl);
+			let text = match std::fs::read_to_string(&mtl_path) {
+				Ok(text) => text,
+				Err(e) => {
+					log::warn!("Skipping mtllib {}: {}", mtl_path.display(), e);
+					return Ok(Vec::new());
+				}
+			};
+			let mut materials = Obj::parse_mtl(&text, &mtl_path.display().to_string())?;
+			let mtl_dir = mtl_path.parent().unwrap_or(Path::new(""));
+			for material in &mut materials {
+				for map in [&mut material.diffuse_map, &mut material.normal_map]
+					.into_iter()
+					.flatten()
+				{
+					*map = mtl_dir.join(&*map);
+				}
+			}
+			Ok(materials)
+		})
+	}
+
+	// mtllib statements are ignored, usemtl names come back as unresolved (material None)
+	pub fn parse(text: &str) -> Result<Obj, ObjError> {
+		Obj::parse_with(text, "<obj>", |_| Ok(Vec::new()))
+	}
+
+	// `file` only names the source in errors
+	pub fn parse_mtl(text: &str, file: &str) -> Result<Vec<ObjMaterial>, ObjError> {
+		let mut materials: Vec<ObjMaterial> = Vec::new();
+		for (i, line) in text.lines().enumerate() {
+			let error = |message: String| ObjError::Parse {
+				file: file.to_owned(),
+				line: i + 1,
+				message,
+			};
+			let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+			let Some(keyword) = tokens.next() else {
+				continue;
+			};
+			let args: Vec<&str> = tokens.collect();
+			if keyword == "newmtl" {
+				let name = args.join(" ");
+				if name.is_empty() {
+					return Err(error("newmtl without a name".to_owned()));
+				}
+				materials.push(ObjMaterial::new(name));
+				continue;
+			}
+			let Some(material) = materials.last_mut() else {
+				return Err(error(format!("{} before any newmtl", keyword)));
+			};
+			match keyword {
+				"Ka" => material.ambient = color(&args).map_err(error)?,
+				"Kd" => material.diffuse = color(&args).map_err(error)?,
+				"Ks" => material.specular = color(&args).map_err(error)?,
+				"Ke" => material.emissive = color(&args).map_err(error)?,
+				"Ns" => material.shininess = single(&args).map_err(error)?,
+				"d" => material.dissolve = dissolve(&args).map_err(error)?,
+				"Tr" => material.dissolve = 1. - single(&args).map_err(error)?,
+				"illum" => {
+					material.illum = match args.as_slice() {
+						[illum] => illum.parse().ok(),
+						_ => None,
+					}
+					.ok_or_else(|| error(format!("invalid illum {:?}", args.join(" "))))?
+				}
+				"map_Kd" => material.diffuse_map = Some(map_path(&args).map_err(error)?),
+				"map_Bump" | "map_bump" | "bump" | "norm" => {
+					material.normal_map = Some(map_path(&args).map_err(error)?)
+				}
+				// Tf, Ni, sharpness, the other maps and the pbr extension
+				_ => {}
+			}
+		}
+		Ok(materials)
+	}
+
+	fn parse_with(
+		text: &str,
+		file: &str,
+		mut load_mtl: impl FnMut(&str) -> Result<Vec<ObjMaterial>, ObjError>,
+	) -> Result<Obj, ObjError> {
+		let mut positions: Vec<[f32; 3]> = Vec::new();
+		let mut uvs: Vec<[f32; 2]> = Vec::new();
+		let mut normals: Vec<[f32; 3]> = Vec::new();
+		let mut materials = Vec::new();
+		let mut groups: Vec<Group> = Vec::new();
+		// (name, usemtl) -> groups index, so a material switching back and forth stays one mesh
+		let mut group_indices: HashMap<(Option<String>, Option<String>), usize> = HashMap::new();
+		let mut name = None;
+		let mut material = None;
+
+		for (i, line) in text.lines().enumerate() {
+			let error = |message: String| ObjError::Parse {
+				file: file.to_owned(),
+				line: i + 1,
+				message,
+			};
+			let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+			let Some(keyword) = tokens.next() else {
+				continue;
+			};
+			let args: Vec<&str> = tokens.collect();
+			match keyword {
+				"v" => {
+					// xyz, optionally w or an rgb vertex color, neither of which we keep
+					if !(3..=7).contains(&args.len()) {
+						return Err(error(format!("v takes 3 to 7 numbers, got {}", args.len())));
+					}
+					let v = floats(&args).map_err(error)?;
+					positions.push([v[0], v[1], v[2]]);
+				}
+				"vt" => {
+					if !(1..=3).contains(&args.len()) {
+						return Err(error(format!(
+							"vt takes 1 to 3 numbers, got {}",
+							args.len()
+						)));
+					}
+					let vt = floats(&args).map_err(error)?;
+					// obj puts v = 0 at the bottom of the image, vulkan at the top
+					uvs.push([vt[0], 1. - vt.get(1).copied().unwrap_or(0.)]);
+				}
+				"vn" => {
+					if args.len() != 3 {
+						return Err(error(format!("vn takes 3 numbers, got {}", args.len())));
+					}
+					let vn = floats(&args).map_err(error)?;
+					normals.push([vn[0], vn[1], vn[2]]);
+				}
+				"f" => {
+					if args.len() < 3 {
+						return Err(error(format!(
+							"a face needs at least 3 vertices, got {}",
+							args.len()
+						)));
+					}
+					let corners = args
+						.iter()
+						.map(|corner| {
+							parse_corner(corner, positions.len(), uvs.len(), normals.len())
+						})
+						.collect::<Result<Vec<_>, _>>()
+						.map_err(error)?;
+					let key = (name.clone(), material.clone());
+					let group = *group_indices.entry(key).or_insert_with(|| {
+						groups.push(Group::new(name.clone(), material.clone()));
+						groups.len() - 1
+					});
+					let group = &mut groups[group];
+					let indices: Vec<u32> = corners
+						.iter()
+						.map(|&corner| group.vertex(corner, &positions, &uvs, &normals))
+						.collect();
+					for j in 1..indices.len() - 1 {
+						group
+							.data
+							.indices
+							.extend([indices[0], indices[j], indices[j + 1]]);
+					}
+				}
+				"o" | "g" => name = (!args.is_empty()).then(|| args.join(" ")),
+				"usemtl" => {
+					if args.is_empty() {
+						return Err(error("usemtl without a name".to_owned()));
+					}
+					material = Some(args.join(" "));
+				}
+				"mtllib" => {
+					if args.is_empty() {
+						return Err(error("mtllib without a file".to_owned()));
+					}
+					// file names with spaces are ambiguous, the common exporters write one
+					// file per statement anyway
+					for mtl in args {
+						materials.extend(load_mtl(mtl)?);
+					}
+				}
+				// smoothing groups, lines, points and free-form geometry
+				_ => {}
+			}
+		}
+
+		let mut warned = Vec::new();
+		let meshes = groups
+			.into_iter()
+			.map(|group| {
+				let material = group.material.and_then(|usemtl| {
+					let found = materials
+						.iter()
+						.position(|m: &ObjMaterial| m.name == usemtl);
+					if found.is_none() && !warned.contains(&usemtl) {
+						log::warn!("{}: usemtl {:?} is not in any mtllib", file, usemtl);
+						warned.push(usemtl);
+					}
+					found
+				});
+				let mut data = group.data;
+				if group.missing_normals {
+					data.generate_normals();
+				}
+				data.generate_tangents();
+				ObjMesh {
+					name: group.name,
+					material,
+					data,
+				}
+			})
+			.collect();
+		Ok(Obj { meshes, materials })
+	}
+
+	// axis aligned (min, max) over every mesh, None if there are no faces
+	pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+		self.meshes
+			.iter()
+			.filter_map(|mesh| mesh.data.bounds())
+			.reduce(|(min, max), (mesh_min, mesh_max)| {
+				(
+					[0, 1, 2].map(|i| min[i].min(mesh_min[i])),
+					[0, 1, 2].map(|i| max[i].max(mesh_max[i])),
+				)
+			})
+	}
+}
+
+// zero based (position, uv, normal) of one face corner
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Group {
+	name: Option<String>,
+	material: Option<String>,
+	data: MeshData,
+	vertices: HashMap<Corner, u32>,
+	missing_normals: bool,
+}
+
+impl Group {
+	fn new(name: Option<String>, material: Option<String>) -> Group {
+		Group {
+			name,
+			material,
+			data: MeshData::default(),
+			vertices: HashMap::new(),
+			missing_normals: false,
+		}
+	}
+
+	// index of the vertex for this corner, adding it the first time it's seen
+	fn vertex(
+		&mut self,
+		corner: Corner,
+		positions: &[[f32; 3]],
+		uvs: &[[f32; 2]],
+		normals: &[[f32; 3]],
+	) -> u32 {
+		let (position, uv, normal) = corner;
+		self.missing_normals |= normal.is_none();
+		*self.vertices.entry(corner).or_insert_with(|| {
+			self.data.vertices.push(Vertex {
+				position: positions[position],
+				normal: normal.map_or([0.; 3], |n| normals[n]),
+				uv: uv.map_or([0.; 2], |uv| uvs[uv]),
+				..Default::default()
+			});
+			(self.data.vertices.len() - 1) as u32
+		})
+	}
+}
+
+// v, v/vt, v//vn or v/vt/vn
+fn parse_corner(
+	corner: &str,
+	position_count: usize,
+	uv_count: usize,
+	normal_count: usize,
+) -> Result<Corner, String> {
+	let mut parts = corner.split('/');
+	let position = match parts.next() {
+		Some(v) if !v.is_empty() => resolve_index(v, position_count, "position")?,
+		_ => return Err(format!("face vertex {:?} has no position", corner)),
+	};
+	let uv = match parts.next() {
+		Some(vt) if !vt.is_empty() => Some(resolve_index(vt, uv_count, "texture coordinate")?),
+		_ => None,
+	};
+	let normal = match parts.next() {
+		Some(vn) if !vn.is_empty() => Some(resolve_index(vn, normal_count, "normal")?),
+		_ => None,
+	};
+	if parts.next().is_some() {
+		return Err(format!("face vertex {:?} has too many parts", corner));
+	}
+	Ok((position, uv, normal))
+}
+
+// 1 based, negative counts back from the last one defined so far
+fn resolve_index(index: &str, count: usize, what: &str) -> Result<usize, String> {
+	let parsed: i64 = index
+		.parse()
+		.map_err(|_| format!("invalid {} index {:?}", what, index))?;
+	let resolved = if parsed > 0 {
+		parsed - 1
+	} else {
+		count as i64 + parsed
+	};
+	if parsed == 0 || resolved < 0 || resolved >= count as i64 {
+		return Err(format!(
+			"{} index {} out of range, {} defined so far",
+			what, parsed, count
+		));
+	}
+	Ok(resolved as usize)
+}
+
+fn floats(args: &[&str]) -> Result<Vec<f32>, String> {
+	args.iter()
+		.map(|arg| {
+			arg.parse::<f32>()
+				.ok()
+				.filter(|n| n.is_finite())
+				.ok_or_else(|| format!("invalid number {:?}", arg))
+		})
+		.collect()
+}
+
+fn single(args: &[&str]) -> Result<f32, String> {
+	match floats(args)?.as_slice() {
+		[n] => Ok(*n),
+		_ => Err(format!("expected one number, got {}", args.len())),
+	}
+}
+
+// `r g b`, or a single value for grey. spectral and xyz colors aren't supported
+fn color(args: &[&str]) -> Result<[f32; 3], String> {
+	if let Some(&kind @ ("spectral" | "xyz")) = args.first() {
+		return Err(format!("{} colors are not supported", kind));
+	}
+	match floats(args)?.as_slice() {
+		[grey] => Ok([*grey; 3]),
+		[r, g, b] => Ok([*r, *g, *b]),
+		_ => Err(format!("expected 1 or 3 color values, got {}", args.len())),
+	}
+}
+
+// `d 0.5` or `d -halo 0.5`, the halo is ignored
+fn dissolve(args: &[&str]) -> Result<f32, String> {
+	match args {
+		["-halo", rest @ ..] => single(rest),
+		_ => single(args),
+	}
+}
+
+// the file name is the last token, whatever options come before it
+fn map_path(args: &[&str]) -> Result<PathBuf, String> {
+	args.last()
+		.map(PathBuf::from)
+		.ok_or_else(|| "texture map without a file".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+	fn parse(text: &str) -> Obj {
+		Obj::parse(text).expect("Should have been able to parse obj")
+	}
+
+	// (line, message) of a parse error
+	fn parse_error(result: Result<impl std::fmt::Debug, ObjError>) -> (usize, String) {
+		match result {
+			Err(ObjError::Parse { line, message, .. }) => (line, message),
+			other => panic!("expected a parse error, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn fan_triangulates_polygons() {
+		let obj = parse(&format!("{}f 1 2 3 4\n", QUAD));
+		assert_eq!(obj.meshes.len(), 1);
+		let data = &obj.meshes[0].data;
+		assert_eq!(data.vertices.len(), 4);
+		assert_eq!(data.indices, [0, 1, 2, 0, 2, 3]);
+
+		let obj = parse("v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5");
+		assert_eq!(obj.meshes[0].data.indices, [0, 1, 2, 0, 2, 3, 0, 3, 4]);
+	}
+
+	#[test]
+	fn dedups_identical_corners() {
+		let obj = parse(&format!("{}f 1 2 3\nf 1 3 4\n", QUAD));
+		let data = &obj.meshes[0].data;
+		assert_eq!(data.vertices.len(), 4);
+		assert_eq!(data.indices, [0, 1, 2, 0, 2, 3]);
+
+		// same position, different uv: two vertices
+		let obj = parse(&format!(
+			"{}vt 0 0\nvt 1 1\nf 1/1 2/1 3/1\nf 1/2 3/1 4/1\n",
+			QUAD
+		));
+		let data = &obj.meshes[0].data;
+		assert_eq!(data.vertices.len(), 5);
+		assert_eq!(data.indices, [0, 1, 2, 3, 2, 4]);
+		// v flipped for vulkan
+		assert_eq!(data.vertices[0].uv, [0., 1.]);
+		assert_eq!(data.vertices[3].uv, [1., 0.]);
+	}
+
+	#[test]
+	fn resolves_negative_indices() {
+		let relative = parse(&format!("{}vn 0 0 1\nf -4//-1 -3//-1 -2//-1\n", QUAD));
+		let absolute = parse(&format!("{}vn 0 0 1\nf 1//1 2//1 3//1\n", QUAD));
+		assert_eq!(relative, absolute);
+		// relative to what's defined so far, not the whole file
+		let obj = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\n");
+		assert_eq!(obj.meshes[0].data.vertices[2].position, [0., 1., 0.]);
+	}
+
+	#[test]
+	fn generates_missing_normals() {
+		let obj = parse(&format!("{}f 1 2 3 4\n", QUAD));
+		for vertex in &obj.meshes[0].data.vertices {
+			assert_eq!(vertex.normal, [0., 0., 1.]);
+		}
+		// clockwise faces the other way
+		let obj = parse(&format!("{}f 4 3 2 1\n", QUAD));
+		assert_eq!(obj.meshes[0].data.vertices[0].normal, [0., 0., -1.]);
+		// given normals are kept as they are
+		let obj = parse(&format!("{}vn 1 0 0\nf 1//1 2//1 3//1\n", QUAD));
+		assert_eq!(obj.meshes[0].data.vertices[0].normal, [1., 0., 0.]);
+	}
+
+	#[test]
+	fn groups_by_name_and_material() {
+		let obj = parse(&format!(
+			"{}o a\nusemtl red\nf 1 2 3\nusemtl blue # comment\nf 1 3 4\nusemtl red\nf 2 3 4\n\
+			 g b c\nf 1 2 4\n",
+			QUAD
+		));
+		let summary: Vec<(Option<&str>, usize)> = obj
+			.meshes
+			.iter()
+			.map(|mesh| (mesh.name.as_deref(), mesh.data.indices.len()))
+			.collect();
+		assert_eq!(summary, [(Some("a"), 6), (Some("a"), 3), (Some("b c"), 3)]);
+		// no mtllib was loaded, so nothing resolves
+		assert!(obj.meshes.iter().all(|mesh| mesh.material.is_none()));
+		assert_eq!(obj.bounds(), Some(([0.; 3], [1., 1., 0.])));
+	}
+
+	#[test]
+	fn resolves_materials_from_mtllib() {
+		let obj = Obj::parse_with(
+			&format!("mtllib a.mtl\n{}usemtl shiny\nf 1 2 3\n", QUAD),
+			"<obj>",
+			|mtl| {
+				assert_eq!(mtl, "a.mtl");
+				Obj::parse_mtl("newmtl dull\nnewmtl shiny\nNs 50", mtl)
+			},
+		)
+		.expect("Should have been able to parse obj");
+		assert_eq!(obj.materials.len(), 2);
+		assert_eq!(obj.meshes[0].material, Some(1));
+		assert_eq!(obj.materials[1].shininess, 50.);
+	}
+
+	#[test]
+	fn obj_errors_have_line_numbers() {
+		let cases = [
+			("v 1 2\n", 1, "v takes 3 to 7 numbers, got 2"),
+			("v 0 0 0\nv 1 x 0\n", 2, "invalid number \"x\""),
+			("v 0 0 0\nv 1 inf 0\n", 2, "invalid number \"inf\""),
+			("vt\n", 1, "vt takes 1 to 3 numbers, got 0"),
+			("vn 0 1\n", 1, "vn takes 3 numbers, got 2"),
+			(
+				"v 0 0 0\nv 1 0 0\n\nf 1 2\n",
+				4,
+				"a face needs at least 3 vertices, got 2",
+			),
+			(
+				"v 0 0 0\nf 1 1 2\n",
+				2,
+				"position index 2 out of range, 1 defined so far",
+			),
+			(
+				"v 0 0 0\nf 0 1 1\n",
+				2,
+				"position index 0 out of range, 1 defined so far",
+			),
+			(
+				"v 0 0 0\nf -2 1 1\n",
+				2,
+				"position index -2 out of range, 1 defined so far",
+			),
+			(
+				"v 0 0 0\nf 1/1 1 1\n",
+				2,
+				"texture coordinate index 1 out of range, 0 defined so far",
+			),
+			(
+				"v 0 0 0\nf 1//1 1 1\n",
+				2,
+				"normal index 1 out of range, 0 defined so far",
+			),
+			("v 0 0 0\nf 1/// 1 1\n", 2, "has too many parts"),
+			("v 0 0 0\nf /1 1 1\n", 2, "has no position"),
+			("v 0 0 0\nf a 1 1\n", 2, "invalid position index \"a\""),
+			("# header\nusemtl\n", 2, "usemtl without a name"),
+			("mtllib # nothing\n", 1, "mtllib without a file"),
+		];
+		for (text, expected_line, expected_message) in cases {
+			let (line, message) = parse_error(Obj::parse(text));
+			assert_eq!(line, expected_line, "{:?}", text);
+			assert!(
+				message.contains(expected_message),
+				"{:?}: {}",
+				text,
+				message
+			);
+		}
+	}
+
+	#[test]
+	fn parses_mtl() {
+		let materials = Obj::parse_mtl(
+			"# exported\n\
+			 newmtl red paint\n\
+			 Ka 0.1\n\
+			 Kd 1 0 0 # red\n\
+			 Ks 0.5 0.5 0.5\n\
+			 Ke 0 0 0.25\n\
+			 Ns 32\n\
+			 d -halo 0.5\n\
+			 illum 1\n\
+			 map_Kd -s 2 2 1 textures/red.png\n\
+			 bump -bm 0.5 normal.png\n\
+			 Ni 1.45\n\
+			 \n\
+			 newmtl glass\n\
+			 Tr 0.75\n",
+			"a.mtl",
+		)
+		.expect("Should have been able to parse mtl");
+		assert_eq!(materials.len(), 2);
+		let red = &materials[0];
+		assert_eq!(red.name, "red paint");
+		assert_eq!(red.ambient, [0.1; 3]);
+		assert_eq!(red.diffuse, [1., 0., 0.]);
+		assert_eq!(red.specular, [0.5; 3]);
+		assert_eq!(red.emissive, [0., 0., 0.25]);
+		assert_eq!(red.shininess, 32.);
+		assert_eq!(red.dissolve, 0.5);
+		assert_eq!(red.illum, 1);
+		assert_eq!(red.diffuse_map, Some(PathBuf::from("textures/red.png")));
+		assert_eq!(red.normal_map, Some(PathBuf::from("normal.png")));
+		let glass = &materials[1];
+		assert_eq!(glass.dissolve, 0.25);
+		assert_eq!(glass.diffuse, ObjMaterial::new("").diffuse);
+	}
+
+	#[test]
+	fn mtl_errors_have_line_numbers() {
+		let cases = [
+			("Kd 1 1 1\n", 1, "Kd before any newmtl"),
+			("newmtl\n", 1, "newmtl without a name"),
+			(
+				"newmtl a\nKd 1 1\n",
+				2,
+				"expected 1 or 3 color values, got 2",
+			),
+			(
+				"newmtl a\n\nKs spectral file.rfl\n",
+				3,
+				"spectral colors are not supported",
+			),
+			("newmtl a\nNs\n", 2, "expected one number, got 0"),
+			("newmtl a\nd -halo\n", 2, "expected one number, got 0"),
+			("newmtl a\nillum two\n", 2, "invalid illum \"two\""),
+			("newmtl a\nmap_Kd\n", 2, "texture map without a file"),
+		];
+		for (text, expected_line, expected_message) in cases {
+			let (line, message) = parse_error(Obj::parse_mtl(text, "a.mtl"));
+			assert_eq!(line, expected_line, "{:?}", text);
+			assert!(
+				message.contains(expected_message),
+				"{:?}: {}",
+				text,
+				message
+			);
+		}
+		match Obj::parse_mtl("Ka 0", "dir/b.mtl") {
+			Err(e) => assert_eq!(e.to_string(), "dir/b.mtl line 1: Ka before any newmtl"),
+			Ok(materials) => panic!("parsed to {:?}", materials),
+		}
+	}
+}
@@ -0,0 +1,154 @@
+use super::{
+	AttachmentLoad, Buffer, BufferUsage, Camera, CameraUniforms, FrameData, Gltf, ImageTracker,
+	ImageUsage, Projection, RenderGraph, SCENE_DEPTH_FORMAT, Scene, SceneRenderer,
+	TransientImageDesc, TransientPool, VkCore, VkSwap, barriers::cmd_barriers, vk,
+};
+use cgmath::{Deg, Point3, Vector3};
+
+// what render_gltf draws into, its pixels come back as 8 bit srgb
+pub const OFFSCREEN_COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// linear, everything the model doesn't cover ends up this color
+pub const OFFSCREEN_CLEAR_COLOR: [f32; 4] = [0.02, 0.02, 0.03, 1.];
+
+// one frame of `gltf` drawn into an image instead of a swapchain, framed from a corner of its
+// bounds. `shader_code` is what SceneRenderer::new takes. rgba8 pixels, rows top to bottom
+pub fn render_gltf(vk: &VkCore, gltf: &Gltf, shader_code: &[u8], extent: vk::Extent2D) -> Vec<u8> {
+	let device_ctx = &vk.device_ctx;
+	let device = device_ctx.device();
+
+	// same camera setup VkSwap does per frame, just once
+	let camera_set_layout = VkSwap::create_camera_set_layout(device);
+	let descriptor_pool = VkSwap::create_descriptor_pool(device);
+	let camera_buffer = Buffer::new(
+		device_ctx,
+		size_of::<CameraUniforms>() as vk::DeviceSize,
+		vk::BufferUsageFlags::UNIFORM_BUFFER,
+		vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+	);
+	let camera_set =
+		FrameData::create_camera_set(device, descriptor_pool, camera_set_layout, &camera_buffer);
+	let (min, max) = gltf.bounds().unwrap_or(([-1.; 3], [1.; 3]));
+	let center = Point3::new(
+		(min[0] + max[0]) / 2.,
+		(min[1] + max[1]) / 2.,
+		(min[2] + max[2]) / 2.,
+	);
+	let radius = (0..3)
+		.map(|i| (max[i] - min[i]) / 2.)
+		.fold(0., f32::max)
+		.max(0.01);
+	let mut camera = Camera::new(
+		center + Vector3::new(1., 0.8, 1.5) * radius * 1.6,
+		Projection::perspective(Deg(45.), radius * 0.01, radius * 100.),
+	)
+	.looking_at(center);
+	camera.set_viewport(extent.width, extent.height);
+	camera_buffer.write(device, CameraUniforms::new(&camera).as_bytes());
+
+	let renderer = SceneRenderer::new(
+		device_ctx,
+		shader_code,
+		OFFSCREEN_COLOR_FORMAT,
+		camera_set_layout,
+	);
+	let scene = Scene::new(&vk.instance_ctx, device_ctx, &renderer, gltf);
+	let readback = Buffer::new(
+		device_ctx,
+		extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4,
+		vk::BufferUsageFlags::TRANSFER_DST,
+		vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+	);
+
+	let mut tracker = ImageTracker::new();
+	let mut transient_pool = TransientPool::new();
+	device_ctx.immediate_submit(|cmd_buff| {
+		let mut graph = RenderGraph::new();
+		let color = graph.create_image(
+			"color",
+			TransientImageDesc {
+				format: OFFSCREEN_COLOR_FORMAT,
+				extent,
+				usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+			},
+		);
+		let depth = graph.create_image(
+			"depth",
+			TransientImageDesc {
+				format: SCENE_DEPTH_FORMAT,
+				extent,
+				usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+			},
+		);
+		let readback_handle = graph.import_buffer("readback", readback.buffer);
+
+		let mut pass = graph.add_pass("scene");
+		let clear_color = vk::ClearValue {
+			color: vk::ClearColorValue {
+				float32: OFFSCREEN_CLEAR_COLOR,
+			},
+		};
+		let clear_depth = vk::ClearValue {
+			depth_stencil: vk::ClearDepthStencilValue {
+				depth: 1.,
+				stencil: 0,
+			},
+		};
+		let color = pass.color_attachment(color, AttachmentLoad::Clear(clear_color));
+		pass.depth_attachment(depth, AttachmentLoad::Clear(clear_depth));
+		pass.execute(|ctx| renderer.draw(ctx.device, ctx.cmd_buff, &scene, camera_set));
+
+		let mut pass = graph.add_pass("readback");
+		pass.read_image(color, ImageUsage::TransferSrc);
+		let readback_handle = pass.write_buffer(readback_handle, BufferUsage::TransferDst);
+		pass.side_effects();
+		pass.execute(move |ctx| unsafe {
+			let region = vk::BufferImageCopy {
+				image_subresource: vk::ImageSubresourceLayers {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: 0,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				image_extent: vk::Extent3D {
+					width: extent.width,
+					height: extent.height,
+					depth: 1,
+				},
+				..Default::default()
+			};
+			ctx.device.cmd_copy_image_to_buffer(
+				ctx.cmd_buff,
+				ctx.image(color),
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				ctx.buffer(readback_handle),
+				&[region],
+			);
+		});
+		graph.export_buffer(readback_handle);
+		graph.execute(device_ctx, cmd_buff, &mut tracker, &mut transient_pool);
+
+		// the wait in immediate_submit doesn't make the copy visible to the host by itself
+		let to_host = vk::BufferMemoryBarrier2 {
+			src_stage_mask: vk::PipelineStageFlags2::TRANSFER,
+			src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+			dst_stage_mask: vk::PipelineStageFlags2::HOST,
+			dst_access_mask: vk::AccessFlags2::HOST_READ,
+			buffer: readback.buffer,
+			size: vk::WHOLE_SIZE,
+			..Default::default()
+		};
+		cmd_barriers(device_ctx, cmd_buff, &[], &[to_host]);
+	});
+	let pixels = readback.read(device);
+
+	readback.cleanup(device);
+	transient_pool.cleanup(device);
+	scene.cleanup(device);
+	renderer.cleanup(device);
+	camera_buffer.cleanup(device);
+	unsafe {
+		device.destroy_descriptor_pool(descriptor_pool, None);
+		device.destroy_descriptor_set_layout(camera_set_layout, None);
+	}
+	pixels
+}
@@ -0,0 +1,268 @@
+use super::{Device, vk};
+use std::path::{Path, PathBuf};
+
+// "off", "temp" or a directory to keep pipeline caches in
+pub const PIPELINE_CACHE_ENV_VAR: &str = "VKRS_PIPELINE_CACHE";
+// size of VkPipelineCacheHeaderVersionOne
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// where the on disk pipeline cache lives
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum PipelineCacheDir {
+	// <os temp dir>/vkrs-pipeline-cache
+	#[default]
+	Temp,
+	Path(PathBuf),
+	// in memory only, nothing loaded or saved
+	Disabled,
+}
+
+impl PipelineCacheDir {
+	pub fn resolve(&self) -> Option<PathBuf> {
+		match self {
+			PipelineCacheDir::Temp => Some(std::env::temp_dir().join("vkrs-pipeline-cache")),
+			PipelineCacheDir::Path(path) => Some(path.clone()),
+			PipelineCacheDir::Disabled => None,
+		}
+	}
+}
+
+impl From<&str> for PipelineCacheDir {
+	fn from(value: &str) -> PipelineCacheDir {
+		match value {
+			"off" | "0" | "false" => PipelineCacheDir::Disabled,
+			"temp" => PipelineCacheDir::Temp,
+			path => PipelineCacheDir::Path(path.into()),
+		}
+	}
+}
+
+// VkPipelineCache owned by DeviceContext. one file per vendor/device/driver/cache uuid, so a
+// driver update or a different gpu just starts from an empty cache instead of handing the
+// driver someone else's blob
+pub struct PipelineCache {
+	pub cache: vk::PipelineCache,
+	path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+	pub fn new(
+		device: &Device,
+		properties: &vk::PhysicalDeviceProperties,
+		dir: &PipelineCacheDir,
+	) -> PipelineCache {
+		let path = dir.resolve().map(|dir| {
+			let uuid: String = properties
+				.pipeline_cache_uuid
+				.iter()
+				.map(|byte| format!("{:02x}", byte))
+				.collect();
+			dir.join(format!(
+				"{:04x}-{:04x}-{:08x}-{}.bin",
+				properties.vendor_id, properties.device_id, properties.driver_version, uuid
+			))
+		});
+		let data = path
+			.as_deref()
+			.and_then(|path| PipelineCache::load(path, properties))
+			.unwrap_or_default();
+		let cache_info = vk::PipelineCacheCreateInfo::default().initial_data(&data);
+		let cache = unsafe {
+			device
+				.create_pipeline_cache(&cache_info, None)
+				.or_else(|e| {
+					// the header matched but the driver still didn't like it
+					log::warn!(
+						"Pipeline cache rejected by the driver ({}), starting empty",
+						e
+					);
+					device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+				})
+				.expect("Should have been able to create pipeline cache")
+		};
+		PipelineCache { cache, path }
+	}
+
+	// None when there's no file yet or its header doesn't match this device
+	fn load(path: &Path, properties: &vk::PhysicalDeviceProperties) -> Option<Vec<u8>> {
+		let data = std::fs::read(path).ok()?;
+		match PipelineCache::validate_header(&data, properties) {
+			Ok(()) => {
+				log::info!(
+					"Loaded pipeline cache {} ({} bytes)",
+					path.display(),
+					data.len()
+				);
+				Some(data)
+			}
+			Err(reason) => {
+				log::warn!("Discarding pipeline cache {}: {}", path.display(), reason);
+				None
+			}
+		}
+	}
+
+	// VkPipelineCacheHeaderVersionOne: length, version, vendor id, device id, cache uuid
+	fn validate_header(
+		data: &[u8],
+		properties: &vk::PhysicalDeviceProperties,
+	) -> Result<(), &'static str> {
+		if data.len() < HEADER_SIZE {
+			return Err("too short for a header");
+		}
+		let word = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+		if (word(0) as usize) < HEADER_SIZE || word(0) as usize > data.len() {
+			return Err("bad header length");
+		}
+		if word(1) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+			return Err("unknown header version");
+		}
+		if word(2) != properties.vendor_id || word(3) != properties.device_id {
+			return Err("written by a different device");
+		}
+		if data[16..HEADER_SIZE] != properties.pipeline_cache_uuid {
+			return Err("pipeline cache uuid mismatch");
+		}
+		Ok(())
+	}
+
+	// writes to a temp file first so a crash mid write can't leave half a cache behind
+	pub fn save(&self, device: &Device) {
+		let Some(path) = &self.path else {
+			return;
+		};
+		let data = unsafe {
+			device
+				.get_pipeline_cache_data(self.cache)
+				.expect("Should have been able to get pipeline cache data")
+		};
+		let tmp_path = path.with_extension("tmp");
+		let written = path
+			.parent()
+			.map_or(Ok(()), std::fs::create_dir_all)
+			.and_then(|()| std::fs::write(&tmp_path, &data))
+			.and_then(|()| std::fs::rename(&tmp_path, path));
+		match written {
+			Ok(()) => log::info!(
+				"Saved pipeline cache {} ({} bytes)",
+				path.display(),
+				data.len()
+			),
+			Err(e) => log::error!("Could not save pipeline cache {}: {}", path.display(), e),
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		unsafe { device.destroy_pipeline_cache(self.cache, None) };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn properties() -> vk::PhysicalDeviceProperties {
+		vk::PhysicalDeviceProperties {
+			vendor_id: 0x10de,
+			device_id: 0x2684,
+			pipeline_cache_uuid: [7; vk::UUID_SIZE],
+			..Default::default()
+		}
+	}
+
+	// a header for `properties` followed by `payload` bytes of cache data
+	fn header(properties: &vk::PhysicalDeviceProperties, payload: usize) -> Vec<u8> {
+		let mut data = Vec::new();
+		data.extend((HEADER_SIZE as u32).to_le_bytes());
+		data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_le_bytes());
+		data.extend(properties.vendor_id.to_le_bytes());
+		data.extend(properties.device_id.to_le_bytes());
+		data.extend(properties.pipeline_cache_uuid);
+		data.resize(HEADER_SIZE + payload, 0xab);
+		data
+	}
+
+	#[test]
+	fn accepts_matching_header() {
+		let properties = properties();
+		assert_eq!(
+			PipelineCache::validate_header(&header(&properties, 0), &properties),
+			Ok(())
+		);
+		assert_eq!(
+			PipelineCache::validate_header(&header(&properties, 64), &properties),
+			Ok(())
+		);
+	}
+
+	#[test]
+	fn rejects_short_data() {
+		let properties = properties();
+		let data = header(&properties, 0);
+		assert_eq!(
+			PipelineCache::validate_header(&[], &properties),
+			Err("too short for a header")
+		);
+		assert_eq!(
+			PipelineCache::validate_header(&data[..HEADER_SIZE - 1], &properties),
+			Err("too short for a header")
+		);
+	}
+
+	#[test]
+	fn rejects_bad_length() {
+		let properties = properties();
+		for length in [0, HEADER_SIZE as u32 - 1, HEADER_SIZE as u32 + 1, u32::MAX] {
+			let mut data = header(&properties, 0);
+			data[..4].copy_from_slice(&length.to_le_bytes());
+			assert_eq!(
+				PipelineCache::validate_header(&data, &properties),
+				Err("bad header length"),
+				"length {}",
+				length
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_wrong_version() {
+		let properties = properties();
+		let mut data = header(&properties, 0);
+		data[4..8].copy_from_slice(&2u32.to_le_bytes());
+		assert_eq!(
+			PipelineCache::validate_header(&data, &properties),
+			Err("unknown header version")
+		);
+	}
+
+	#[test]
+	fn rejects_other_device() {
+		let properties = properties();
+		let data = header(&properties, 0);
+		let other_vendor = vk::PhysicalDeviceProperties {
+			vendor_id: 0x1002,
+			..properties
+		};
+		let other_device = vk::PhysicalDeviceProperties {
+			device_id: 0x2685,
+			..properties
+		};
+		for other in [other_vendor, other_device] {
+			assert_eq!(
+				PipelineCache::validate_header(&data, &other),
+				Err("written by a different device")
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_uuid_mismatch() {
+		let properties = properties();
+		let mut data = header(&properties, 0);
+		data[HEADER_SIZE - 1] ^= 1;
+		assert_eq!(
+			PipelineCache::validate_header(&data, &properties),
+			Err("pipeline cache uuid mismatch")
+		);
+	}
+}
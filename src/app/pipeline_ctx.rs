@@ -1,15 +1,105 @@
 use super::{Device, DeviceContext, vk};
 use std::ffi::c_void;
 
+// fixed function state that differs between pipelines, everything else is shared
+#[derive(Clone, Copy)]
+pub struct PipelineDesc<'a> {
+	pub color_format: vk::Format,
+	// None means no depth attachment and no depth test
+	pub depth_format: Option<vk::Format>,
+	pub depth_write: bool,
+	pub cull_mode: vk::CullModeFlags,
+	pub set_layouts: &'a [vk::DescriptorSetLayout],
+	pub push_constant_ranges: &'a [vk::PushConstantRange],
+	pub vertex_bindings: &'a [vk::VertexInputBindingDescription],
+	pub vertex_attributes: &'a [vk::VertexInputAttributeDescription],
+}
+
+impl<'a> PipelineDesc<'a> {
+	pub fn new(color_format: vk::Format) -> PipelineDesc<'a> {
+		PipelineDesc {
+			color_format,
+			depth_format: None,
+			depth_write: true,
+			cull_mode: vk::CullModeFlags::BACK,
+			set_layouts: &[],
+			push_constant_ranges: &[],
+			vertex_bindings: &[],
+			vertex_attributes: &[],
+		}
+	}
+
+	pub fn with_depth(mut self, format: vk::Format, write: bool) -> PipelineDesc<'a> {
+		self.depth_format = Some(format);
+		self.depth_write = write;
+		self
+	}
+
+	pub fn with_cull_mode(mut self, cull_mode: vk::CullModeFlags) -> PipelineDesc<'a> {
+		self.cull_mode = cull_mode;
+		self
+	}
+
+	pub fn with_set_layouts(
+		mut self,
+		set_layouts: &'a [vk::DescriptorSetLayout],
+	) -> PipelineDesc<'a> {
+		self.set_layouts = set_layouts;
+		self
+	}
+
+	pub fn with_push_constants(mut self, ranges: &'a [vk::PushConstantRange]) -> PipelineDesc<'a> {
+		self.push_constant_ranges = ranges;
+		self
+	}
+
+	pub fn with_vertex_input(
+		mut self,
+		bindings: &'a [vk::VertexInputBindingDescription],
+		attributes: &'a [vk::VertexInputAttributeDescription],
+	) -> PipelineDesc<'a> {
+		self.vertex_bindings = bindings;
+		self.vertex_attributes = attributes;
+		self
+	}
+}
+
 pub struct PipelineContext {
 	pub pipeline_layout: vk::PipelineLayout,
 	pub graphics_pipeline: vk::Pipeline,
 }
 
 impl PipelineContext {
-	pub fn new(device_ctx: &DeviceContext, swap_format: vk::Format) -> PipelineContext {
-		let (pipeline_layout, graphics_pipeline) =
-			PipelineContext::create_graphics_pipeline(device_ctx.device(), swap_format);
+	// `shader_code` is spirv with vertMain and fragMain entry points, `set_layouts` go into the
+	// pipeline layout in order (e.g. VkSwap::camera_set_layout as set 0)
+	pub fn new(
+		device_ctx: &DeviceContext,
+		shader_code: &[u8],
+		swap_format: vk::Format,
+		set_layouts: &[vk::DescriptorSetLayout],
+	) -> PipelineContext {
+		PipelineContext::with_desc(
+			device_ctx,
+			shader_code,
+			&PipelineDesc::new(swap_format).with_set_layouts(set_layouts),
+			"graphics pipeline",
+		)
+	}
+
+	pub fn with_desc(
+		device_ctx: &DeviceContext,
+		shader_code: &[u8],
+		desc: &PipelineDesc,
+		name: &str,
+	) -> PipelineContext {
+		let (pipeline_layout, graphics_pipeline) = PipelineContext::create_graphics_pipeline(
+			device_ctx.device(),
+			device_ctx.pipeline_cache.cache,
+			shader_code,
+			desc,
+		);
+		device_ctx.set_name(pipeline_layout, &format!("{} layout", name));
+		device_ctx.set_name(graphics_pipeline, name);
 
 		PipelineContext {
 			pipeline_layout,
@@ -17,12 +107,19 @@ impl PipelineContext {
 		}
 	}
 
+	pub fn cleanup(&self, device: &Device) {
+		unsafe {
+			device.destroy_pipeline_layout(self.pipeline_layout, None);
+			device.destroy_pipeline(self.graphics_pipeline, None);
+		}
+	}
+
 	fn create_graphics_pipeline(
 		device: &Device,
-		swap_format: vk::Format,
+		pipeline_cache: vk::PipelineCache,
+		shader_code: &[u8],
+		desc: &PipelineDesc,
 	) -> (vk::PipelineLayout, vk::Pipeline) {
-		// TODO: normalize path
-		let shader_code = include_bytes!("../../shaders/slang.spv");
 		debug_assert!(shader_code.len() > 0, "shader_code byte len <= 0");
 		let shader_module = PipelineContext::create_shader_module(device, shader_code);
 		let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo {
@@ -46,8 +143,10 @@ impl PipelineContext {
 			..Default::default()
 		};
 		let vertex_input_info = vk::PipelineVertexInputStateCreateInfo {
-			vertex_binding_description_count: 0,
-			vertex_attribute_description_count: 0,
+			vertex_binding_description_count: desc.vertex_bindings.len() as u32,
+			p_vertex_binding_descriptions: desc.vertex_bindings.as_ptr(),
+			vertex_attribute_description_count: desc.vertex_attributes.len() as u32,
+			p_vertex_attribute_descriptions: desc.vertex_attributes.as_ptr(),
 			..Default::default()
 		};
 		let input_asm_info = vk::PipelineInputAssemblyStateCreateInfo {
@@ -63,8 +162,9 @@ impl PipelineContext {
 			depth_clamp_enable: vk::FALSE,
 			rasterizer_discard_enable: vk::FALSE,
 			polygon_mode: vk::PolygonMode::FILL,
-			cull_mode: vk::CullModeFlags::BACK,
-			front_face: vk::FrontFace::CLOCKWISE,
+			cull_mode: desc.cull_mode,
+			// the projection flips y, so ccw in world space stays ccw on screen
+			front_face: vk::FrontFace::COUNTER_CLOCKWISE,
 			depth_bias_enable: vk::FALSE,
 			depth_bias_slope_factor: 1.,
 			line_width: 1.,
@@ -75,6 +175,12 @@ impl PipelineContext {
 			sample_shading_enable: vk::FALSE,
 			..Default::default()
 		};
+		let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo {
+			depth_test_enable: vk::TRUE,
+			depth_write_enable: desc.depth_write as vk::Bool32,
+			depth_compare_op: vk::CompareOp::LESS,
+			..Default::default()
+		};
 		let color_blend_attachment = vk::PipelineColorBlendAttachmentState {
 			blend_enable: vk::TRUE,
 			color_write_mask: vk::ColorComponentFlags::R
@@ -97,8 +203,10 @@ impl PipelineContext {
 			..Default::default()
 		};
 		let pipeline_info = vk::PipelineLayoutCreateInfo {
-			set_layout_count: 0,
-			push_constant_range_count: 0,
+			set_layout_count: desc.set_layouts.len() as u32,
+			p_set_layouts: desc.set_layouts.as_ptr(),
+			push_constant_range_count: desc.push_constant_ranges.len() as u32,
+			p_push_constant_ranges: desc.push_constant_ranges.as_ptr(),
 			..Default::default()
 		};
 
@@ -109,7 +217,8 @@ impl PipelineContext {
 		};
 		let pipeline_rendering_info = vk::PipelineRenderingCreateInfo {
 			color_attachment_count: 1,
-			p_color_attachment_formats: &swap_format,
+			p_color_attachment_formats: &desc.color_format,
+			depth_attachment_format: desc.depth_format.unwrap_or(vk::Format::UNDEFINED),
 			..Default::default()
 		};
 		let pipeline_info = vk::GraphicsPipelineCreateInfo {
@@ -121,6 +230,11 @@ impl PipelineContext {
 			p_viewport_state: &viewport_info,
 			p_rasterization_state: &rasterizer_info,
 			p_multisample_state: &multisampling_info,
+			p_depth_stencil_state: if desc.depth_format.is_some() {
+				&depth_stencil_info
+			} else {
+				std::ptr::null()
+			},
 			p_color_blend_state: &color_blend_info,
 			p_dynamic_state: &dyn_state_info,
 			layout: pipeline_layout,
@@ -129,7 +243,7 @@ impl PipelineContext {
 		};
 		let graphics_pipeline = unsafe {
 			device
-				.create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
+				.create_graphics_pipelines(pipeline_cache, &[pipeline_info], None)
 				.expect("Should have been able to greate graphics pipeline")[0] // only creating one for now
 		};
 
@@ -138,10 +252,11 @@ impl PipelineContext {
 		(pipeline_layout, graphics_pipeline)
 	}
 
-	pub fn create_shader_module(device: &Device, code: &'static [u8]) -> vk::ShaderModule {
+	pub fn create_shader_module(device: &Device, code: &[u8]) -> vk::ShaderModule {
+		let words = spirv_words(code);
 		let create_info = vk::ShaderModuleCreateInfo {
-			code_size: code.len(),
-			p_code: code.as_ptr() as *const u32,
+			code_size: words.len() * size_of::<u32>(),
+			p_code: words.as_ptr(),
 			..Default::default()
 		};
 		unsafe {
@@ -151,3 +266,34 @@ impl PipelineContext {
 		}
 	}
 }
+
+// include_bytes! gives no alignment guarantee, so copy the spirv into u32s. read_spv also
+// rejects lengths that aren't a whole number of words
+fn spirv_words(code: &[u8]) -> Vec<u32> {
+	ash::util::read_spv(&mut std::io::Cursor::new(code))
+		.expect("Should have been able to read spirv words")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MAGIC: u32 = 0x0723_0203;
+
+	#[test]
+	fn reads_misaligned_spirv() {
+		let mut bytes = vec![0u8];
+		bytes.extend(MAGIC.to_le_bytes());
+		bytes.extend(0x0001_0600u32.to_le_bytes());
+		// one byte past the start of the allocation, so not u32 aligned
+		assert_eq!(spirv_words(&bytes[1..]), [MAGIC, 0x0001_0600]);
+	}
+
+	#[test]
+	#[should_panic(expected = "spirv words")]
+	fn rejects_partial_words() {
+		let mut bytes = MAGIC.to_le_bytes().to_vec();
+		bytes.push(0);
+		spirv_words(&bytes);
+	}
+}
@@ -0,0 +1,1268 @@
+use super::{
+	Device, DeviceContext, FrameQueries, FrameTimestamps, ImageState, ImageTracker, ImageUsage,
+	barriers::{aspect_for_format, cmd_barriers},
+	vk,
+};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt::Write;
+
+// handles are versioned: every write hands back a new version, reads name the version they want.
+// that's what lets the graph figure out ordering and culling from the declarations alone
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ImageHandle {
+	index: usize,
+	version: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BufferHandle {
+	index: usize,
+	version: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TransientImageDesc {
+	pub format: vk::Format,
+	pub extent: vk::Extent2D,
+	pub usage: vk::ImageUsageFlags,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BufferUsage {
+	VertexInput,
+	IndexInput,
+	IndirectRead,
+	UniformRead,
+	StorageRead,
+	StorageWrite,
+	TransferSrc,
+	TransferDst,
+}
+
+impl BufferUsage {
+	pub fn state(self) -> (vk::AccessFlags2, vk::PipelineStageFlags2) {
+		use vk::{AccessFlags2 as A, PipelineStageFlags2 as S};
+		let shaders = S::VERTEX_SHADER | S::FRAGMENT_SHADER | S::COMPUTE_SHADER;
+		match self {
+			BufferUsage::VertexInput => (A::VERTEX_ATTRIBUTE_READ, S::VERTEX_ATTRIBUTE_INPUT),
+			BufferUsage::IndexInput => (A::INDEX_READ, S::INDEX_INPUT),
+			BufferUsage::IndirectRead => (A::INDIRECT_COMMAND_READ, S::DRAW_INDIRECT),
+			BufferUsage::UniformRead => (A::UNIFORM_READ, shaders),
+			BufferUsage::StorageRead => (A::SHADER_STORAGE_READ, shaders),
+			BufferUsage::StorageWrite => {
+				(A::SHADER_STORAGE_READ | A::SHADER_STORAGE_WRITE, shaders)
+			}
+			BufferUsage::TransferSrc => (A::TRANSFER_READ, S::TRANSFER),
+			BufferUsage::TransferDst => (A::TRANSFER_WRITE, S::TRANSFER),
+		}
+	}
+	pub fn writes(self) -> bool {
+		matches!(self, BufferUsage::StorageWrite | BufferUsage::TransferDst)
+	}
+}
+
+#[derive(Clone, Copy)]
+pub enum AttachmentLoad {
+	Clear(vk::ClearValue),
+	Load,
+	DontCare,
+}
+
+enum ImageSource {
+	Imported {
+		image: vk::Image,
+		view: vk::ImageView,
+	},
+	Transient(TransientImageDesc),
+}
+
+struct ImageResource {
+	name: String,
+	source: ImageSource,
+	extent: vk::Extent2D,
+	// pass that wrote each version, version 0 is whatever was there before the graph
+	producers: Vec<Option<usize>>,
+	export: Option<(u32, Option<ImageUsage>)>,
+}
+
+struct BufferResource {
+	name: String,
+	buffer: vk::Buffer,
+	producers: Vec<Option<usize>>,
+	export: Option<u32>,
+}
+
+struct ImageWrite {
+	handle: ImageHandle, // the version this write produces
+	usage: ImageUsage,
+	// false when the previous contents are thrown away (cleared/dont care attachments)
+	preserves: bool,
+}
+
+type PassExecute<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+struct Pass<'a> {
+	name: String,
+	image_reads: Vec<(ImageHandle, ImageUsage)>,
+	image_writes: Vec<ImageWrite>,
+	buffer_reads: Vec<(BufferHandle, BufferUsage)>,
+	buffer_writes: Vec<(BufferHandle, BufferUsage)>,
+	color_attachments: Vec<(ImageHandle, AttachmentLoad)>,
+	depth_attachment: Option<(ImageHandle, AttachmentLoad)>,
+	side_effects: bool,
+	execute: Option<PassExecute<'a>>,
+}
+
+pub struct PassContext<'g> {
+	pub device: &'g Device,
+	pub cmd_buff: vk::CommandBuffer,
+	pub render_area: vk::Extent2D,
+	// for occlusion queries, set when the graph collects queries
+	pub queries: Option<&'g FrameQueries>,
+	images: &'g [(vk::Image, vk::ImageView)],
+	buffers: &'g [vk::Buffer],
+}
+
+impl PassContext<'_> {
+	pub fn image(&self, handle: ImageHandle) -> vk::Image {
+		self.images[handle.index].0
+	}
+	pub fn view(&self, handle: ImageHandle) -> vk::ImageView {
+		self.images[handle.index].1
+	}
+	pub fn buffer(&self, handle: BufferHandle) -> vk::Buffer {
+		self.buffers[handle.index]
+	}
+}
+
+// per frame graph: passes declare what they read/write, the graph culls whatever doesn't
+// contribute to an exported resource, orders the rest, places the barriers and records
+// everything with dynamic rendering. rebuilt every frame, the transient memory behind it
+// lives in a TransientPool that survives between frames
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+	images: Vec<ImageResource>,
+	buffers: Vec<BufferResource>,
+	passes: Vec<Pass<'a>>,
+	// every executed pass gets a profile scope when set
+	timestamps: Option<&'a FrameTimestamps>,
+	// every executed pass gets a pipeline statistics scope when set
+	queries: Option<&'a FrameQueries>,
+}
+
+impl<'a> RenderGraph<'a> {
+	pub fn new() -> RenderGraph<'a> {
+		RenderGraph {
+			images: Vec::new(),
+			buffers: Vec::new(),
+			passes: Vec::new(),
+			timestamps: None,
+			queries: None,
+		}
+	}
+
+	pub fn profile_passes(&mut self, timestamps: &'a FrameTimestamps) {
+		self.timestamps = Some(timestamps);
+	}
+
+	pub fn collect_queries(&mut self, queries: &'a FrameQueries) {
+		self.queries = Some(queries);
+	}
+
+	// imported images have to already be registered with the tracker handed to `execute`
+	pub fn import_image(
+		&mut self,
+		name: &str,
+		image: vk::Image,
+		view: vk::ImageView,
+		extent: vk::Extent2D,
+	) -> ImageHandle {
+		self.images.push(ImageResource {
+			name: name.to_owned(),
+			source: ImageSource::Imported { image, view },
+			extent,
+			producers: vec![None],
+			export: None,
+		});
+		ImageHandle {
+			index: self.images.len() - 1,
+			version: 0,
+		}
+	}
+
+	pub fn create_image(&mut self, name: &str, desc: TransientImageDesc) -> ImageHandle {
+		self.images.push(ImageResource {
+			name: name.to_owned(),
+			source: ImageSource::Transient(desc),
+			extent: desc.extent,
+			producers: vec![None],
+			export: None,
+		});
+		ImageHandle {
+			index: self.images.len() - 1,
+			version: 0,
+		}
+	}
+
+	pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer) -> BufferHandle {
+		self.buffers.push(BufferResource {
+			name: name.to_owned(),
+			buffer,
+			producers: vec![None],
+			export: None,
+		});
+		BufferHandle {
+			index: self.buffers.len() - 1,
+			version: 0,
+		}
+	}
+
+	// keeps whatever produced `handle` alive, optionally leaving the image in `final_usage`
+	// (Present for the swapchain image)
+	pub fn export_image(&mut self, handle: ImageHandle, final_usage: Option<ImageUsage>) {
+		self.images[handle.index].export = Some((handle.version, final_usage));
+	}
+
+	pub fn export_buffer(&mut self, handle: BufferHandle) {
+		self.buffers[handle.index].export = Some(handle.version);
+	}
+
+	pub fn add_pass<'g>(&'g mut self, name: &str) -> PassBuilder<'g, 'a> {
+		self.passes.push(Pass {
+			name: name.to_owned(),
+			image_reads: Vec::new(),
+			image_writes: Vec::new(),
+			buffer_reads: Vec::new(),
+			buffer_writes: Vec::new(),
+			color_attachments: Vec::new(),
+			depth_attachment: None,
+			side_effects: false,
+			execute: None,
+		});
+		let pass = self.passes.len() - 1;
+		PassBuilder { graph: self, pass }
+	}
+
+	// which passes end up contributing to an export (or have side effects)
+	fn live_passes(&self) -> Vec<bool> {
+		let mut live = vec![false; self.passes.len()];
+		let mut needed_images: HashSet<(usize, u32)> = HashSet::new();
+		let mut needed_buffers: HashSet<(usize, u32)> = HashSet::new();
+		for (index, image) in self.images.iter().enumerate() {
+			if let Some((version, _)) = image.export {
+				needed_images.insert((index, version));
+			}
+		}
+		for (index, buffer) in self.buffers.iter().enumerate() {
+			if let Some(version) = buffer.export {
+				needed_buffers.insert((index, version));
+			}
+		}
+		// walk until nothing changes, graphs are small enough that this doesn't matter
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for (pass_idx, pass) in self.passes.iter().enumerate() {
+				if live[pass_idx] {
+					continue;
+				}
+				let produces_needed = pass.image_writes.iter().any(|write| {
+					needed_images.contains(&(write.handle.index, write.handle.version))
+				}) || pass
+					.buffer_writes
+					.iter()
+					.any(|(handle, _)| needed_buffers.contains(&(handle.index, handle.version)));
+				if !produces_needed && !pass.side_effects {
+					continue;
+				}
+				live[pass_idx] = true;
+				changed = true;
+				for (handle, _) in &pass.image_reads {
+					needed_images.insert((handle.index, handle.version));
+				}
+				for write in pass.image_writes.iter().filter(|write| write.preserves) {
+					needed_images.insert((write.handle.index, write.handle.version - 1));
+				}
+				for (handle, _) in &pass.buffer_reads {
+					needed_buffers.insert((handle.index, handle.version));
+				}
+				for (handle, _) in &pass.buffer_writes {
+					needed_buffers.insert((handle.index, handle.version - 1));
+				}
+			}
+		}
+		live
+	}
+
+	// topological order of the live passes, ties broken by declaration order
+	fn schedule(&self, live: &[bool]) -> Vec<usize> {
+		let pass_count = self.passes.len();
+		let mut edges: Vec<HashSet<usize>> = vec![HashSet::new(); pass_count];
+		let mut add_edge = |from: Option<usize>, to: usize| {
+			if let Some(from) = from
+				&& from != to
+				&& live[from]
+			{
+				edges[from].insert(to);
+			}
+		};
+		for (pass_idx, pass) in self.passes.iter().enumerate() {
+			if !live[pass_idx] {
+				continue;
+			}
+			// read after write
+			for (handle, _) in &pass.image_reads {
+				add_edge(
+					self.images[handle.index].producers[handle.version as usize],
+					pass_idx,
+				);
+			}
+			for (handle, _) in &pass.buffer_reads {
+				add_edge(
+					self.buffers[handle.index].producers[handle.version as usize],
+					pass_idx,
+				);
+			}
+			// write after write, and write after read of the version being replaced
+			for write in &pass.image_writes {
+				let prev = write.handle.version - 1;
+				add_edge(
+					self.images[write.handle.index].producers[prev as usize],
+					pass_idx,
+				);
+				for (reader_idx, reader) in self.passes.iter().enumerate() {
+					if reader.image_reads.iter().any(|(handle, _)| {
+						handle.index == write.handle.index && handle.version == prev
+					}) {
+						add_edge(Some(reader_idx), pass_idx);
+					}
+				}
+			}
+			for (write, _) in &pass.buffer_writes {
+				let prev = write.version - 1;
+				add_edge(self.buffers[write.index].producers[prev as usize], pass_idx);
+				for (reader_idx, reader) in self.passes.iter().enumerate() {
+					if reader
+						.buffer_reads
+						.iter()
+						.any(|(handle, _)| handle.index == write.index && handle.version == prev)
+					{
+						add_edge(Some(reader_idx), pass_idx);
+					}
+				}
+			}
+		}
+
+		let mut in_degree = vec![0usize; pass_count];
+		for targets in &edges {
+			for &to in targets {
+				in_degree[to] += 1;
+			}
+		}
+		let mut ready: BinaryHeap<Reverse<usize>> = (0..pass_count)
+			.filter(|&idx| live[idx] && in_degree[idx] == 0)
+			.map(Reverse)
+			.collect();
+		let mut order = Vec::new();
+		while let Some(Reverse(pass_idx)) = ready.pop() {
+			order.push(pass_idx);
+			for &to in &edges[pass_idx] {
+				in_degree[to] -= 1;
+				if in_degree[to] == 0 {
+					ready.push(Reverse(to));
+				}
+			}
+		}
+		assert_eq!(
+			order.len(),
+			live.iter().filter(|&&is_live| is_live).count(),
+			"Render graph should not contain cycles"
+		);
+		order
+	}
+
+	pub fn execute(
+		mut self,
+		device_ctx: &DeviceContext,
+		cmd_buff: vk::CommandBuffer,
+		tracker: &mut ImageTracker,
+		pool: &mut TransientPool,
+	) {
+		let device = device_ctx.device();
+		let live = self.live_passes();
+		let order = self.schedule(&live);
+
+		let (transient_indices, transient_descs, lifetimes) = self.transient_lifetimes(&order);
+		if pool.prepare(device_ctx, &transient_descs, &lifetimes) {
+			for (slot, &index) in transient_indices.iter().enumerate() {
+				let (image, view) = pool.images[slot];
+				let name = &self.images[index].name;
+				device_ctx.set_name(image, name);
+				device_ctx.set_name(view, &format!("{} view", name));
+			}
+		}
+
+		let mut resolved: Vec<(vk::Image, vk::ImageView)> =
+			vec![(vk::Image::null(), vk::ImageView::null()); self.images.len()];
+		for (index, image) in self.images.iter().enumerate() {
+			if let ImageSource::Imported { image, view } = image.source {
+				resolved[index] = (image, view);
+			}
+		}
+		for (slot, &index) in transient_indices.iter().enumerate() {
+			resolved[index] = pool.images[slot];
+			let aspect = aspect_for_format(transient_descs[slot].format);
+			tracker.register(pool.images[slot].0, aspect, 1, 1);
+		}
+		let buffers: Vec<vk::Buffer> = self.buffers.iter().map(|buffer| buffer.buffer).collect();
+		let mut buffer_states: Vec<(vk::AccessFlags2, vk::PipelineStageFlags2)> =
+			vec![(vk::AccessFlags2::NONE, vk::PipelineStageFlags2::NONE); self.buffers.len()];
+
+		for (position, &pass_idx) in order.iter().enumerate() {
+			// first use of an aliased transient, it inherits the previous owner's sync
+			for (slot, &(first, _)) in lifetimes.iter().enumerate() {
+				if first != position {
+					continue;
+				}
+				if let Some(prev_slot) = pool.aliases[slot] {
+					let prev_state = tracker
+						.state(pool.images[prev_slot].0, 0, 0)
+						.unwrap_or(ImageState::UNDEFINED);
+					tracker.alias(pool.images[slot].0, prev_state);
+				}
+			}
+
+			let pass = &mut self.passes[pass_idx];
+			// covers the pass's barriers too
+			let _label = device_ctx.cmd_label(cmd_buff, &pass.name);
+			let _scope = self
+				.timestamps
+				.map(|timestamps| timestamps.profile_scope(device, cmd_buff, &pass.name));
+			for (handle, usage) in &pass.image_reads {
+				tracker.use_image(resolved[handle.index].0, *usage);
+			}
+			for write in &pass.image_writes {
+				tracker.use_image(resolved[write.handle.index].0, write.usage);
+			}
+			let mut buffer_barriers: Vec<vk::BufferMemoryBarrier2> = Vec::new();
+			let buffer_accesses = pass.buffer_reads.iter().chain(pass.buffer_writes.iter());
+			for &(handle, usage) in buffer_accesses {
+				let (prev_access, prev_stage) = buffer_states[handle.index];
+				let (access, stage) = usage.state();
+				let prev_writes = prev_access.intersects(
+					vk::AccessFlags2::SHADER_STORAGE_WRITE | vk::AccessFlags2::TRANSFER_WRITE,
+				);
+				if prev_writes || (usage.writes() && !prev_stage.is_empty()) {
+					buffer_barriers.push(vk::BufferMemoryBarrier2 {
+						src_stage_mask: prev_stage,
+						src_access_mask: if prev_writes {
+							prev_access
+						} else {
+							vk::AccessFlags2::NONE
+						},
+						dst_stage_mask: stage,
+						dst_access_mask: access,
+						src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+						buffer: buffers[handle.index],
+						offset: 0,
+						size: vk::WHOLE_SIZE,
+						..Default::default()
+					});
+					buffer_states[handle.index] = (access, stage);
+				} else {
+					buffer_states[handle.index] = (prev_access | access, prev_stage | stage);
+				}
+			}
+			let image_barriers = tracker.take_pending();
+			cmd_barriers(device_ctx, cmd_buff, &image_barriers, &buffer_barriers);
+
+			let is_raster = !pass.color_attachments.is_empty() || pass.depth_attachment.is_some();
+			let render_area = pass
+				.color_attachments
+				.iter()
+				.chain(pass.depth_attachment.iter())
+				.map(|(handle, _)| self.images[handle.index].extent)
+				.reduce(|a, b| vk::Extent2D {
+					width: a.width.min(b.width),
+					height: a.height.min(b.height),
+				})
+				.unwrap_or_default();
+			if is_raster {
+				let color_infos: Vec<vk::RenderingAttachmentInfo> = pass
+					.color_attachments
+					.iter()
+					.map(|&(handle, load)| {
+						RenderGraph::attachment_info(
+							resolved[handle.index].1,
+							vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+							load,
+						)
+					})
+					.collect();
+				let depth_info = pass.depth_attachment.map(|(handle, load)| {
+					RenderGraph::attachment_info(
+						resolved[handle.index].1,
+						vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+						load,
+					)
+				});
+				let mut render_info = vk::RenderingInfo {
+					render_area: vk::Rect2D {
+						offset: vk::Offset2D { x: 0, y: 0 },
+						extent: render_area,
+					},
+					layer_count: 1,
+					..Default::default()
+				}
+				.color_attachments(&color_infos);
+				if let Some(depth_info) = depth_info.as_ref() {
+					render_info = render_info.depth_attachment(depth_info);
+				}
+				device_ctx.cmd_begin_rendering(cmd_buff, &render_info);
+				unsafe {
+					// sane defaults, passes are free to override them
+					device.cmd_set_viewport(
+						cmd_buff,
+						0,
+						&[vk::Viewport {
+							x: 0.,
+							y: 0.,
+							width: render_area.width as f32,
+							height: render_area.height as f32,
+							min_depth: 0.,
+							max_depth: 1.,
+						}],
+					);
+					device.cmd_set_scissor(
+						cmd_buff,
+						0,
+						&[vk::Rect2D {
+							offset: vk::Offset2D { x: 0, y: 0 },
+							extent: render_area,
+						}],
+					);
+				}
+			}
+			if let Some(execute) = pass.execute.take() {
+				let _statistics = self
+					.queries
+					.map(|queries| queries.pipeline_statistics(device, cmd_buff, &pass.name));
+				execute(&PassContext {
+					device,
+					cmd_buff,
+					render_area,
+					queries: self.queries,
+					images: &resolved,
+					buffers: &buffers,
+				});
+			}
+			if is_raster {
+				device_ctx.cmd_end_rendering(cmd_buff);
+			}
+		}
+
+		for (index, image) in self.images.iter().enumerate() {
+			if let Some((_, Some(final_usage))) = image.export {
+				tracker.use_image(resolved[index].0, final_usage);
+			}
+		}
+		tracker.flush(device_ctx, cmd_buff);
+		for &(image, _) in &pool.images {
+			tracker.unregister(image);
+		}
+	}
+
+	// the transient images some scheduled pass uses: their indices, descs and (first, last)
+	// positions in `order`
+	fn transient_lifetimes(
+		&self,
+		order: &[usize],
+	) -> (Vec<usize>, Vec<TransientImageDesc>, Vec<(usize, usize)>) {
+		let mut transient_indices: Vec<usize> = Vec::new();
+		let mut transient_descs: Vec<TransientImageDesc> = Vec::new();
+		let mut lifetimes: Vec<(usize, usize)> = Vec::new();
+		for (index, image) in self.images.iter().enumerate() {
+			let ImageSource::Transient(desc) = image.source else {
+				continue;
+			};
+			let touched: Vec<usize> = order
+				.iter()
+				.enumerate()
+				.filter(|&(_, &pass_idx)| self.pass_touches_image(pass_idx, index))
+				.map(|(position, _)| position)
+				.collect();
+			if let (Some(&first), Some(&last)) = (touched.first(), touched.last()) {
+				transient_indices.push(index);
+				transient_descs.push(desc);
+				lifetimes.push((first, last));
+			}
+		}
+		(transient_indices, transient_descs, lifetimes)
+	}
+
+	fn pass_touches_image(&self, pass_idx: usize, image_index: usize) -> bool {
+		let pass = &self.passes[pass_idx];
+		pass.image_reads
+			.iter()
+			.any(|(handle, _)| handle.index == image_index)
+			|| pass
+				.image_writes
+				.iter()
+				.any(|write| write.handle.index == image_index)
+	}
+
+	fn attachment_info(
+		view: vk::ImageView,
+		layout: vk::ImageLayout,
+		load: AttachmentLoad,
+	) -> vk::RenderingAttachmentInfo<'static> {
+		let (load_op, clear_value) = match load {
+			AttachmentLoad::Clear(clear_value) => (vk::AttachmentLoadOp::CLEAR, clear_value),
+			AttachmentLoad::Load => (vk::AttachmentLoadOp::LOAD, vk::ClearValue::default()),
+			AttachmentLoad::DontCare => {
+				(vk::AttachmentLoadOp::DONT_CARE, vk::ClearValue::default())
+			}
+		};
+		vk::RenderingAttachmentInfo {
+			image_view: view,
+			image_layout: layout,
+			load_op,
+			store_op: vk::AttachmentStoreOp::STORE,
+			clear_value,
+			..Default::default()
+		}
+	}
+
+	// graphviz dump: passes are boxes (dashed when culled), resources ellipses per version
+	pub fn to_dot(&self) -> String {
+		let live = self.live_passes();
+		let mut dot = String::from("digraph render_graph {\n\trankdir=LR;\n");
+		for (pass_idx, pass) in self.passes.iter().enumerate() {
+			let style = if live[pass_idx] { "filled" } else { "dashed" };
+			let _ = writeln!(
+				dot,
+				"\tpass_{} [label=\"{}\", shape=box, style={}, fillcolor=lightblue];",
+				pass_idx, pass.name, style
+			);
+		}
+		for (index, image) in self.images.iter().enumerate() {
+			let color = match image.source {
+				ImageSource::Imported { .. } => "orange",
+				ImageSource::Transient(_) => "palegreen",
+			};
+			for version in 0..image.producers.len() {
+				let _ = writeln!(
+					dot,
+					"\timg_{}_{} [label=\"{} v{}\", shape=ellipse, style=filled, fillcolor={}];",
+					index, version, image.name, version, color
+				);
+			}
+		}
+		for (index, buffer) in self.buffers.iter().enumerate() {
+			for version in 0..buffer.producers.len() {
+				let _ = writeln!(
+					dot,
+					"\tbuf_{}_{} [label=\"{} v{}\", shape=ellipse, style=filled, fillcolor=khaki];",
+					index, version, buffer.name, version
+				);
+			}
+		}
+		for (pass_idx, pass) in self.passes.iter().enumerate() {
+			for (handle, usage) in &pass.image_reads {
+				let _ = writeln!(
+					dot,
+					"\timg_{}_{} -> pass_{} [label=\"{:?}\"];",
+					handle.index, handle.version, pass_idx, usage
+				);
+			}
+			for write in &pass.image_writes {
+				let _ = writeln!(
+					dot,
+					"\tpass_{} -> img_{}_{} [label=\"{:?}\"];",
+					pass_idx, write.handle.index, write.handle.version, write.usage
+				);
+			}
+			for (handle, usage) in &pass.buffer_reads {
+				let _ = writeln!(
+					dot,
+					"\tbuf_{}_{} -> pass_{} [label=\"{:?}\"];",
+					handle.index, handle.version, pass_idx, usage
+				);
+			}
+			for (handle, usage) in &pass.buffer_writes {
+				let _ = writeln!(
+					dot,
+					"\tpass_{} -> buf_{}_{} [label=\"{:?}\"];",
+					pass_idx, handle.index, handle.version, usage
+				);
+			}
+		}
+		dot.push_str("}\n");
+		dot
+	}
+}
+
+pub struct PassBuilder<'g, 'a> {
+	graph: &'g mut RenderGraph<'a>,
+	pass: usize,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+	pub fn read_image(&mut self, handle: ImageHandle, usage: ImageUsage) {
+		self.graph.passes[self.pass]
+			.image_reads
+			.push((handle, usage));
+	}
+
+	pub fn write_image(&mut self, handle: ImageHandle, usage: ImageUsage) -> ImageHandle {
+		self.push_image_write(handle, usage, true)
+	}
+
+	pub fn color_attachment(&mut self, handle: ImageHandle, load: AttachmentLoad) -> ImageHandle {
+		let preserves = matches!(load, AttachmentLoad::Load);
+		let written = self.push_image_write(handle, ImageUsage::ColorAttachment, preserves);
+		self.graph.passes[self.pass]
+			.color_attachments
+			.push((written, load));
+		written
+	}
+
+	pub fn depth_attachment(&mut self, handle: ImageHandle, load: AttachmentLoad) -> ImageHandle {
+		let preserves = matches!(load, AttachmentLoad::Load);
+		let written = self.push_image_write(handle, ImageUsage::DepthAttachment, preserves);
+		self.graph.passes[self.pass].depth_attachment = Some((written, load));
+		written
+	}
+
+	pub fn read_buffer(&mut self, handle: BufferHandle, usage: BufferUsage) {
+		self.graph.passes[self.pass]
+			.buffer_reads
+			.push((handle, usage));
+	}
+
+	pub fn write_buffer(&mut self, handle: BufferHandle, usage: BufferUsage) -> BufferHandle {
+		let buffer = &mut self.graph.buffers[handle.index];
+		assert_eq!(
+			handle.version as usize,
+			buffer.producers.len() - 1,
+			"Should only write the latest version of buffer {}",
+			buffer.name
+		);
+		buffer.producers.push(Some(self.pass));
+		let written = BufferHandle {
+			index: handle.index,
+			version: handle.version + 1,
+		};
+		self.graph.passes[self.pass]
+			.buffer_writes
+			.push((written, usage));
+		written
+	}
+
+	// never culled, for passes whose results leave the graph some other way (readbacks etc)
+	pub fn side_effects(&mut self) {
+		self.graph.passes[self.pass].side_effects = true;
+	}
+
+	pub fn execute<F: FnOnce(&PassContext) + 'a>(self, execute: F) {
+		self.graph.passes[self.pass].execute = Some(Box::new(execute));
+	}
+
+	fn push_image_write(
+		&mut self,
+		handle: ImageHandle,
+		usage: ImageUsage,
+		preserves: bool,
+	) -> ImageHandle {
+		let image = &mut self.graph.images[handle.index];
+		assert_eq!(
+			handle.version as usize,
+			image.producers.len() - 1,
+			"Should only write the latest version of image {}",
+			image.name
+		);
+		image.producers.push(Some(self.pass));
+		let written = ImageHandle {
+			index: handle.index,
+			version: handle.version + 1,
+		};
+		self.graph.passes[self.pass].image_writes.push(ImageWrite {
+			handle: written,
+			usage,
+			preserves,
+		});
+		written
+	}
+}
+
+// first fit: a transient can move into a block once everything in it is dead. returns each
+// transient's offset, the previous occupant of the memory it reuses and the total size
+fn place_transients(
+	requirements: &[vk::MemoryRequirements],
+	lifetimes: &[(usize, usize)],
+) -> (Vec<vk::DeviceSize>, Vec<Option<usize>>, vk::DeviceSize) {
+	struct Block {
+		offset: vk::DeviceSize,
+		size: vk::DeviceSize,
+		busy_until: usize,
+		occupant: usize,
+	}
+	let mut blocks: Vec<Block> = Vec::new();
+	let mut offsets = vec![0; requirements.len()];
+	let mut aliases = vec![None; requirements.len()];
+	let mut heap_size: vk::DeviceSize = 0;
+	let mut by_start: Vec<usize> = (0..requirements.len()).collect();
+	by_start.sort_by_key(|&idx| lifetimes[idx].0);
+	for idx in by_start {
+		let req = requirements[idx];
+		let (start, end) = lifetimes[idx];
+		let reusable = blocks.iter_mut().find(|block| {
+			block.busy_until < start && block.size >= req.size && block.offset % req.alignment == 0
+		});
+		match reusable {
+			Some(block) => {
+				offsets[idx] = block.offset;
+				aliases[idx] = Some(block.occupant);
+				block.busy_until = end;
+				block.occupant = idx;
+			}
+			None => {
+				let offset = heap_size.next_multiple_of(req.alignment);
+				offsets[idx] = offset;
+				heap_size = offset + req.size;
+				blocks.push(Block {
+					offset,
+					size: req.size,
+					busy_until: end,
+					occupant: idx,
+				});
+			}
+		}
+	}
+
+	(offsets, aliases, heap_size)
+}
+
+// transients that share at least one memory type go into the same heap. there's no single type
+// every image accepts on some drivers (e.g. depth vs color), so this can hand back several
+fn group_by_memory_type(requirements: &[vk::MemoryRequirements]) -> Vec<(u32, Vec<usize>)> {
+	let mut groups: Vec<(u32, Vec<usize>)> = Vec::new();
+	for (idx, req) in requirements.iter().enumerate() {
+		match groups
+			.iter_mut()
+			.find(|(bits, _)| bits & req.memory_type_bits != 0)
+		{
+			Some((bits, members)) => {
+				*bits &= req.memory_type_bits;
+				members.push(idx);
+			}
+			None => groups.push((req.memory_type_bits, vec![idx])),
+		}
+	}
+	groups
+}
+
+struct TransientLayout {
+	// one per allocation, `size` is the heap size and `memory_type_bits` what it can live in
+	heaps: Vec<vk::MemoryRequirements>,
+	// per transient: which heap, where in it and who was there before
+	heap_of: Vec<usize>,
+	offsets: Vec<vk::DeviceSize>,
+	aliases: Vec<Option<usize>>,
+}
+
+// place_transients per memory type group, so memory is only reused within a heap
+fn layout_transients(
+	requirements: &[vk::MemoryRequirements],
+	lifetimes: &[(usize, usize)],
+) -> TransientLayout {
+	let mut layout = TransientLayout {
+		heaps: Vec::new(),
+		heap_of: vec![0; requirements.len()],
+		offsets: vec![0; requirements.len()],
+		aliases: vec![None; requirements.len()],
+	};
+	for (heap, (memory_type_bits, members)) in
+		group_by_memory_type(requirements).into_iter().enumerate()
+	{
+		let group_requirements: Vec<vk::MemoryRequirements> =
+			members.iter().map(|&idx| requirements[idx]).collect();
+		let group_lifetimes: Vec<(usize, usize)> =
+			members.iter().map(|&idx| lifetimes[idx]).collect();
+		let (offsets, aliases, size) = place_transients(&group_requirements, &group_lifetimes);
+		for (slot, &idx) in members.iter().enumerate() {
+			layout.heap_of[idx] = heap;
+			layout.offsets[idx] = offsets[slot];
+			layout.aliases[idx] = aliases[slot].map(|prev| members[prev]);
+		}
+		layout.heaps.push(vk::MemoryRequirements {
+			size,
+			alignment: 1,
+			memory_type_bits,
+		});
+	}
+	layout
+}
+
+// owns the images + memory behind transient graph resources. one per frame in flight, and it
+// only reallocates when the set of transients (or how they overlap) changes
+#[derive(Default)]
+pub struct TransientPool {
+	key: Vec<(TransientImageDesc, (usize, usize))>,
+	memory: Vec<vk::DeviceMemory>,
+	pub images: Vec<(vk::Image, vk::ImageView)>,
+	// previous occupant of the memory each transient reuses
+	pub aliases: Vec<Option<usize>>,
+}
+
+impl TransientPool {
+	pub fn new() -> TransientPool {
+		TransientPool::default()
+	}
+
+	// true when the images were (re)created
+	fn prepare(
+		&mut self,
+		device_ctx: &DeviceContext,
+		descs: &[TransientImageDesc],
+		lifetimes: &[(usize, usize)],
+	) -> bool {
+		let key: Vec<(TransientImageDesc, (usize, usize))> = descs
+			.iter()
+			.copied()
+			.zip(lifetimes.iter().copied())
+			.collect();
+		if key == self.key {
+			return false;
+		}
+		let device = device_ctx.device();
+		self.cleanup(device);
+		self.key = key;
+		if descs.is_empty() {
+			return false;
+		}
+
+		let images: Vec<vk::Image> = descs
+			.iter()
+			.map(|desc| {
+				let image_info = vk::ImageCreateInfo {
+					image_type: vk::ImageType::TYPE_2D,
+					format: desc.format,
+					extent: vk::Extent3D {
+						width: desc.extent.width,
+						height: desc.extent.height,
+						depth: 1,
+					},
+					mip_levels: 1,
+					array_layers: 1,
+					samples: vk::SampleCountFlags::TYPE_1,
+					tiling: vk::ImageTiling::OPTIMAL,
+					usage: desc.usage,
+					sharing_mode: vk::SharingMode::EXCLUSIVE,
+					initial_layout: vk::ImageLayout::UNDEFINED,
+					..Default::default()
+				};
+				unsafe {
+					device
+						.create_image(&image_info, None)
+						.expect("Should have been able to create transient image")
+				}
+			})
+			.collect();
+		let requirements: Vec<vk::MemoryRequirements> = images
+			.iter()
+			.map(|&image| unsafe { device.get_image_memory_requirements(image) })
+			.collect();
+
+		let layout = layout_transients(&requirements, lifetimes);
+		self.memory = layout
+			.heaps
+			.iter()
+			.map(|&heap| device_ctx.allocate_memory(heap, vk::MemoryPropertyFlags::DEVICE_LOCAL))
+			.collect();
+		self.images = images
+			.iter()
+			.zip(descs)
+			.zip(layout.heap_of.iter().zip(&layout.offsets))
+			.map(|((&image, desc), (&heap, &offset))| {
+				unsafe {
+					device
+						.bind_image_memory(image, self.memory[heap], offset)
+						.expect("Should have been able to bind transient image memory")
+				};
+				let view_info = vk::ImageViewCreateInfo {
+					image,
+					view_type: vk::ImageViewType::TYPE_2D,
+					format: desc.format,
+					subresource_range: vk::ImageSubresourceRange {
+						aspect_mask: aspect_for_format(desc.format),
+						base_mip_level: 0,
+						level_count: 1,
+						base_array_layer: 0,
+						layer_count: 1,
+					},
+					..Default::default()
+				};
+				let view = unsafe {
+					device
+						.create_image_view(&view_info, None)
+						.expect("Should have been able to create transient image view")
+				};
+				(image, view)
+			})
+			.collect();
+		self.aliases = layout.aliases;
+		log::info!(
+			"render graph: {} transient images in {} bytes over {} allocations",
+			descs.len(),
+			layout
+				.heaps
+				.iter()
+				.map(|heap| heap.size)
+				.sum::<vk::DeviceSize>(),
+			layout.heaps.len()
+		);
+		for &memory in &self.memory {
+			device_ctx.set_name(memory, "transient memory");
+		}
+		true
+	}
+
+	pub fn cleanup(&mut self, device: &Device) {
+		unsafe {
+			for (image, view) in self.images.drain(..) {
+				device.destroy_image_view(view, None);
+				device.destroy_image(image, None);
+			}
+			for memory in self.memory.drain(..) {
+				device.free_memory(memory, None);
+			}
+		}
+		self.aliases.clear();
+		self.key.clear();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn color_desc() -> TransientImageDesc {
+		TransientImageDesc {
+			format: vk::Format::R8G8B8A8_UNORM,
+			extent: vk::Extent2D {
+				width: 4,
+				height: 4,
+			},
+			usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+		}
+	}
+
+	fn swapchain(graph: &mut RenderGraph) -> ImageHandle {
+		graph.import_image(
+			"swapchain",
+			vk::Image::null(),
+			vk::ImageView::null(),
+			vk::Extent2D {
+				width: 4,
+				height: 4,
+			},
+		)
+	}
+
+	fn names(graph: &RenderGraph, order: &[usize]) -> Vec<String> {
+		order
+			.iter()
+			.map(|&pass| graph.passes[pass].name.clone())
+			.collect()
+	}
+
+	#[test]
+	fn schedules_by_dependency_not_declaration() {
+		let mut graph = RenderGraph::new();
+		let target = swapchain(&mut graph);
+		let shadow = graph.create_image("shadow", color_desc());
+		// declared first but reads what "shadow" writes, which is declared afterwards
+		let shadow_v1 = ImageHandle {
+			index: shadow.index,
+			version: 1,
+		};
+		let mut pass = graph.add_pass("lighting");
+		pass.read_image(shadow_v1, ImageUsage::SampledFragment);
+		let target = pass.color_attachment(target, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("shadow");
+		let written = pass.color_attachment(shadow, AttachmentLoad::DontCare);
+		assert_eq!(written, shadow_v1);
+		graph.export_image(target, Some(ImageUsage::Present));
+
+		let live = graph.live_passes();
+		assert_eq!(live, vec![true, true]);
+		let order = graph.schedule(&live);
+		assert_eq!(names(&graph, &order), ["shadow", "lighting"]);
+	}
+
+	#[test]
+	fn culls_passes_nothing_exported_depends_on() {
+		let mut graph = RenderGraph::new();
+		let target = swapchain(&mut graph);
+		let unused = graph.create_image("unused", color_desc());
+		let mut pass = graph.add_pass("dead");
+		pass.color_attachment(unused, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("main");
+		let target = pass.color_attachment(target, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("debug");
+		pass.side_effects();
+		graph.export_image(target, Some(ImageUsage::Present));
+
+		let live = graph.live_passes();
+		assert_eq!(live, vec![false, true, true]);
+		assert_eq!(names(&graph, &graph.schedule(&live)), ["main", "debug"]);
+	}
+
+	#[test]
+	fn cleared_attachment_does_not_keep_earlier_writer_alive() {
+		let mut graph = RenderGraph::new();
+		let target = swapchain(&mut graph);
+		let mut pass = graph.add_pass("overwritten");
+		let target = pass.color_attachment(target, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("final");
+		let target = pass.color_attachment(target, AttachmentLoad::DontCare);
+		graph.export_image(target, None);
+
+		assert_eq!(graph.live_passes(), vec![false, true]);
+	}
+
+	#[test]
+	fn non_overlapping_transients_share_memory() {
+		let mut graph = RenderGraph::new();
+		let target = swapchain(&mut graph);
+		let a = graph.create_image("a", color_desc());
+		let b = graph.create_image("b", color_desc());
+		let mut pass = graph.add_pass("write a");
+		let a = pass.color_attachment(a, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("read a");
+		pass.read_image(a, ImageUsage::SampledFragment);
+		let target = pass.color_attachment(target, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("write b");
+		let b = pass.color_attachment(b, AttachmentLoad::DontCare);
+		let mut pass = graph.add_pass("read b");
+		pass.read_image(b, ImageUsage::SampledFragment);
+		let target = pass.color_attachment(target, AttachmentLoad::Load);
+		graph.export_image(target, Some(ImageUsage::Present));
+
+		let live = graph.live_passes();
+		let order = graph.schedule(&live);
+		assert_eq!(
+			names(&graph, &order),
+			["write a", "read a", "write b", "read b"]
+		);
+		let (indices, _, lifetimes) = graph.transient_lifetimes(&order);
+		assert_eq!(indices, [1, 2]);
+		assert_eq!(lifetimes, [(0, 1), (2, 3)]);
+
+		let requirements = [vk::MemoryRequirements {
+			size: 256,
+			alignment: 64,
+			memory_type_bits: 1,
+		}; 2];
+		let (offsets, aliases, heap_size) = place_transients(&requirements, &lifetimes);
+		assert_eq!(offsets, [0, 0]);
+		assert_eq!(aliases, [None, Some(0)]);
+		assert_eq!(heap_size, 256);
+	}
+
+	#[test]
+	fn overlapping_transients_get_their_own_memory() {
+		let requirements = [
+			vk::MemoryRequirements {
+				size: 100,
+				alignment: 64,
+				memory_type_bits: 1,
+			},
+			vk::MemoryRequirements {
+				size: 256,
+				alignment: 64,
+				memory_type_bits: 1,
+			},
+		];
+		let (offsets, aliases, heap_size) = place_transients(&requirements, &[(0, 2), (1, 3)]);
+		assert_eq!(offsets, [0, 128]);
+		assert_eq!(aliases, [None, None]);
+		assert_eq!(heap_size, 384);
+	}
+
+	#[test]
+	fn larger_transient_does_not_reuse_smaller_block() {
+		let requirements = [
+			vk::MemoryRequirements {
+				size: 64,
+				alignment: 64,
+				memory_type_bits: 1,
+			},
+			vk::MemoryRequirements {
+				size: 128,
+				alignment: 64,
+				memory_type_bits: 1,
+			},
+		];
+		let (offsets, aliases, _) = place_transients(&requirements, &[(0, 0), (1, 1)]);
+		assert_eq!(offsets, [0, 64]);
+		assert_eq!(aliases, [None, None]);
+	}
+
+	fn requirement(size: vk::DeviceSize, memory_type_bits: u32) -> vk::MemoryRequirements {
+		vk::MemoryRequirements {
+			size,
+			alignment: 64,
+			memory_type_bits,
+		}
+	}
+
+	#[test]
+	fn groups_narrow_to_shared_memory_types() {
+		let requirements = [
+			requirement(64, 0b011),
+			requirement(64, 0b110),
+			requirement(64, 0b100),
+			requirement(64, 0b001),
+		];
+		assert_eq!(
+			group_by_memory_type(&requirements),
+			[(0b010, vec![0, 1]), (0b100, vec![2]), (0b001, vec![3])]
+		);
+	}
+
+	#[test]
+	fn disjoint_memory_types_get_separate_heaps() {
+		// the intersection of all of these is empty, a single allocation can't hold them
+		let requirements = [
+			requirement(256, 0b01),
+			requirement(128, 0b10),
+			requirement(256, 0b01),
+			requirement(128, 0b10),
+		];
+		let lifetimes = [(0, 0), (0, 0), (1, 1), (1, 1)];
+		let layout = layout_transients(&requirements, &lifetimes);
+		assert_eq!(layout.heaps.len(), 2);
+		assert_eq!(layout.heaps[0].memory_type_bits, 0b01);
+		assert_eq!(layout.heaps[0].size, 256);
+		assert_eq!(layout.heaps[1].memory_type_bits, 0b10);
+		assert_eq!(layout.heaps[1].size, 128);
+		assert_eq!(layout.heap_of, [0, 1, 0, 1]);
+		assert_eq!(layout.offsets, [0, 0, 0, 0]);
+		// aliases refer back to transient indices, and never cross heaps
+		assert_eq!(layout.aliases, [None, None, Some(0), Some(1)]);
+	}
+
+	#[test]
+	fn compatible_memory_types_share_one_heap() {
+		let requirements = [requirement(256, 0b11), requirement(256, 0b01)];
+		let layout = layout_transients(&requirements, &[(0, 0), (1, 1)]);
+		assert_eq!(layout.heaps.len(), 1);
+		assert_eq!(layout.heaps[0].memory_type_bits, 0b01);
+		assert_eq!(layout.heaps[0].size, 256);
+		assert_eq!(layout.aliases, [None, Some(0)]);
+	}
+}
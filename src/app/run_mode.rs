@@ -0,0 +1,146 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+// how the runner decides when to draw
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum RunMode {
+	// redraw as fast as possible, one core stays busy
+	#[default]
+	Continuous,
+	// sleep until there's input, a window event or an explicit RunControl::request_redraw
+	Reactive,
+	// redraw at most this many times a second, sleeping in between. RunControl turns caps that
+	// don't give a usable interval (zero, negative, NaN, infinite) into Reactive
+	CappedFps(f64),
+}
+
+impl RunMode {
+	// time between frames in CappedFps mode, None if the cap doesn't give a positive one
+	pub fn frame_interval(&self) -> Option<Duration> {
+		match *self {
+			RunMode::CappedFps(fps) => Duration::try_from_secs_f64(1. / fps)
+				.ok()
+				.filter(|interval| !interval.is_zero()),
+			_ => None,
+		}
+	}
+
+	// an invalid cap would leave the runner spinning on a zero interval
+	fn sanitized(self) -> RunMode {
+		match self {
+			RunMode::CappedFps(fps) if self.frame_interval().is_none() => {
+				log::warn!("Invalid fps cap {}, running reactive instead", fps);
+				RunMode::Reactive
+			}
+			mode => mode,
+		}
+	}
+}
+
+struct RunControlState {
+	mode: Cell<RunMode>,
+	redraw: Cell<bool>,
+}
+
+// handed to App::init. clones share the same state, so the app can keep one around and switch
+// modes or ask for a frame from any of its hooks
+#[derive(Clone)]
+pub struct RunControl {
+	state: Rc<RunControlState>,
+}
+
+impl RunControl {
+	pub fn new(mode: RunMode) -> RunControl {
+		RunControl {
+			state: Rc::new(RunControlState {
+				mode: Cell::new(mode.sanitized()),
+				// the first frame always gets drawn
+				redraw: Cell::new(true),
+			}),
+		}
+	}
+
+	pub fn mode(&self) -> RunMode {
+		self.state.mode.get()
+	}
+
+	pub fn set_mode(&self, mode: RunMode) {
+		let mode = mode.sanitized();
+		if self.state.mode.replace(mode) != mode {
+			log::debug!("run mode {:?}", mode);
+			self.request_redraw();
+		}
+	}
+
+	// draw one more frame, mostly for Reactive mode where nothing else would
+	pub fn request_redraw(&self) {
+		self.state.redraw.set(true);
+	}
+
+	pub fn take_redraw(&self) -> bool {
+		self.state.redraw.replace(false)
+	}
+}
+
+impl Default for RunControl {
+	fn default() -> RunControl {
+		RunControl::new(RunMode::default())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn frame_interval_of_valid_caps() {
+		assert_eq!(
+			RunMode::CappedFps(50.).frame_interval(),
+			Some(Duration::from_millis(20))
+		);
+		assert_eq!(
+			RunMode::CappedFps(0.5).frame_interval(),
+			Some(Duration::from_secs(2))
+		);
+		assert_eq!(RunMode::Continuous.frame_interval(), None);
+		assert_eq!(RunMode::Reactive.frame_interval(), None);
+	}
+
+	#[test]
+	fn invalid_caps_become_reactive() {
+		for fps in [
+			0.,
+			-0.,
+			-30.,
+			f64::NAN,
+			f64::INFINITY,
+			f64::NEG_INFINITY,
+			1e-300,
+		] {
+			assert_eq!(
+				RunMode::CappedFps(fps).frame_interval(),
+				None,
+				"fps {}",
+				fps
+			);
+			let control = RunControl::new(RunMode::CappedFps(fps));
+			assert_eq!(control.mode(), RunMode::Reactive, "fps {}", fps);
+			control.set_mode(RunMode::Continuous);
+			control.set_mode(RunMode::CappedFps(fps));
+			assert_eq!(control.mode(), RunMode::Reactive, "fps {}", fps);
+		}
+	}
+
+	#[test]
+	fn set_mode_requests_a_redraw_on_change() {
+		let control = RunControl::new(RunMode::Reactive);
+		assert!(control.take_redraw());
+		assert!(!control.take_redraw());
+		control.set_mode(RunMode::Reactive);
+		assert!(!control.take_redraw());
+		control.set_mode(RunMode::CappedFps(30.));
+		assert_eq!(control.mode(), RunMode::CappedFps(30.));
+		assert!(control.take_redraw());
+	}
+}
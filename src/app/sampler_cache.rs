@@ -0,0 +1,108 @@
+use super::{Device, DeviceContext, vk};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// everything that makes two samplers different. anisotropy is a whole number (0 = off)
+// so the desc stays hashable
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SamplerDesc {
+	pub mag_filter: vk::Filter,
+	pub min_filter: vk::Filter,
+	pub mipmap_mode: vk::SamplerMipmapMode,
+	pub address_mode_u: vk::SamplerAddressMode,
+	pub address_mode_v: vk::SamplerAddressMode,
+	pub address_mode_w: vk::SamplerAddressMode,
+	pub max_anisotropy: u32,
+}
+
+impl Default for SamplerDesc {
+	fn default() -> Self {
+		SamplerDesc::linear(vk::SamplerAddressMode::REPEAT)
+	}
+}
+
+impl SamplerDesc {
+	pub fn linear(address_mode: vk::SamplerAddressMode) -> SamplerDesc {
+		SamplerDesc {
+			mag_filter: vk::Filter::LINEAR,
+			min_filter: vk::Filter::LINEAR,
+			mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+			address_mode_u: address_mode,
+			address_mode_v: address_mode,
+			address_mode_w: address_mode,
+			max_anisotropy: 0,
+		}
+	}
+	pub fn nearest(address_mode: vk::SamplerAddressMode) -> SamplerDesc {
+		SamplerDesc {
+			mag_filter: vk::Filter::NEAREST,
+			min_filter: vk::Filter::NEAREST,
+			mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+			..SamplerDesc::linear(address_mode)
+		}
+	}
+	pub fn with_anisotropy(mut self, max_anisotropy: u32) -> SamplerDesc {
+		self.max_anisotropy = max_anisotropy;
+		self
+	}
+}
+
+// samplers are tiny and there are only ever a handful of distinct ones, so hand out the same
+// handle for the same desc instead of creating one per texture
+#[derive(Default)]
+pub struct SamplerCache {
+	samplers: RefCell<HashMap<SamplerDesc, vk::Sampler>>,
+}
+
+impl SamplerCache {
+	pub fn new() -> SamplerCache {
+		SamplerCache::default()
+	}
+
+	pub fn get(&self, device_ctx: &DeviceContext, desc: &SamplerDesc) -> vk::Sampler {
+		if let Some(&sampler) = self.samplers.borrow().get(desc) {
+			return sampler;
+		}
+		let sampler = SamplerCache::create_sampler(device_ctx, desc);
+		self.samplers.borrow_mut().insert(*desc, sampler);
+		sampler
+	}
+
+	fn create_sampler(device_ctx: &DeviceContext, desc: &SamplerDesc) -> vk::Sampler {
+		// clamp to what the device can do, and drop it entirely if the feature is off
+		let anisotropy_enabled = desc.max_anisotropy > 1
+			&& device_ctx.enabled_features.features.core.sampler_anisotropy == vk::TRUE;
+		let max_anisotropy =
+			(desc.max_anisotropy as f32).min(device_ctx.properties.limits.max_sampler_anisotropy);
+		let sampler_info = vk::SamplerCreateInfo {
+			mag_filter: desc.mag_filter,
+			min_filter: desc.min_filter,
+			mipmap_mode: desc.mipmap_mode,
+			address_mode_u: desc.address_mode_u,
+			address_mode_v: desc.address_mode_v,
+			address_mode_w: desc.address_mode_w,
+			anisotropy_enable: anisotropy_enabled as vk::Bool32,
+			max_anisotropy: if anisotropy_enabled {
+				max_anisotropy
+			} else {
+				1.
+			},
+			min_lod: 0.,
+			max_lod: vk::LOD_CLAMP_NONE,
+			border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+			..Default::default()
+		};
+		unsafe {
+			device_ctx
+				.device()
+				.create_sampler(&sampler_info, None)
+				.expect("Should have been able to create sampler")
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		for (_, sampler) in self.samplers.borrow_mut().drain() {
+			unsafe { device.destroy_sampler(sampler, None) };
+		}
+	}
+}
@@ -0,0 +1,474 @@
+use super::{
+	AlphaMode, Buffer, Device, DeviceContext, Gltf, InstanceContext, Material, Mesh,
+	PipelineContext, PipelineDesc, SamplerCache, SamplerDesc, Texture, TextureRef, Vertex, vk,
+};
+use cgmath::{Matrix, Matrix4, SquareMatrix};
+use std::collections::HashMap;
+
+// what the scene pipelines expect their depth attachment in
+pub const SCENE_DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+// base color, metallic-roughness, normal, occlusion, emissive at bindings 1..=5 of set 1
+const MATERIAL_TEXTURE_COUNT: usize = 5;
+
+// set 1 binding 0, std140 compatible as laid out
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialUniforms {
+	pub base_color_factor: [f32; 4],
+	// w unused
+	pub emissive_factor: [f32; 4],
+	pub metallic_factor: f32,
+	pub roughness_factor: f32,
+	pub normal_scale: f32,
+	pub occlusion_strength: f32,
+	// 0 unless the material is AlphaMode::Mask
+	pub alpha_cutoff: f32,
+	pub _pad: [f32; 3],
+}
+
+impl MaterialUniforms {
+	pub fn as_bytes(&self) -> &[u8] {
+		unsafe {
+			std::slice::from_raw_parts(
+				self as *const MaterialUniforms as *const u8,
+				size_of::<MaterialUniforms>(),
+			)
+		}
+	}
+}
+
+// vertex stage push constants, exactly the guaranteed 128 bytes
+#[repr(C)]
+struct DrawConstants {
+	model: [[f32; 4]; 4],
+	// inverse transpose of model, only the upper 3x3 matters
+	normal: [[f32; 4]; 4],
+}
+
+// pipelines + material layout for drawing Scenes. set 0 is the camera (VkSwap's layout),
+// set 1 the material, the model matrix comes in push constants
+pub struct SceneRenderer {
+	pub pipeline: PipelineContext,
+	// same thing without back face culling, for double sided materials
+	pub double_sided_pipeline: PipelineContext,
+	pub material_set_layout: vk::DescriptorSetLayout,
+	pub sampler_cache: SamplerCache,
+}
+
+impl SceneRenderer {
+	// `shader_code` is spirv with vertMain and fragMain, see shaders/scene.slang
+	pub fn new(
+		device_ctx: &DeviceContext,
+		shader_code: &[u8],
+		color_format: vk::Format,
+		camera_set_layout: vk::DescriptorSetLayout,
+	) -> SceneRenderer {
+		let material_set_layout = SceneRenderer::create_material_set_layout(device_ctx.device());
+		device_ctx.set_name(material_set_layout, "material set layout");
+		let set_layouts = [camera_set_layout, material_set_layout];
+		let push_constants = [vk::PushConstantRange {
+			stage_flags: vk::ShaderStageFlags::VERTEX,
+			offset: 0,
+			size: size_of::<DrawConstants>() as u32,
+		}];
+		let bindings = [Vertex::binding_description()];
+		let attributes = Vertex::attribute_descriptions();
+		let desc = PipelineDesc::new(color_format)
+			.with_depth(SCENE_DEPTH_FORMAT, true)
+			.with_set_layouts(&set_layouts)
+			.with_push_constants(&push_constants)
+			.with_vertex_input(&bindings, &attributes);
+		let pipeline = PipelineContext::with_desc(device_ctx, shader_code, &desc, "scene pipeline");
+		let double_sided_pipeline = PipelineContext::with_desc(
+			device_ctx,
+			shader_code,
+			&desc.with_cull_mode(vk::CullModeFlags::NONE),
+			"scene double sided pipeline",
+		);
+
+		SceneRenderer {
+			pipeline,
+			double_sided_pipeline,
+			material_set_layout,
+			sampler_cache: SamplerCache::new(),
+		}
+	}
+
+	fn create_material_set_layout(device: &Device) -> vk::DescriptorSetLayout {
+		let mut bindings = vec![vk::DescriptorSetLayoutBinding {
+			binding: 0,
+			descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+			descriptor_count: 1,
+			stage_flags: vk::ShaderStageFlags::FRAGMENT,
+			..Default::default()
+		}];
+		bindings.extend((1..=MATERIAL_TEXTURE_COUNT as u32).map(|binding| {
+			vk::DescriptorSetLayoutBinding {
+				binding,
+				descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+				descriptor_count: 1,
+				stage_flags: vk::ShaderStageFlags::FRAGMENT,
+				..Default::default()
+			}
+		}));
+		let layout_info = vk::DescriptorSetLayoutCreateInfo {
+			binding_count: bindings.len() as u32,
+			p_bindings: bindings.as_ptr(),
+			..Default::default()
+		};
+		unsafe {
+			device
+				.create_descriptor_set_layout(&layout_info, None)
+				.expect("Should have been able to create material set layout")
+		}
+	}
+
+	// inside a pass with a color and a SCENE_DEPTH_FORMAT depth attachment. opaque and masked
+	// materials first, blended ones after them (unsorted)
+	pub fn draw(
+		&self,
+		device: &Device,
+		cmd_buff: vk::CommandBuffer,
+		scene: &Scene,
+		camera_set: vk::DescriptorSet,
+	) {
+		let layout = self.pipeline.pipeline_layout;
+		// both pipelines share the layout, so sets stay bound across the switch
+		unsafe {
+			device.cmd_bind_descriptor_sets(
+				cmd_buff,
+				vk::PipelineBindPoint::GRAPHICS,
+				layout,
+				0,
+				&[camera_set],
+				&[],
+			);
+		}
+		let mut bound_pipeline = vk::Pipeline::null();
+		for blended in [false, true] {
+			for (world, mesh) in &scene.instances {
+				let constants = DrawConstants {
+					model: (*world).into(),
+					normal: world.invert().unwrap_or(*world).transpose().into(),
+				};
+				for primitive in &scene.meshes[*mesh] {
+					let material = &scene.materials[primitive.material];
+					if material.blended != blended {
+						continue;
+					}
+					let pipeline = if material.double_sided {
+						self.double_sided_pipeline.graphics_pipeline
+					} else {
+						self.pipeline.graphics_pipeline
+					};
+					unsafe {
+						if pipeline != bound_pipeline {
+							device.cmd_bind_pipeline(
+								cmd_buff,
+								vk::PipelineBindPoint::GRAPHICS,
+								pipeline,
+							);
+							bound_pipeline = pipeline;
+						}
+						device.cmd_bind_descriptor_sets(
+							cmd_buff,
+							vk::PipelineBindPoint::GRAPHICS,
+							layout,
+							1,
+							&[material.set],
+							&[],
+						);
+						device.cmd_push_constants(
+							cmd_buff,
+							layout,
+							vk::ShaderStageFlags::VERTEX,
+							0,
+							std::slice::from_raw_parts(
+								&constants as *const DrawConstants as *const u8,
+								size_of::<DrawConstants>(),
+							),
+						);
+					}
+					primitive.mesh.draw(device, cmd_buff);
+				}
+			}
+		}
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		self.pipeline.cleanup(device);
+		self.double_sided_pipeline.cleanup(device);
+		self.sampler_cache.cleanup(device);
+		unsafe { device.destroy_descriptor_set_layout(self.material_set_layout, None) };
+	}
+}
+
+struct SceneMaterial {
+	uniforms: Buffer,
+	set: vk::DescriptorSet,
+	double_sided: bool,
+	blended: bool,
+}
+
+struct ScenePrimitive {
+	mesh: Mesh,
+	// index into Scene::materials, the default material is the last one
+	material: usize,
+}
+
+// a Gltf uploaded to the gpu: one Mesh per primitive, one Texture per (image, srgb) pair the
+// materials actually use, one descriptor set per material
+pub struct Scene {
+	meshes: Vec<Vec<ScenePrimitive>>,
+	instances: Vec<(Matrix4<f32>, usize)>,
+	materials: Vec<SceneMaterial>,
+	textures: Vec<Texture>,
+	descriptor_pool: vk::DescriptorPool,
+}
+
+impl Scene {
+	pub fn new(
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		renderer: &SceneRenderer,
+		gltf: &Gltf,
+	) -> Scene {
+		let device = device_ctx.device();
+		// materials that leave a slot empty get these: white keeps the factors as they are,
+		// the flat normal leaves the vertex normal alone
+		let mut textures: Vec<Texture> = [[255; 4], [128, 128, 255, 255]]
+			.map(|pixel| {
+				Texture::from_rgba8(instance_ctx, device_ctx, 1, 1, &pixel, false)
+					.expect("Should have been able to create default texture")
+			})
+			.into();
+		textures[0].set_name(device_ctx, "default white");
+		textures[1].set_name(device_ctx, "default normal");
+		let default_slot = |slot: usize| if slot == 2 { 1 } else { 0 };
+		let mut uploaded: HashMap<(usize, bool), usize> = HashMap::new();
+
+		let default_material = Material::default();
+		let materials: Vec<&Material> = gltf
+			.materials
+			.iter()
+			.chain(std::iter::once(&default_material))
+			.collect();
+		let descriptor_pool = Scene::create_descriptor_pool(device, materials.len() as u32);
+		device_ctx.set_name(descriptor_pool, "scene descriptor pool");
+
+		let mut scene_materials = Vec::with_capacity(materials.len());
+		for (i, material) in materials.iter().enumerate() {
+			let slots: [(Option<TextureRef>, bool); MATERIAL_TEXTURE_COUNT] = [
+				(material.base_color_texture, true),
+				(material.metallic_roughness_texture, false),
+				(material.normal_texture, false),
+				(material.occlusion_texture, false),
+				(material.emissive_texture, true),
+			];
+			let mut image_infos = [vk::DescriptorImageInfo::default(); MATERIAL_TEXTURE_COUNT];
+			for (slot, (texture_ref, srgb)) in slots.into_iter().enumerate() {
+				let (texture, sampler) = match texture_ref {
+					Some(texture_ref) => {
+						if texture_ref.tex_coord != 0 {
+							log::warn!(
+								"Material {:?} samples TEXCOORD_{}, only TEXCOORD_0 is loaded",
+								material.name,
+								texture_ref.tex_coord
+							);
+						}
+						let gltf_texture = gltf.textures[texture_ref.texture];
+						let texture =
+							*uploaded
+								.entry((gltf_texture.image, srgb))
+								.or_insert_with(|| {
+									let image = &gltf.images[gltf_texture.image];
+									let texture = Texture::from_rgba8(
+										instance_ctx,
+										device_ctx,
+										image.width(),
+										image.height(),
+										image.as_raw(),
+										srgb,
+									)
+									// Gltf only hands out decoded, non empty images
+									.expect("Should have been able to upload gltf image");
+									texture.set_name(
+										device_ctx,
+										&format!("gltf image {}", gltf_texture.image),
+									);
+									textures.push(texture);
+									textures.len() - 1
+								});
+						(texture, gltf_texture.sampler)
+					}
+					None => (default_slot(slot), SamplerDesc::default()),
+				};
+				image_infos[slot] = vk::DescriptorImageInfo {
+					sampler: renderer.sampler_cache.get(device_ctx, &sampler),
+					image_view: textures[texture].view,
+					image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				};
+			}
+
+			let [r, g, b] = material.emissive_factor;
+			let uniforms = MaterialUniforms {
+				base_color_factor: material.base_color_factor,
+				emissive_factor: [r, g, b, 0.],
+				metallic_factor: material.metallic_factor,
+				roughness_factor: material.roughness_factor,
+				normal_scale: material.normal_scale,
+				occlusion_strength: material.occlusion_strength,
+				alpha_cutoff: match material.alpha_mode {
+					AlphaMode::Mask(cutoff) => cutoff,
+					_ => 0.,
+				},
+				_pad: [0.; 3],
+			};
+			let buffer = Buffer::device_local(
+				device_ctx,
+				uniforms.as_bytes(),
+				vk::BufferUsageFlags::UNIFORM_BUFFER,
+			);
+			let name = material
+				.name
+				.clone()
+				.unwrap_or_else(|| format!("material {}", i));
+			buffer.set_name(device_ctx, &name);
+			let set = Scene::create_material_set(
+				device,
+				descriptor_pool,
+				renderer.material_set_layout,
+				&buffer,
+				&image_infos,
+			);
+			device_ctx.set_name(set, &name);
+			scene_materials.push(SceneMaterial {
+				uniforms: buffer,
+				set,
+				double_sided: material.double_sided,
+				blended: material.alpha_mode == AlphaMode::Blend,
+			});
+		}
+
+		let default_material = scene_materials.len() - 1;
+		let meshes = gltf
+			.meshes
+			.iter()
+			.enumerate()
+			.map(|(i, gltf_mesh)| {
+				gltf_mesh
+					.primitives
+					.iter()
+					.filter(|primitive| !primitive.data.indices.is_empty())
+					.enumerate()
+					.map(|(j, primitive)| {
+						let mesh = Mesh::new(device_ctx, &primitive.data);
+						let name = gltf_mesh.name.as_deref().unwrap_or("mesh");
+						mesh.set_name(device_ctx, &format!("{} {}.{}", name, i, j));
+						ScenePrimitive {
+							mesh,
+							material: primitive.material.unwrap_or(default_material),
+						}
+					})
+					.collect()
+			})
+			.collect();
+		log::info!(
+			"Uploaded scene: {} meshes, {} materials, {} textures",
+			gltf.meshes.len(),
+			scene_materials.len(),
+			textures.len()
+		);
+
+		Scene {
+			meshes,
+			instances: gltf.mesh_instances(),
+			materials: scene_materials,
+			textures,
+			descriptor_pool,
+		}
+	}
+
+	fn create_descriptor_pool(device: &Device, material_count: u32) -> vk::DescriptorPool {
+		let pool_sizes = [
+			vk::DescriptorPoolSize {
+				ty: vk::DescriptorType::UNIFORM_BUFFER,
+				descriptor_count: material_count,
+			},
+			vk::DescriptorPoolSize {
+				ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+				descriptor_count: material_count * MATERIAL_TEXTURE_COUNT as u32,
+			},
+		];
+		let pool_info = vk::DescriptorPoolCreateInfo {
+			max_sets: material_count,
+			pool_size_count: pool_sizes.len() as u32,
+			p_pool_sizes: pool_sizes.as_ptr(),
+			..Default::default()
+		};
+		unsafe {
+			device
+				.create_descriptor_pool(&pool_info, None)
+				.expect("Should have been able to create scene descriptor pool")
+		}
+	}
+
+	fn create_material_set(
+		device: &Device,
+		descriptor_pool: vk::DescriptorPool,
+		layout: vk::DescriptorSetLayout,
+		uniforms: &Buffer,
+		image_infos: &[vk::DescriptorImageInfo; MATERIAL_TEXTURE_COUNT],
+	) -> vk::DescriptorSet {
+		let alloc_info = vk::DescriptorSetAllocateInfo {
+			descriptor_pool,
+			descriptor_set_count: 1,
+			p_set_layouts: &layout,
+			..Default::default()
+		};
+		let set = unsafe {
+			device
+				.allocate_descriptor_sets(&alloc_info)
+				.expect("Should have been able to allocate material descriptor set")[0]
+		};
+		let buffer_info = vk::DescriptorBufferInfo {
+			buffer: uniforms.buffer,
+			offset: 0,
+			range: uniforms.size,
+		};
+		let mut writes = vec![vk::WriteDescriptorSet {
+			dst_set: set,
+			dst_binding: 0,
+			descriptor_count: 1,
+			descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+			p_buffer_info: &buffer_info,
+			..Default::default()
+		}];
+		writes.extend(image_infos.iter().enumerate().map(|(slot, image_info)| {
+			vk::WriteDescriptorSet {
+				dst_set: set,
+				dst_binding: slot as u32 + 1,
+				descriptor_count: 1,
+				descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+				p_image_info: image_info,
+				..Default::default()
+			}
+		}));
+		unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+		set
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		for primitive in self.meshes.iter().flatten() {
+			primitive.mesh.cleanup(device);
+		}
+		for material in &self.materials {
+			material.uniforms.cleanup(device);
+		}
+		for texture in &self.textures {
+			texture.cleanup(device);
+		}
+		unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+	}
+}
@@ -0,0 +1,318 @@
+use super::VkError;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+// last `capacity` samples of something (frame times, gpu scope times), oldest dropped first
+#[derive(Clone, Debug)]
+pub struct RollingStats {
+	samples: VecDeque<f64>,
+	capacity: usize,
+}
+
+impl RollingStats {
+	pub fn new(capacity: usize) -> RollingStats {
+		RollingStats {
+			samples: VecDeque::with_capacity(capacity),
+			capacity,
+		}
+	}
+
+	pub fn push(&mut self, sample: f64) {
+		if self.samples.len() == self.capacity {
+			self.samples.pop_front();
+		}
+		self.samples.push_back(sample);
+	}
+
+	pub fn len(&self) -> usize {
+		self.samples.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.samples.is_empty()
+	}
+
+	pub fn last(&self) -> f64 {
+		self.samples.back().copied().unwrap_or(0.)
+	}
+
+	pub fn avg(&self) -> f64 {
+		if self.samples.is_empty() {
+			return 0.;
+		}
+		self.samples.iter().sum::<f64>() / self.samples.len() as f64
+	}
+
+	pub fn min(&self) -> f64 {
+		self.samples.iter().copied().reduce(f64::min).unwrap_or(0.)
+	}
+
+	pub fn max(&self) -> f64 {
+		self.samples.iter().copied().reduce(f64::max).unwrap_or(0.)
+	}
+
+	// nearest rank, `p` in 0..=100
+	pub fn percentile(&self, p: f64) -> f64 {
+		if self.samples.is_empty() {
+			return 0.;
+		}
+		let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+		sorted.sort_by(f64::total_cmp);
+		let rank = ((p / 100.) * sorted.len() as f64).ceil() as usize;
+		sorted[rank.clamp(1, sorted.len()) - 1]
+	}
+}
+
+// "1" to show the fps in the window title
+pub const FPS_TITLE_ENV_VAR: &str = "VKRS_FPS_TITLE";
+// path of a csv that gets one line per frame
+pub const FRAME_CSV_ENV_VAR: &str = "VKRS_FRAME_CSV";
+// frames the rolling stats look back over
+pub const FRAME_STATS_HISTORY: usize = 600;
+// the table gets logged (at debug) this often
+pub const FRAME_STATS_LOG_INTERVAL: u64 = 600;
+
+#[derive(Clone, Debug, Default)]
+pub struct FrameStatsConfig {
+	pub fps_in_title: bool,
+	pub csv_path: Option<PathBuf>,
+}
+
+impl FrameStatsConfig {
+	pub fn with_fps_in_title(mut self, fps_in_title: bool) -> FrameStatsConfig {
+		self.fps_in_title = fps_in_title;
+		self
+	}
+
+	pub fn with_csv(mut self, path: impl Into<PathBuf>) -> FrameStatsConfig {
+		self.csv_path = Some(path.into());
+		self
+	}
+
+	pub fn apply_env(mut self) -> Result<FrameStatsConfig, VkError> {
+		if let Ok(value) = std::env::var(FPS_TITLE_ENV_VAR)
+			&& !value.is_empty()
+		{
+			self.fps_in_title = match value.as_str() {
+				"1" | "true" | "on" => true,
+				"0" | "false" | "off" => false,
+				_ => {
+					return Err(VkError::InvalidConfig {
+						var: FPS_TITLE_ENV_VAR,
+						value,
+					});
+				}
+			};
+		}
+		if let Ok(path) = std::env::var(FRAME_CSV_ENV_VAR)
+			&& !path.is_empty()
+		{
+			self.csv_path = Some(path.into());
+		}
+		Ok(self)
+	}
+}
+
+// cpu side timings of one frame, all in ms
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+	// since the previous frame started
+	pub frame_ms: f64,
+	// waiting on the frame's timeline value before reusing its resources
+	pub wait_ms: f64,
+	pub acquire_ms: f64,
+	pub record_ms: f64,
+	pub submit_ms: f64,
+	pub present_ms: f64,
+}
+
+pub struct FrameStats {
+	pub frame: RollingStats,
+	pub wait: RollingStats,
+	pub acquire: RollingStats,
+	pub record: RollingStats,
+	pub submit: RollingStats,
+	pub present: RollingStats,
+	frame_count: u64,
+	fps_in_title: bool,
+	csv: Option<BufWriter<File>>,
+}
+
+impl FrameStats {
+	// a csv that can't be created is logged and skipped, it's not worth failing over
+	pub fn new(config: &FrameStatsConfig) -> FrameStats {
+		let csv = config.csv_path.as_ref().and_then(|path| {
+			let csv = File::create(path).and_then(|file| {
+				let mut csv = BufWriter::new(file);
+				writeln!(
+					csv,
+					"frame,frame_ms,wait_ms,acquire_ms,record_ms,submit_ms,present_ms"
+				)?;
+				Ok(csv)
+			});
+			match csv {
+				Ok(csv) => {
+					log::info!("writing frame timings to {}", path.display());
+					Some(csv)
+				}
+				Err(e) => {
+					log::error!("Could not create frame csv {}: {}", path.display(), e);
+					None
+				}
+			}
+		});
+		FrameStats {
+			frame: RollingStats::new(FRAME_STATS_HISTORY),
+			wait: RollingStats::new(FRAME_STATS_HISTORY),
+			acquire: RollingStats::new(FRAME_STATS_HISTORY),
+			record: RollingStats::new(FRAME_STATS_HISTORY),
+			submit: RollingStats::new(FRAME_STATS_HISTORY),
+			present: RollingStats::new(FRAME_STATS_HISTORY),
+			frame_count: 0,
+			fps_in_title: config.fps_in_title,
+			csv,
+		}
+	}
+
+	pub fn frame_count(&self) -> u64 {
+		self.frame_count
+	}
+
+	pub fn fps_in_title(&self) -> bool {
+		self.fps_in_title
+	}
+
+	pub fn fps(&self) -> f64 {
+		let avg = self.frame.avg();
+		if avg > 0. { 1000. / avg } else { 0. }
+	}
+
+	pub fn record(&mut self, timings: FrameTimings) {
+		self.frame.push(timings.frame_ms);
+		self.wait.push(timings.wait_ms);
+		self.acquire.push(timings.acquire_ms);
+		self.record.push(timings.record_ms);
+		self.submit.push(timings.submit_ms);
+		self.present.push(timings.present_ms);
+		if let Some(csv) = &mut self.csv {
+			let line = writeln!(
+				csv,
+				"{},{:.4},{:.4},{:.4},{:.4},{:.4},{:.4}",
+				self.frame_count,
+				timings.frame_ms,
+				timings.wait_ms,
+				timings.acquire_ms,
+				timings.record_ms,
+				timings.submit_ms,
+				timings.present_ms
+			);
+			if let Err(e) = line {
+				log::error!("Could not write frame csv, stopping: {}", e);
+				self.csv = None;
+			}
+		}
+		self.frame_count += 1;
+	}
+
+	pub fn flush(&mut self) {
+		if let Some(csv) = &mut self.csv
+			&& let Err(e) = csv.flush()
+		{
+			log::error!("Could not flush frame csv: {}", e);
+		}
+	}
+
+	pub fn log_table(&self, level: log::Level) {
+		log::log!(level, "{}", self);
+	}
+}
+
+impl fmt::Display for FrameStats {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"cpu timings over {} frames ({:.1} fps, ms):\n  {:<8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+			self.frame.len(),
+			self.fps(),
+			"",
+			"avg",
+			"min",
+			"max",
+			"p50",
+			"p95",
+			"p99"
+		)?;
+		let rows = [
+			("frame", &self.frame),
+			("wait", &self.wait),
+			("acquire", &self.acquire),
+			("record", &self.record),
+			("submit", &self.submit),
+			("present", &self.present),
+		];
+		for (name, stats) in rows {
+			write!(
+				f,
+				"\n  {:<8} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+				name,
+				stats.avg(),
+				stats.min(),
+				stats.max(),
+				stats.percentile(50.),
+				stats.percentile(95.),
+				stats.percentile(99.)
+			)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn stats(samples: &[f64]) -> RollingStats {
+		let mut stats = RollingStats::new(samples.len());
+		for &sample in samples {
+			stats.push(sample);
+		}
+		stats
+	}
+
+	#[test]
+	fn percentile_is_nearest_rank() {
+		let stats = stats(&[5., 1., 4., 2., 3., 10., 9., 8., 7., 6.]);
+		assert_eq!(stats.percentile(0.), 1.);
+		assert_eq!(stats.percentile(10.), 1.);
+		assert_eq!(stats.percentile(11.), 2.);
+		assert_eq!(stats.percentile(50.), 5.);
+		assert_eq!(stats.percentile(95.), 10.);
+		assert_eq!(stats.percentile(100.), 10.);
+	}
+
+	#[test]
+	fn percentile_clamps_out_of_range() {
+		let stats = stats(&[3., 1., 2.]);
+		assert_eq!(stats.percentile(-20.), 1.);
+		assert_eq!(stats.percentile(250.), 3.);
+		assert_eq!(stats.percentile(f64::NAN), 1.);
+		assert_eq!(RollingStats::new(4).percentile(50.), 0.);
+	}
+
+	#[test]
+	fn drops_oldest_past_capacity() {
+		let mut stats = RollingStats::new(3);
+		for sample in [100., 1., 2., 3.] {
+			stats.push(sample);
+		}
+		assert_eq!(stats.len(), 3);
+		assert_eq!(stats.max(), 3.);
+		assert_eq!(stats.min(), 1.);
+		assert_eq!(stats.avg(), 2.);
+		assert_eq!(stats.last(), 3.);
+		assert_eq!(stats.percentile(100.), 3.);
+	}
+}
@@ -1,5 +1,14 @@
 use super::{Device, DeviceContext, InstanceContext, Window, swapchain, vk};
 
+// how swapchain images move between the graphics and present families when those differ.
+// CONCURRENT is simplest, explicit ownership transfers can be faster on some drivers
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PresentSharing {
+	#[default]
+	Concurrent,
+	OwnershipTransfer,
+}
+
 pub struct SwapchainContext {
 	pub swapchain_device: swapchain::Device,
 	pub swapchain: vk::SwapchainKHR,
@@ -7,6 +16,7 @@ pub struct SwapchainContext {
 	pub swapchain_extent: vk::Extent2D,
 	pub swapchain_imgs: Vec<vk::Image>,
 	pub swapchain_img_views: Vec<vk::ImageView>,
+	pub sharing: PresentSharing,
 }
 impl SwapchainContext {
 	pub fn new(
@@ -14,14 +24,76 @@ impl SwapchainContext {
 		device_ctx: &DeviceContext,
 		window: &Window,
 		surface: vk::SurfaceKHR,
+		sharing: PresentSharing,
+	) -> SwapchainContext {
+		SwapchainContext::replacing(
+			instance_ctx,
+			device_ctx,
+			window,
+			surface,
+			sharing,
+			vk::SwapchainKHR::null(),
+		)
+	}
+
+	// after a resize or an out of date acquire/present. the device has to be idle, the old
+	// images and views are gone afterwards
+	pub fn recreate(
+		&mut self,
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		window: &Window,
+		surface: vk::SurfaceKHR,
+	) {
+		let new = SwapchainContext::replacing(
+			instance_ctx,
+			device_ctx,
+			window,
+			surface,
+			self.sharing,
+			self.swapchain,
+		);
+		std::mem::replace(self, new).cleanup(device_ctx.device());
+	}
+
+	// destroys the views and the swapchain (and with it the images)
+	pub fn cleanup(&self, device: &Device) {
+		unsafe {
+			self.swapchain_img_views
+				.iter()
+				.for_each(|view| device.destroy_image_view(*view, None));
+			self.swapchain_device
+				.destroy_swapchain(self.swapchain, None);
+		}
+	}
+
+	fn replacing(
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		window: &Window,
+		surface: vk::SurfaceKHR,
+		sharing: PresentSharing,
+		old_swapchain: vk::SwapchainKHR,
 	) -> SwapchainContext {
 		let (swapchain_device, swapchain, swapchain_format, swapchain_extent, swapchain_imgs) =
-			SwapchainContext::create_swapchain(instance_ctx, device_ctx, window, surface);
+			SwapchainContext::create_swapchain(
+				instance_ctx,
+				device_ctx,
+				window,
+				surface,
+				sharing,
+				old_swapchain,
+			);
 		let swapchain_img_views = SwapchainContext::create_image_views(
 			&swapchain_imgs,
 			swapchain_format,
 			device_ctx.device(),
 		);
+		device_ctx.set_name(swapchain, "swapchain");
+		for (i, (&img, &view)) in swapchain_imgs.iter().zip(&swapchain_img_views).enumerate() {
+			device_ctx.set_name(img, &format!("swapchain image {}", i));
+			device_ctx.set_name(view, &format!("swapchain view {}", i));
+		}
 
 		SwapchainContext {
 			swapchain_device: swapchain_device,
@@ -30,6 +102,7 @@ impl SwapchainContext {
 			swapchain_extent: swapchain_extent,
 			swapchain_imgs: swapchain_imgs,
 			swapchain_img_views: swapchain_img_views,
+			sharing,
 		}
 	}
 
@@ -38,6 +111,8 @@ impl SwapchainContext {
 		device_ctx: &DeviceContext,
 		window: &Window,
 		surface: vk::SurfaceKHR,
+		sharing: PresentSharing,
+		old_swapchain: vk::SwapchainKHR,
 	) -> (
 		swapchain::Device,
 		vk::SwapchainKHR,
@@ -82,15 +157,19 @@ impl SwapchainContext {
 			image_extent: swapchain_extent,
 			image_array_layers: 1, // always 1 unless doing stereostopic 3d app
 			image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
-			image_sharing_mode: vk::SharingMode::EXCLUSIVE, // assume same family for present and graphics
+			image_sharing_mode: vk::SharingMode::EXCLUSIVE, // same family, or ownership is transferred explicitly
 			pre_transform: capabilities.current_transform,
 			composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
 			present_mode: present_mode,
 			clipped: vk::TRUE,
+			// lets the driver hand over resources, the old one still has to be destroyed
+			old_swapchain,
 			..Default::default()
 		};
 		let indices: [u32; 2];
-		if device_ctx.graphics_index != device_ctx.present_index {
+		if device_ctx.graphics_index != device_ctx.present_index
+			&& sharing == PresentSharing::Concurrent
+		{
 			let graphics_idx = device_ctx.graphics_index;
 			let present_idx = device_ctx.present_index;
 			indices = [graphics_idx, present_idx];
@@ -151,7 +230,7 @@ impl SwapchainContext {
 				capabilities.min_image_extent.width,
 				capabilities.max_image_extent.width,
 			),
-			height: size.width.clamp(
+			height: size.height.clamp(
 				capabilities.min_image_extent.height,
 				capabilities.max_image_extent.height,
 			),
@@ -0,0 +1,526 @@
+use super::{
+	Buffer, Device, DeviceContext, ImageTracker, ImageUsage, Instance, InstanceContext,
+	barriers::color_subresource_range, vk,
+};
+use std::path::Path;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TextureError {
+	#[error("failed to read texture file: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("failed to decode image: {0}")]
+	Decode(#[from] image::ImageError),
+	#[error("failed to parse ktx2 file: {0:?}")]
+	Ktx2(ktx2::ParseError),
+	#[error("unsupported texture: {0}")]
+	Unsupported(String),
+	#[error("invalid texture data: {0}")]
+	Invalid(String),
+}
+
+pub struct Texture {
+	pub image: vk::Image,
+	pub memory: vk::DeviceMemory,
+	pub view: vk::ImageView,
+	pub format: vk::Format,
+	pub extent: vk::Extent2D,
+	pub mip_levels: u32,
+}
+
+impl Texture {
+	// png/jpeg go through `image`, .ktx2 is uploaded as is (pre-mipped and/or block compressed)
+	pub fn from_file(
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		path: impl AsRef<Path>,
+		srgb: bool,
+	) -> Result<Texture, TextureError> {
+		let path = path.as_ref();
+		let is_ktx2 = path
+			.extension()
+			.is_some_and(|ext| ext.eq_ignore_ascii_case("ktx2"));
+		if is_ktx2 {
+			let bytes = std::fs::read(path)?;
+			let texture = Texture::from_ktx2(instance_ctx, device_ctx, &bytes)?;
+			texture.set_name(device_ctx, &path.display().to_string());
+			return Ok(texture);
+		}
+		let img = image::open(path)?.to_rgba8();
+		let texture = Texture::from_rgba8(
+			instance_ctx,
+			device_ctx,
+			img.width(),
+			img.height(),
+			img.as_raw(),
+			srgb,
+		)?;
+		texture.set_name(device_ctx, &path.display().to_string());
+		Ok(texture)
+	}
+
+	pub fn from_rgba8(
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		width: u32,
+		height: u32,
+		pixels: &[u8],
+		srgb: bool,
+	) -> Result<Texture, TextureError> {
+		if width == 0 || height == 0 {
+			return Err(TextureError::Invalid(format!(
+				"{}x{} image has no pixels",
+				width, height
+			)));
+		}
+		// the staging copy reads exactly this much
+		let expected = width as u64 * height as u64 * 4;
+		if pixels.len() as u64 != expected {
+			return Err(TextureError::Invalid(format!(
+				"{}x{} rgba8 needs {} bytes, got {}",
+				width,
+				height,
+				expected,
+				pixels.len()
+			)));
+		}
+		let format = if srgb {
+			vk::Format::R8G8B8A8_SRGB
+		} else {
+			vk::Format::R8G8B8A8_UNORM
+		};
+		let extent = vk::Extent2D { width, height };
+		// only build a mip chain if we can actually blit it down with linear filtering
+		let mip_levels =
+			if Texture::supports_linear_blit(instance_ctx.instance(), device_ctx, format) {
+				width.max(height).ilog2() + 1
+			} else {
+				log::warn!(
+					"{:?} does not support linear blits, skipping mipmaps",
+					format
+				);
+				1
+			};
+		let (image, memory) = Texture::create_image(
+			device_ctx,
+			format,
+			extent,
+			mip_levels,
+			vk::ImageUsageFlags::TRANSFER_SRC
+				| vk::ImageUsageFlags::TRANSFER_DST
+				| vk::ImageUsageFlags::SAMPLED,
+		);
+
+		let staging = Buffer::staging(device_ctx, pixels);
+		let device = device_ctx.device();
+		let mut tracker = ImageTracker::new();
+		tracker.register(image, vk::ImageAspectFlags::COLOR, mip_levels, 1);
+		device_ctx.immediate_submit(|cmd_buff| {
+			tracker.use_image(image, ImageUsage::TransferDst);
+			tracker.flush(device_ctx, cmd_buff);
+			let region = vk::BufferImageCopy {
+				buffer_offset: 0,
+				image_subresource: vk::ImageSubresourceLayers {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: 0,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				image_extent: vk::Extent3D {
+					width,
+					height,
+					depth: 1,
+				},
+				..Default::default()
+			};
+			unsafe {
+				device.cmd_copy_buffer_to_image(
+					cmd_buff,
+					staging.buffer,
+					image,
+					vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+					&[region],
+				);
+			}
+			Texture::generate_mipmaps(
+				device_ctx,
+				cmd_buff,
+				&mut tracker,
+				image,
+				extent,
+				mip_levels,
+			);
+		});
+		staging.cleanup(device);
+
+		let view = Texture::create_view(device, image, format, mip_levels);
+		Ok(Texture {
+			image,
+			memory,
+			view,
+			format,
+			extent,
+			mip_levels,
+		})
+	}
+
+	pub fn from_ktx2(
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		bytes: &[u8],
+	) -> Result<Texture, TextureError> {
+		let reader = ktx2::Reader::new(bytes).map_err(TextureError::Ktx2)?;
+		let header = reader.header();
+		if header.supercompression_scheme.is_some() {
+			return Err(TextureError::Unsupported(
+				"supercompressed ktx2 (basis/zstd) is not supported".into(),
+			));
+		}
+		if header.face_count != 1 || header.layer_count > 1 || header.pixel_depth > 1 {
+			return Err(TextureError::Unsupported(
+				"only single layer 2D ktx2 textures are supported".into(),
+			));
+		}
+		// ktx2 format values are VkFormat values
+		let format = header
+			.format
+			.map(|format| vk::Format::from_raw(format.value() as i32))
+			.ok_or_else(|| TextureError::Unsupported("ktx2 without a vk format".into()))?;
+		let format_props = unsafe {
+			instance_ctx
+				.instance()
+				.get_physical_device_format_properties(device_ctx.phys_device(), format)
+		};
+		if !format_props
+			.optimal_tiling_features
+			.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+		{
+			return Err(TextureError::Unsupported(format!(
+				"{:?} can't be sampled on this device",
+				format
+			)));
+		}
+
+		let block_size = ktx2_block_size(&reader).ok_or_else(|| {
+			TextureError::Unsupported("ktx2 without a texel block size in its dfd".into())
+		})?;
+		let level_alignment = copy_offset_alignment(block_size);
+
+		let extent = vk::Extent2D {
+			width: header.pixel_width,
+			height: header.pixel_height.max(1),
+		};
+		// pack every level into one staging buffer, remembering where each one starts
+		let mut data: Vec<u8> = Vec::new();
+		let mut regions: Vec<vk::BufferImageCopy> = Vec::new();
+		for (level, level_data) in reader.levels().enumerate() {
+			regions.push(vk::BufferImageCopy {
+				buffer_offset: data.len() as vk::DeviceSize,
+				image_subresource: vk::ImageSubresourceLayers {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: level as u32,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				image_extent: vk::Extent3D {
+					width: (extent.width >> level).max(1),
+					height: (extent.height >> level).max(1),
+					depth: 1,
+				},
+				..Default::default()
+			});
+			data.extend_from_slice(level_data.data);
+			data.resize(data.len().next_multiple_of(level_alignment), 0);
+		}
+		let mip_levels = regions.len() as u32;
+
+		let (image, memory) = Texture::create_image(
+			device_ctx,
+			format,
+			extent,
+			mip_levels,
+			vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+		);
+		let staging = Buffer::staging(device_ctx, &data);
+		let device = device_ctx.device();
+		let mut tracker = ImageTracker::new();
+		tracker.register(image, vk::ImageAspectFlags::COLOR, mip_levels, 1);
+		device_ctx.immediate_submit(|cmd_buff| {
+			tracker.use_image(image, ImageUsage::TransferDst);
+			tracker.flush(device_ctx, cmd_buff);
+			unsafe {
+				device.cmd_copy_buffer_to_image(
+					cmd_buff,
+					staging.buffer,
+					image,
+					vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+					&regions,
+				);
+			}
+			tracker.use_image(image, ImageUsage::SampledFragment);
+			tracker.flush(device_ctx, cmd_buff);
+		});
+		staging.cleanup(device);
+
+		let view = Texture::create_view(device, image, format, mip_levels);
+		Ok(Texture {
+			image,
+			memory,
+			view,
+			format,
+			extent,
+			mip_levels,
+		})
+	}
+
+	fn supports_linear_blit(
+		instance: &Instance,
+		device_ctx: &DeviceContext,
+		format: vk::Format,
+	) -> bool {
+		let format_props = unsafe {
+			instance.get_physical_device_format_properties(device_ctx.phys_device(), format)
+		};
+		format_props
+			.optimal_tiling_features
+			.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+	}
+
+	// expects level 0 to be filled in, leaves every level ready to be sampled
+	fn generate_mipmaps(
+		device_ctx: &DeviceContext,
+		cmd_buff: vk::CommandBuffer,
+		tracker: &mut ImageTracker,
+		image: vk::Image,
+		extent: vk::Extent2D,
+		mip_levels: u32,
+	) {
+		let mut mip_width = extent.width as i32;
+		let mut mip_height = extent.height as i32;
+		for level in 1..mip_levels {
+			// read from the previous level, write into this one
+			tracker.use_subresource(
+				image,
+				color_subresource_range(level - 1, 1),
+				ImageUsage::TransferSrc,
+			);
+			tracker.use_subresource(
+				image,
+				color_subresource_range(level, 1),
+				ImageUsage::TransferDst,
+			);
+			tracker.flush(device_ctx, cmd_buff);
+			let next_width = (mip_width / 2).max(1);
+			let next_height = (mip_height / 2).max(1);
+			let blit = vk::ImageBlit {
+				src_subresource: vk::ImageSubresourceLayers {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: level - 1,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				src_offsets: [
+					vk::Offset3D { x: 0, y: 0, z: 0 },
+					vk::Offset3D {
+						x: mip_width,
+						y: mip_height,
+						z: 1,
+					},
+				],
+				dst_subresource: vk::ImageSubresourceLayers {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: level,
+					base_array_layer: 0,
+					layer_count: 1,
+				},
+				dst_offsets: [
+					vk::Offset3D { x: 0, y: 0, z: 0 },
+					vk::Offset3D {
+						x: next_width,
+						y: next_height,
+						z: 1,
+					},
+				],
+			};
+			unsafe {
+				device_ctx.device().cmd_blit_image(
+					cmd_buff,
+					image,
+					vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+					image,
+					vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+					&[blit],
+					vk::Filter::LINEAR,
+				);
+			}
+			mip_width = next_width;
+			mip_height = next_height;
+		}
+		// levels are a mix of TRANSFER_SRC and TRANSFER_DST now, the tracker sorts it out
+		tracker.use_image(image, ImageUsage::SampledFragment);
+		tracker.flush(device_ctx, cmd_buff);
+	}
+
+	fn create_image(
+		device_ctx: &DeviceContext,
+		format: vk::Format,
+		extent: vk::Extent2D,
+		mip_levels: u32,
+		usage: vk::ImageUsageFlags,
+	) -> (vk::Image, vk::DeviceMemory) {
+		let device = device_ctx.device();
+		let image_info = vk::ImageCreateInfo {
+			image_type: vk::ImageType::TYPE_2D,
+			format,
+			extent: vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			},
+			mip_levels,
+			array_layers: 1,
+			samples: vk::SampleCountFlags::TYPE_1,
+			tiling: vk::ImageTiling::OPTIMAL,
+			usage,
+			sharing_mode: vk::SharingMode::EXCLUSIVE,
+			initial_layout: vk::ImageLayout::UNDEFINED,
+			..Default::default()
+		};
+		let image = unsafe {
+			device
+				.create_image(&image_info, None)
+				.expect("Should have been able to create texture image")
+		};
+		let requirements = unsafe { device.get_image_memory_requirements(image) };
+		let memory =
+			device_ctx.allocate_memory(requirements, vk::MemoryPropertyFlags::DEVICE_LOCAL);
+		unsafe {
+			device
+				.bind_image_memory(image, memory, 0)
+				.expect("Should have been able to bind texture memory")
+		};
+		(image, memory)
+	}
+
+	fn create_view(
+		device: &Device,
+		image: vk::Image,
+		format: vk::Format,
+		mip_levels: u32,
+	) -> vk::ImageView {
+		let view_info = vk::ImageViewCreateInfo {
+			image,
+			view_type: vk::ImageViewType::TYPE_2D,
+			format,
+			subresource_range: color_subresource_range(0, mip_levels),
+			..Default::default()
+		};
+		unsafe {
+			device
+				.create_image_view(&view_info, None)
+				.expect("Should have been able to create texture view")
+		}
+	}
+
+	pub fn set_name(&self, device_ctx: &DeviceContext, name: &str) {
+		device_ctx.set_name(self.image, name);
+		device_ctx.set_name(self.view, &format!("{} view", name));
+		device_ctx.set_name(self.memory, &format!("{} memory", name));
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		unsafe {
+			device.destroy_image_view(self.view, None);
+			device.destroy_image(self.image, None);
+			device.free_memory(self.memory, None);
+		}
+	}
+}
+
+// bytes per texel block, from the basic data format descriptor. 0 there means supercompressed
+fn ktx2_block_size(reader: &ktx2::Reader<&[u8]>) -> Option<usize> {
+	reader
+		.dfd_blocks()
+		.find(|block| {
+			block.header.vendor_id == ktx2::DfdHeader::BASIC.vendor_id
+				&& block.header.descriptor_type == ktx2::DfdHeader::BASIC.descriptor_type
+		})
+		.and_then(|block| ktx2::DfdBlockBasic::parse(block.data).ok())
+		.map(|basic| basic.header.bytes_planes[0] as usize)
+		.filter(|&size| size > 0)
+}
+
+// vkCmdCopyBufferToImage wants bufferOffset to be a multiple of both the texel block size and
+// 4, so the lcm of the two. 3, 6 and 12 byte formats need more than the block size alone
+fn copy_offset_alignment(block_size: usize) -> usize {
+	let gcd = if block_size.is_multiple_of(4) {
+		4
+	} else if block_size.is_multiple_of(2) {
+		2
+	} else {
+		1
+	};
+	block_size * 4 / gcd
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// a one level ktx2 whose dfd says `block_size` bytes per texel block
+	fn ktx2_bytes(format: vk::Format, block_size: u8) -> Vec<u8> {
+		let level_len = 4 * block_size.max(1) as u64;
+		let mut bytes = vec![
+			0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+		];
+		// format, type size, 2x2x0, 0 layers, 1 face, 1 level, no supercompression
+		for word in [format.as_raw() as u32, 1, 2, 2, 0, 0, 1, 1, 0] {
+			bytes.extend(word.to_le_bytes());
+		}
+		// dfd offset/length, kvd offset/length, sgd offset/length
+		bytes.extend(104u32.to_le_bytes());
+		bytes.extend(28u32.to_le_bytes());
+		bytes.extend([0u8; 24]);
+		// level index
+		bytes.extend(132u64.to_le_bytes());
+		bytes.extend(level_len.to_le_bytes());
+		bytes.extend(level_len.to_le_bytes());
+		// dfd: total size, basic block header, then the basic block without samples
+		bytes.extend(28u32.to_le_bytes());
+		bytes.extend(0u32.to_le_bytes());
+		bytes.extend(2u16.to_le_bytes());
+		bytes.extend(24u16.to_le_bytes());
+		bytes.extend([1, 1, 1, 0, 0, 0, 0, 0, block_size, 0, 0, 0, 0, 0, 0, 0]);
+		bytes.resize(132 + level_len as usize, 0x7f);
+		bytes
+	}
+
+	#[test]
+	fn reads_block_size_from_dfd() {
+		let bytes = ktx2_bytes(vk::Format::R8G8B8_UNORM, 3);
+		let reader = ktx2::Reader::new(&bytes[..]).expect("Should have been able to read ktx2");
+		assert_eq!(ktx2_block_size(&reader), Some(3));
+		let bytes = ktx2_bytes(vk::Format::R32G32B32_SFLOAT, 12);
+		let reader = ktx2::Reader::new(&bytes[..]).expect("Should have been able to read ktx2");
+		assert_eq!(ktx2_block_size(&reader), Some(12));
+		// supercompressed files leave it at 0
+		let bytes = ktx2_bytes(vk::Format::R8G8B8A8_UNORM, 0);
+		let reader = ktx2::Reader::new(&bytes[..]).expect("Should have been able to read ktx2");
+		assert_eq!(ktx2_block_size(&reader), None);
+	}
+
+	#[test]
+	fn copy_offsets_are_multiples_of_block_size_and_4() {
+		for (block_size, alignment) in [
+			(1, 4),
+			(2, 4),
+			(3, 12),
+			(4, 4),
+			(6, 12),
+			(8, 8),
+			(12, 12),
+			(16, 16),
+		] {
+			assert_eq!(copy_offset_alignment(block_size), alignment);
+		}
+	}
+}
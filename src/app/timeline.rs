@@ -0,0 +1,142 @@
+use super::{Device, DeviceContext, vk};
+use std::cell::Cell;
+
+// "submit after value N" on another (or the same) timeline
+#[derive(Clone, Copy)]
+pub struct TimelineWait<'t> {
+	pub timeline: &'t Timeline,
+	pub value: u64,
+	pub stage: vk::PipelineStageFlags2,
+}
+
+// everything a submit waits on / signals besides its own timeline. binary semaphores are
+// still needed for the swapchain, acquire and present don't take timeline semaphores
+#[derive(Default)]
+pub struct SubmitDeps<'t> {
+	pub timeline_waits: &'t [TimelineWait<'t>],
+	pub binary_waits: &'t [(vk::Semaphore, vk::PipelineStageFlags2)],
+	pub binary_signals: &'t [vk::Semaphore],
+}
+
+// one per queue. every submit through it signals the next value, so "is this work done" is
+// just comparing against the counter instead of juggling a fence per thing in flight
+pub struct Timeline {
+	pub semaphore: vk::Semaphore,
+	last_submitted: Cell<u64>,
+}
+
+impl Timeline {
+	pub fn new(device: &Device) -> Timeline {
+		let mut type_info = vk::SemaphoreTypeCreateInfo {
+			semaphore_type: vk::SemaphoreType::TIMELINE,
+			initial_value: 0,
+			..Default::default()
+		};
+		let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+		let semaphore = unsafe {
+			device
+				.create_semaphore(&create_info, None)
+				.expect("Should have been able to create timeline semaphore")
+		};
+		Timeline {
+			semaphore,
+			last_submitted: Cell::new(0),
+		}
+	}
+
+	pub fn last_submitted(&self) -> u64 {
+		self.last_submitted.get()
+	}
+
+	pub fn completed_value(&self, device: &Device) -> u64 {
+		unsafe {
+			device
+				.get_semaphore_counter_value(self.semaphore)
+				.expect("Should have been able to read timeline value")
+		}
+	}
+
+	pub fn is_complete(&self, device: &Device, value: u64) -> bool {
+		self.completed_value(device) >= value
+	}
+
+	// blocks the cpu until the gpu reached `value`, false on timeout
+	pub fn wait_for_value(&self, device: &Device, value: u64, timeout_ns: u64) -> bool {
+		let semaphores = [self.semaphore];
+		let values = [value];
+		let wait_info = vk::SemaphoreWaitInfo::default()
+			.semaphores(&semaphores)
+			.values(&values);
+		match unsafe { device.wait_semaphores(&wait_info, timeout_ns) } {
+			Ok(()) => true,
+			Err(vk::Result::TIMEOUT) => false,
+			Err(e) => panic!("Failed to wait for timeline value {}: {:?}", value, e),
+		}
+	}
+
+	// submits `cmd_buffs` to `queue`, signalling the next value on this timeline. returns that
+	// value so callers (or other queues) can wait on it
+	pub fn submit(
+		&self,
+		device_ctx: &DeviceContext,
+		queue: vk::Queue,
+		cmd_buffs: &[vk::CommandBuffer],
+		deps: &SubmitDeps,
+	) -> u64 {
+		let value = self.last_submitted.get() + 1;
+		let wait_infos: Vec<vk::SemaphoreSubmitInfo> = deps
+			.timeline_waits
+			.iter()
+			.map(|wait| vk::SemaphoreSubmitInfo {
+				semaphore: wait.timeline.semaphore,
+				value: wait.value,
+				stage_mask: wait.stage,
+				..Default::default()
+			})
+			.chain(deps.binary_waits.iter().map(|&(semaphore, stage_mask)| {
+				vk::SemaphoreSubmitInfo {
+					semaphore,
+					stage_mask,
+					..Default::default()
+				}
+			}))
+			.collect();
+		let signal_infos: Vec<vk::SemaphoreSubmitInfo> = std::iter::once(vk::SemaphoreSubmitInfo {
+			semaphore: self.semaphore,
+			value,
+			stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+			..Default::default()
+		})
+		.chain(
+			deps.binary_signals
+				.iter()
+				.map(|&semaphore| vk::SemaphoreSubmitInfo {
+					semaphore,
+					stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+					..Default::default()
+				}),
+		)
+		.collect();
+		let cmd_buff_infos: Vec<vk::CommandBufferSubmitInfo> = cmd_buffs
+			.iter()
+			.map(|&command_buffer| vk::CommandBufferSubmitInfo {
+				command_buffer,
+				..Default::default()
+			})
+			.collect();
+		let submit_info = vk::SubmitInfo2::default()
+			.wait_semaphore_infos(&wait_infos)
+			.command_buffer_infos(&cmd_buff_infos)
+			.signal_semaphore_infos(&signal_infos);
+		match device_ctx.queue_submit2(queue, &[submit_info], vk::Fence::null()) {
+			Ok(()) => {}
+			Err(e) => panic!("Failed to submit to queue: {:?}", e),
+		};
+		self.last_submitted.set(value);
+		value
+	}
+
+	pub fn cleanup(&self, device: &Device) {
+		unsafe { device.destroy_semaphore(self.semaphore, None) };
+	}
+}
@@ -0,0 +1,310 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// fixed updates a single frame may run before the rest of the backlog gets dropped, so one
+// long stall (window drag, breakpoint) doesn't turn into a spiral of catch-up frames
+pub const DEFAULT_MAX_STEPS: u32 = 8;
+
+// where Timestep gets time from. swap in a ManualClock to make runs deterministic
+pub trait Clock {
+	// time since some fixed origin, never goes backwards
+	fn now(&mut self) -> Duration;
+}
+
+pub struct SystemClock {
+	start: Instant,
+}
+
+impl Default for SystemClock {
+	fn default() -> SystemClock {
+		SystemClock::new()
+	}
+}
+
+impl SystemClock {
+	pub fn new() -> SystemClock {
+		SystemClock {
+			start: Instant::now(),
+		}
+	}
+}
+
+impl Clock for SystemClock {
+	fn now(&mut self) -> Duration {
+		self.start.elapsed()
+	}
+}
+
+// only moves when told to. clones share the same time, so a test can keep one and hand the
+// other to the Runner
+#[derive(Clone, Default)]
+pub struct ManualClock {
+	now: Rc<Cell<Duration>>,
+	auto_advance: Duration,
+}
+
+impl ManualClock {
+	pub fn new() -> ManualClock {
+		ManualClock::default()
+	}
+
+	// moves forward by `frame` every time it's read, i.e. every frame is exactly `frame` long
+	pub fn stepping(frame: Duration) -> ManualClock {
+		ManualClock {
+			now: Rc::default(),
+			auto_advance: frame,
+		}
+	}
+
+	pub fn advance(&self, by: Duration) {
+		self.now.set(self.now.get() + by);
+	}
+
+	pub fn get(&self) -> Duration {
+		self.now.get()
+	}
+}
+
+impl Clock for ManualClock {
+	fn now(&mut self) -> Duration {
+		self.advance(self.auto_advance);
+		self.now.get()
+	}
+}
+
+// what one frame should do
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Tick {
+	// clock time since the previous tick
+	pub dt: Duration,
+	// updates to run this frame, always 1 in variable mode
+	pub steps: u32,
+	// how far the accumulator is into the next step, for interpolating between the last two
+	// simulation states when rendering. always 1 in variable mode
+	pub alpha: f32,
+	// backlog thrown away because it was more than max_steps could catch up on
+	pub dropped: Duration,
+}
+
+// turns clock time into update steps. variable (one update per frame with the frame's dt) or
+// fixed (an accumulator hands out whole steps of `step`, the leftover becomes alpha)
+pub struct Timestep {
+	clock: Box<dyn Clock>,
+	last: Duration,
+	step: Option<Duration>,
+	max_steps: u32,
+	accumulator: Duration,
+	total_steps: u64,
+}
+
+impl Default for Timestep {
+	fn default() -> Timestep {
+		Timestep::variable()
+	}
+}
+
+impl Timestep {
+	pub fn variable() -> Timestep {
+		let mut clock = SystemClock::new();
+		Timestep {
+			last: clock.now(),
+			clock: Box::new(clock),
+			step: None,
+			max_steps: DEFAULT_MAX_STEPS,
+			accumulator: Duration::ZERO,
+			total_steps: 0,
+		}
+	}
+
+	pub fn fixed(step: Duration) -> Timestep {
+		assert!(!step.is_zero(), "fixed timestep can't be zero");
+		Timestep {
+			step: Some(step),
+			..Timestep::variable()
+		}
+	}
+
+	pub fn fixed_hz(rate: f64) -> Timestep {
+		Timestep::fixed(Duration::from_secs_f64(1. / rate))
+	}
+
+	pub fn with_max_steps(mut self, max_steps: u32) -> Timestep {
+		self.max_steps = max_steps.max(1);
+		self
+	}
+
+	pub fn with_clock(mut self, clock: impl Clock + 'static) -> Timestep {
+		self.clock = Box::new(clock);
+		self.reset();
+		self
+	}
+
+	pub fn step(&self) -> Option<Duration> {
+		self.step
+	}
+
+	// updates handed out so far
+	pub fn total_steps(&self) -> u64 {
+		self.total_steps
+	}
+
+	// forget the time since the last tick, e.g. after a long setup
+	pub fn reset(&mut self) {
+		self.last = self.clock.now();
+		self.accumulator = Duration::ZERO;
+	}
+
+	// dt App::update gets: the fixed step, or the frame's dt in variable mode
+	pub fn update_dt(&self, tick: &Tick) -> f32 {
+		self.step.unwrap_or(tick.dt).as_secs_f32()
+	}
+
+	pub fn tick(&mut self) -> Tick {
+		let now = self.clock.now();
+		let dt = now.saturating_sub(self.last);
+		self.last = now;
+		let Some(step) = self.step else {
+			self.total_steps += 1;
+			return Tick {
+				dt,
+				steps: 1,
+				alpha: 1.,
+				dropped: Duration::ZERO,
+			};
+		};
+
+		self.accumulator += dt;
+		let available = self.accumulator.as_nanos() / step.as_nanos();
+		let mut dropped = Duration::ZERO;
+		let steps = if available > self.max_steps as u128 {
+			// keep the partial step so alpha doesn't jump
+			let remainder = self.accumulator.as_nanos() % step.as_nanos();
+			let kept = step * self.max_steps + Duration::from_nanos(remainder as u64);
+			dropped = self.accumulator - kept;
+			self.accumulator = kept;
+			self.max_steps
+		} else {
+			available as u32
+		};
+		self.accumulator -= step * steps;
+		self.total_steps += steps as u64;
+		Tick {
+			dt,
+			steps,
+			alpha: (self.accumulator.as_secs_f64() / step.as_secs_f64()) as f32,
+			dropped,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MS: Duration = Duration::from_millis(1);
+
+	fn assert_alpha(tick: &Tick, alpha: f32) {
+		assert!(
+			(tick.alpha - alpha).abs() < 1e-4,
+			"alpha {} != {}",
+			tick.alpha,
+			alpha
+		);
+	}
+
+	#[test]
+	fn variable_runs_one_update_per_frame() {
+		let mut timestep = Timestep::variable().with_clock(ManualClock::stepping(16 * MS));
+		for frame in 1..=3 {
+			let tick = timestep.tick();
+			assert_eq!(tick.dt, 16 * MS);
+			assert_eq!(tick.steps, 1);
+			assert_eq!(tick.alpha, 1.);
+			assert_eq!(tick.dropped, Duration::ZERO);
+			assert_eq!(timestep.update_dt(&tick), 0.016);
+			assert_eq!(timestep.total_steps(), frame);
+		}
+	}
+
+	#[test]
+	fn fixed_accumulates_partial_steps() {
+		let mut timestep = Timestep::fixed(4 * MS).with_clock(ManualClock::stepping(10 * MS));
+		let tick = timestep.tick();
+		assert_eq!((tick.dt, tick.steps), (10 * MS, 2));
+		assert_alpha(&tick, 0.5);
+		assert_eq!(timestep.update_dt(&tick), 0.004);
+		// 2ms left over + 10ms
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 3);
+		assert_alpha(&tick, 0.);
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 2);
+		assert_alpha(&tick, 0.5);
+		assert_eq!(tick.dropped, Duration::ZERO);
+		assert_eq!(timestep.total_steps(), 7);
+	}
+
+	#[test]
+	fn fixed_slower_than_frames_skips_updates() {
+		let mut timestep = Timestep::fixed(25 * MS).with_clock(ManualClock::stepping(10 * MS));
+		let steps: Vec<u32> = (0..5).map(|_| timestep.tick().steps).collect();
+		assert_eq!(steps, [0, 0, 1, 0, 1]);
+		assert_eq!(timestep.total_steps(), 2);
+	}
+
+	#[test]
+	fn max_steps_drops_backlog_but_keeps_remainder() {
+		let mut timestep = Timestep::fixed(10 * MS)
+			.with_max_steps(3)
+			.with_clock(ManualClock::stepping(105 * MS));
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 3);
+		assert_eq!(tick.dropped, 70 * MS);
+		assert_alpha(&tick, 0.5);
+		assert_eq!(timestep.total_steps(), 3);
+		// the kept 5ms carries into the next frame: 110ms is 11 steps, clamped again
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 3);
+		assert_eq!(tick.dropped, 80 * MS);
+		assert_alpha(&tick, 0.);
+		assert_eq!(timestep.total_steps(), 6);
+	}
+
+	#[test]
+	fn max_steps_is_at_least_one() {
+		let mut timestep = Timestep::fixed(10 * MS)
+			.with_max_steps(0)
+			.with_clock(ManualClock::stepping(30 * MS));
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 1);
+		assert_eq!(tick.dropped, 20 * MS);
+	}
+
+	#[test]
+	fn reset_forgets_elapsed_time_and_backlog() {
+		let clock = ManualClock::new();
+		let mut timestep = Timestep::fixed(10 * MS).with_clock(clock.clone());
+		clock.advance(25 * MS);
+		let tick = timestep.tick();
+		assert_eq!(tick.steps, 2);
+		assert_alpha(&tick, 0.5);
+		clock.advance(Duration::from_secs(1));
+		timestep.reset();
+		let tick = timestep.tick();
+		assert_eq!(tick.dt, Duration::ZERO);
+		assert_eq!(tick.steps, 0);
+		assert_alpha(&tick, 0.);
+		assert_eq!(timestep.total_steps(), 2);
+	}
+
+	#[test]
+	fn manual_clock_clones_share_time() {
+		let clock = ManualClock::stepping(5 * MS);
+		let mut other = clock.clone();
+		assert_eq!(other.now(), 5 * MS);
+		clock.advance(MS);
+		assert_eq!(clock.get(), 6 * MS);
+		assert_eq!(other.now(), 11 * MS);
+	}
+}
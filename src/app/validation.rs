@@ -0,0 +1,571 @@
+use super::{Entry, Instance, VkError, common::*, debug_utils, vk};
+use std::ffi::{CStr, c_void};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+// "0"/"1", overrides the debug build + `validation` feature default
+pub const VALIDATION_ENV_VAR: &str = "VKRS_VALIDATION";
+// comma separated: sync, best-practices, gpu-av, printf
+pub const VALIDATION_FEATURES_ENV_VAR: &str = "VKRS_VALIDATION_FEATURES";
+// verbose, info, warning or error
+pub const VALIDATION_SEVERITY_ENV_VAR: &str = "VKRS_VALIDATION_SEVERITY";
+// comma separated VUID names or message id numbers (decimal or 0x hex)
+pub const VALIDATION_IGNORE_ENV_VAR: &str = "VKRS_VALIDATION_IGNORE";
+// "0"/"1", panic on the first validation error
+pub const VALIDATION_STRICT_ENV_VAR: &str = "VKRS_VALIDATION_STRICT";
+// shader printf output is logged under this target instead of with the validation messages
+pub const PRINTF_LOG_TARGET: &str = "lvkrs::shader_printf";
+
+// how a message to ignore is identified, the layer gives both the VUID name and its hash
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MessageId {
+	Name(String),
+	Number(i32),
+}
+
+impl MessageId {
+	pub fn parse(s: &str) -> MessageId {
+		let number = match s.strip_prefix("0x") {
+			Some(hex) => u32::from_str_radix(hex, 16).map(|n| n as i32).ok(),
+			None => s.parse().ok(),
+		};
+		match number {
+			Some(number) => MessageId::Number(number),
+			None => MessageId::Name(s.to_owned()),
+		}
+	}
+
+	fn matches(&self, name: Option<&str>, number: i32) -> bool {
+		match self {
+			MessageId::Name(id) => name == Some(id.as_str()),
+			MessageId::Number(id) => *id == number,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationConfig {
+	pub enabled: bool,
+	// the extra (slow) layer checks, all go through VkValidationFeaturesEXT
+	pub synchronization: bool,
+	pub best_practices: bool,
+	pub gpu_assisted: bool,
+	// printf in shaders, also needs VK_KHR_shader_non_semantic_info on the device
+	pub debug_printf: bool,
+	// messages below this never reach the callback
+	pub min_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+	pub ignored: Vec<MessageId>,
+	// panic (outside the callback, see InstanceContext::check_validation) on errors
+	pub strict: bool,
+}
+
+impl Default for ValidationConfig {
+	fn default() -> ValidationConfig {
+		ValidationConfig {
+			enabled: ENABLE_VALIDATION_LAYERS && cfg!(feature = "validation"),
+			synchronization: cfg!(feature = "validation-sync"),
+			best_practices: cfg!(feature = "validation-best-practices"),
+			gpu_assisted: cfg!(feature = "validation-gpu-av"),
+			debug_printf: cfg!(feature = "shader-printf"),
+			min_severity: vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+			ignored: Vec::new(),
+			strict: false,
+		}
+	}
+}
+
+impl ValidationConfig {
+	pub fn strict(mut self) -> ValidationConfig {
+		self.strict = true;
+		self
+	}
+
+	pub fn ignore(mut self, id: &str) -> ValidationConfig {
+		self.ignored.push(MessageId::parse(id));
+		self
+	}
+
+	pub fn apply_env(mut self) -> Result<ValidationConfig, VkError> {
+		let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+		let invalid = |var: &'static str, value: &str| VkError::InvalidConfig {
+			var,
+			value: value.to_owned(),
+		};
+		let flag = |name: &'static str, value: String| match value.as_str() {
+			"1" | "true" | "on" => Ok(true),
+			"0" | "false" | "off" => Ok(false),
+			_ => Err(invalid(name, &value)),
+		};
+		if let Some(enabled) = var(VALIDATION_ENV_VAR) {
+			self.enabled = flag(VALIDATION_ENV_VAR, enabled)?;
+		}
+		if let Some(strict) = var(VALIDATION_STRICT_ENV_VAR) {
+			self.strict = flag(VALIDATION_STRICT_ENV_VAR, strict)?;
+		}
+		if let Some(features) = var(VALIDATION_FEATURES_ENV_VAR) {
+			for feature in features.split(',').map(str::trim) {
+				match feature {
+					"sync" => self.synchronization = true,
+					"best-practices" => self.best_practices = true,
+					"gpu-av" => self.gpu_assisted = true,
+					"printf" => self.debug_printf = true,
+					_ => return Err(invalid(VALIDATION_FEATURES_ENV_VAR, feature)),
+				}
+			}
+		}
+		if let Some(severity) = var(VALIDATION_SEVERITY_ENV_VAR) {
+			use vk::DebugUtilsMessageSeverityFlagsEXT as S;
+			self.min_severity = match severity.as_str() {
+				"verbose" => S::VERBOSE,
+				"info" => S::INFO,
+				"warning" => S::WARNING,
+				"error" => S::ERROR,
+				_ => return Err(invalid(VALIDATION_SEVERITY_ENV_VAR, &severity)),
+			};
+		}
+		if let Some(ignored) = var(VALIDATION_IGNORE_ENV_VAR) {
+			self.ignored
+				.extend(ignored.split(',').map(|id| MessageId::parse(id.trim())));
+		}
+		Ok(self)
+	}
+
+	pub fn severity_flags(&self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+		use vk::DebugUtilsMessageSeverityFlagsEXT as S;
+		let flags = [S::VERBOSE, S::INFO, S::WARNING, S::ERROR]
+			.into_iter()
+			.filter(|&severity| severity.as_raw() >= self.min_severity.as_raw())
+			.fold(S::empty(), |flags, severity| flags | severity);
+		// printf output comes in as INFO
+		if self.debug_printf {
+			flags | S::INFO
+		} else {
+			flags
+		}
+	}
+
+	pub fn layer_features(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+		let mut features = Vec::new();
+		if self.synchronization {
+			features.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+		}
+		if self.best_practices {
+			features.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+		}
+		if self.gpu_assisted {
+			features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+			features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+		}
+		if self.debug_printf {
+			if self.gpu_assisted {
+				log::warn!("Older validation layers can't run GPU-AV and debug printf together");
+			}
+			features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+		}
+		features
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ValidationObject {
+	pub object_type: vk::ObjectType,
+	pub handle: u64,
+	pub name: Option<String>,
+}
+
+// one message from the layer, copied out of the callback data
+#[derive(Clone, Debug)]
+pub struct ValidationRecord {
+	pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+	pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+	pub id_name: Option<String>,
+	pub id_number: i32,
+	pub objects: Vec<ValidationObject>,
+	pub message: String,
+}
+
+impl ValidationRecord {
+	pub fn is_error(&self) -> bool {
+		self.severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+	}
+}
+
+impl fmt::Display for ValidationRecord {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"[{:?}] {} ({:#x})",
+			self.severity,
+			self.id_name.as_deref().unwrap_or("?"),
+			self.id_number
+		)?;
+		for object in &self.objects {
+			write!(f, " {:?} {:#x}", object.object_type, object.handle)?;
+			if let Some(name) = &object.name {
+				write!(f, " {:?}", name)?;
+			}
+		}
+		write!(f, ": {}", self.message)
+	}
+}
+
+#[derive(Default)]
+struct SinkState {
+	records: Vec<ValidationRecord>,
+	// how many errors `take_new_errors` already handed out
+	seen_errors: usize,
+}
+
+// every message that got past the ignore list, shared between the callback and whoever wants
+// to look (tests asserting a clean frame, strict mode). cloning shares the same records
+#[derive(Clone, Default)]
+pub struct ValidationSink {
+	state: Arc<Mutex<SinkState>>,
+}
+
+impl ValidationSink {
+	fn push(&self, record: ValidationRecord) {
+		self.state.lock().unwrap().records.push(record);
+	}
+
+	pub fn records(&self) -> Vec<ValidationRecord> {
+		self.state.lock().unwrap().records.clone()
+	}
+
+	pub fn errors(&self) -> Vec<ValidationRecord> {
+		let state = self.state.lock().unwrap();
+		state
+			.records
+			.iter()
+			.filter(|record| record.is_error())
+			.cloned()
+			.collect()
+	}
+
+	pub fn error_count(&self) -> usize {
+		let state = self.state.lock().unwrap();
+		state
+			.records
+			.iter()
+			.filter(|record| record.is_error())
+			.count()
+	}
+
+	// errors recorded since the last call
+	pub fn take_new_errors(&self) -> Vec<ValidationRecord> {
+		let mut state = self.state.lock().unwrap();
+		let errors: Vec<ValidationRecord> = state
+			.records
+			.iter()
+			.filter(|record| record.is_error())
+			.skip(state.seen_errors)
+			.cloned()
+			.collect();
+		state.seen_errors += errors.len();
+		errors
+	}
+
+	pub fn clear(&self) {
+		*self.state.lock().unwrap() = SinkState::default();
+	}
+
+	pub fn assert_no_errors(&self) {
+		let errors = self.errors();
+		if !errors.is_empty() {
+			let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+			panic!(
+				"{} validation error(s):\n{}",
+				errors.len(),
+				lines.join("\n")
+			);
+		}
+	}
+}
+
+// what the callback gets through p_user_data. boxed so the pointer survives InstanceContext
+// being moved around, and owned by the DebugMessenger so it lives until the instance is gone
+pub struct MessengerState {
+	pub ignored: Vec<MessageId>,
+	pub sink: ValidationSink,
+}
+
+pub struct DebugMessenger {
+	pub loader: debug_utils::Instance,
+	pub messenger: vk::DebugUtilsMessengerEXT,
+	// the callback points into this, has to outlive the messenger
+	_state: Box<MessengerState>,
+}
+
+impl DebugMessenger {
+	// made before the instance, so the create info can also go into InstanceCreateInfo's p_next
+	pub fn state(config: &ValidationConfig, sink: ValidationSink) -> Box<MessengerState> {
+		Box::new(MessengerState {
+			ignored: config.ignored.clone(),
+			sink,
+		})
+	}
+
+	// `state` has to outlive whatever this is handed to, including vkDestroyInstance when it's
+	// chained into the instance create info
+	pub fn create_info(
+		config: &ValidationConfig,
+		state: &MessengerState,
+	) -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+		let message_type_flags = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+			| vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+			| vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
+		vk::DebugUtilsMessengerCreateInfoEXT {
+			message_severity: config.severity_flags(),
+			message_type: message_type_flags,
+			pfn_user_callback: Some(debug_callback),
+			p_user_data: state as *const MessengerState as *mut c_void,
+			..Default::default()
+		}
+	}
+
+	pub fn new(
+		entry: &Entry,
+		instance: &Instance,
+		config: &ValidationConfig,
+		state: Box<MessengerState>,
+	) -> DebugMessenger {
+		let loader = debug_utils::Instance::new(entry, instance);
+		let messenger_create_info = DebugMessenger::create_info(config, &state);
+		let messenger = unsafe {
+			loader
+				.create_debug_utils_messenger(&messenger_create_info, None)
+				.expect("Should have been able to create debug messenger")
+		};
+		DebugMessenger {
+			loader,
+			messenger,
+			_state: state,
+		}
+	}
+
+	pub fn cleanup(&self) {
+		unsafe {
+			self.loader
+				.destroy_debug_utils_messenger(self.messenger, None);
+		}
+	}
+}
+
+// the layer wraps the shader's output in its own context: "<header> | MessageID = 0x.. |
+// ... Draw Index 0x2. ... Stage = Fragment. ... | <printf output>". the exact layout moves
+// around between layer versions, so anything missing just comes back as None
+pub struct PrintfMessage<'m> {
+	pub stage: Option<&'m str>,
+	// "Draw Index 0x2", "Compute Dispatch Index 0x0", ...
+	pub invocation: Option<&'m str>,
+	pub text: &'m str,
+}
+
+impl<'m> PrintfMessage<'m> {
+	pub fn parse(msg: &'m str) -> PrintfMessage<'m> {
+		// skip the header, the output can have '|'s of its own so only the first "| " after
+		// the context separates the two
+		let body = match msg.find("MessageID = ") {
+			Some(idx) => {
+				let rest = &msg[idx..];
+				rest.find('|').map_or("", |end| &rest[end + 1..])
+			}
+			None => msg,
+		};
+		let (context, text) = match body.find("| ") {
+			Some(idx) => (&body[..idx], body[idx + 2..].trim()),
+			None => (body, body.trim()),
+		};
+		// the sentence starting at `key` in the context, up to the next '.'
+		let field = |key: &str| {
+			let start = context.find(key)?;
+			let rest = &context[start..];
+			Some(rest[..rest.find('.').unwrap_or(rest.len())].trim())
+		};
+		let stage = field("Stage = ").map(|stage| stage.trim_start_matches("Stage = "));
+		let invocation = field("Draw Index")
+			.or_else(|| field("Compute Dispatch Index"))
+			.or_else(|| field("Trace Rays Index"));
+		PrintfMessage {
+			stage,
+			invocation,
+			text,
+		}
+	}
+}
+
+unsafe extern "system" fn debug_callback(
+	msg_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+	msg_type: vk::DebugUtilsMessageTypeFlagsEXT,
+	p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+	p_user_data: *mut c_void,
+) -> vk::Bool32 {
+	let callback_data = unsafe { *p_callback_data };
+	let state = unsafe { &*(p_user_data as *const MessengerState) };
+	let id_name =
+		unsafe { callback_data.message_id_name_as_c_str() }.and_then(|name| name.to_str().ok());
+	if state
+		.ignored
+		.iter()
+		.any(|id| id.matches(id_name, callback_data.message_id_number))
+	{
+		return vk::FALSE;
+	}
+	let msg = unsafe { CStr::from_ptr(callback_data.p_message) }.to_string_lossy();
+	if id_name.is_some_and(|name| name.contains("DEBUG-PRINTF")) {
+		let printf = PrintfMessage::parse(&msg);
+		log::info!(
+			target: PRINTF_LOG_TARGET,
+			"[{} {}] {}",
+			printf.stage.unwrap_or("?"),
+			printf.invocation.unwrap_or("?"),
+			printf.text
+		);
+		return vk::FALSE;
+	}
+	let objects = if callback_data.p_objects.is_null() {
+		&[][..]
+	} else {
+		unsafe {
+			std::slice::from_raw_parts(callback_data.p_objects, callback_data.object_count as usize)
+		}
+	};
+	// no panicking in here, unwinding through the driver aborts. strict mode checks the sink
+	// afterwards instead
+	state.sink.push(ValidationRecord {
+		severity: msg_severity,
+		message_type: msg_type,
+		id_name: id_name.map(str::to_owned),
+		id_number: callback_data.message_id_number,
+		objects: objects
+			.iter()
+			.map(|object| ValidationObject {
+				object_type: object.object_type,
+				handle: object.object_handle,
+				name: unsafe { object.object_name_as_c_str() }
+					.map(|name| name.to_string_lossy().into_owned()),
+			})
+			.collect(),
+		message: msg.clone().into_owned(),
+	});
+	match msg_severity {
+		vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => {
+			log::error!("-- Validation layer -- [{:?}]: {}", msg_type, msg);
+		}
+		vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => {
+			log::warn!("-- Validation layer -- [{:?}]: {}", msg_type, msg);
+		}
+		vk::DebugUtilsMessageSeverityFlagsEXT::INFO => {
+			log::info!("-- Validation layer -- [{:?}], {}", msg_type, msg);
+		}
+		_ => {
+			log::debug!("-- Validation layer -- [{:?}], {}", msg_type, msg);
+		}
+	}
+	vk::FALSE
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_message_ids() {
+		assert_eq!(MessageId::parse("1234"), MessageId::Number(1234));
+		assert_eq!(MessageId::parse("-5"), MessageId::Number(-5));
+		// hashes above i32::MAX as the layer prints them, in hex
+		assert_eq!(
+			MessageId::parse("0x8b2a8f6c"),
+			MessageId::Number(0x8b2a8f6cu32 as i32)
+		);
+		assert_eq!(
+			MessageId::parse("VUID-vkCmdDraw-None-02699"),
+			MessageId::Name("VUID-vkCmdDraw-None-02699".to_owned())
+		);
+		// not valid hex, so it's a name
+		assert_eq!(MessageId::parse("0xzz"), MessageId::Name("0xzz".to_owned()));
+	}
+
+	#[test]
+	fn message_ids_match_name_or_number() {
+		let name = MessageId::parse("VUID-x");
+		assert!(name.matches(Some("VUID-x"), 7));
+		assert!(!name.matches(Some("VUID-y"), 7));
+		assert!(!name.matches(None, 7));
+		let number = MessageId::parse("7");
+		assert!(number.matches(None, 7));
+		assert!(!number.matches(Some("7"), 8));
+	}
+
+	#[test]
+	fn parses_printf_context() {
+		let msg = "Validation Information: [ WARNING-DEBUG-PRINTF ] | MessageID = 0x4fe1fef9 | \
+			vkQueueSubmit(): Command buffer (0x1)(name = frame). Draw Index 0x2. Pipeline (0x3). \
+			Shader Module (0x4). Stage = Fragment. Fragment coord (x,y) = (1.5, 2.5). \
+			Shader Instruction Index = 75. | color = 0.50, 0.25";
+		let printf = PrintfMessage::parse(msg);
+		assert_eq!(printf.stage, Some("Fragment"));
+		assert_eq!(printf.invocation, Some("Draw Index 0x2"));
+		assert_eq!(printf.text, "color = 0.50, 0.25");
+	}
+
+	#[test]
+	fn printf_output_keeps_its_own_pipes() {
+		let msg = "Validation Information: [ WARNING-DEBUG-PRINTF ] | MessageID = 0x4fe1fef9 | \
+			vkQueueSubmit(): Compute Dispatch Index 0x1. Stage = Compute. | a | b || Stage = x";
+		let printf = PrintfMessage::parse(msg);
+		assert_eq!(printf.text, "a | b || Stage = x");
+		// fields only come from the layer's context, not the output
+		assert_eq!(printf.stage, Some("Compute"));
+		assert_eq!(printf.invocation, Some("Compute Dispatch Index 0x1"));
+
+		let printf = PrintfMessage::parse("Stage = Vertex. | x|y");
+		assert_eq!(printf.stage, Some("Vertex"));
+		assert_eq!(printf.text, "x|y");
+	}
+
+	#[test]
+	fn printf_falls_back_to_whole_message() {
+		let printf = PrintfMessage::parse("  x = 1  ");
+		assert_eq!(printf.stage, None);
+		assert_eq!(printf.invocation, None);
+		assert_eq!(printf.text, "x = 1");
+
+		let printf = PrintfMessage::parse("Compute Dispatch Index 0x0. Stage = Compute | id 3");
+		assert_eq!(printf.invocation, Some("Compute Dispatch Index 0x0"));
+		assert_eq!(printf.stage, Some("Compute"));
+	}
+
+	#[test]
+	fn instance_create_info_feeds_the_sink() {
+		let config = ValidationConfig::default().ignore("VUID-ignored");
+		let sink = ValidationSink::default();
+		let state = DebugMessenger::state(&config, sink.clone());
+		let create_info = DebugMessenger::create_info(&config, &state);
+		assert_eq!(create_info.message_severity, config.severity_flags());
+		let callback = create_info.pfn_user_callback.unwrap();
+		let message = |id: &CStr| {
+			let data = vk::DebugUtilsMessengerCallbackDataEXT {
+				p_message_id_name: id.as_ptr(),
+				p_message: c"vkDestroyInstance(): leaked VkDevice".as_ptr(),
+				..Default::default()
+			};
+			unsafe {
+				callback(
+					vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+					vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+					&data,
+					create_info.p_user_data,
+				)
+			}
+		};
+		assert_eq!(message(c"VUID-vkDestroyInstance-instance-00629"), vk::FALSE);
+		message(c"VUID-ignored");
+		let errors = sink.take_new_errors();
+		assert_eq!(errors.len(), 1);
+		assert_eq!(
+			errors[0].id_name.as_deref(),
+			Some("VUID-vkDestroyInstance-instance-00629")
+		);
+	}
+}
@@ -1,33 +1,75 @@
-use super::{DeviceContext, InstanceContext, Window};
+use super::{DeviceContext, InstanceContext, SamplerCache, VkConfig, VkError, Window};
 
 pub struct VkCore {
 	pub instance_ctx: InstanceContext,
 	pub device_ctx: DeviceContext,
+	pub sampler_cache: SamplerCache,
+	// what it was actually built with, env vars applied
+	pub config: VkConfig,
 }
 impl VkCore {
-	pub fn new(window: &Window) -> VkCore {
-		let instance_ctx = InstanceContext::new();
-		let device_ctx = DeviceContext::new(&instance_ctx, window);
+	pub fn new(window: &Window, config: &VkConfig) -> Result<VkCore, VkError> {
+		VkCore::create(Some(window), config)
+	}
+	// no window, surface or present queue, for offscreen rendering (e.g. on lavapipe in ci)
+	pub fn headless(config: &VkConfig) -> Result<VkCore, VkError> {
+		VkCore::create(None, config)
+	}
+	fn create(window: Option<&Window>, config: &VkConfig) -> Result<VkCore, VkError> {
+		let config = config.clone().apply_env()?;
+		let instance_ctx = match window {
+			Some(_) => InstanceContext::new(&config)?,
+			None => InstanceContext::headless(&config)?,
+		};
+		let device_ctx = match window {
+			Some(window) => DeviceContext::new(&instance_ctx, window, &config),
+			None => DeviceContext::headless(&instance_ctx, &config),
+		};
+		let device_ctx = match device_ctx {
+			Ok(device_ctx) => device_ctx,
+			Err(e) => {
+				instance_ctx.cleanup();
+				return Err(e.into());
+			}
+		};
 
-		VkCore {
+		Ok(VkCore {
 			instance_ctx,
 			device_ctx,
-		}
+			sampler_cache: SamplerCache::new(),
+			config,
+		})
+	}
+	// what the loader could do, what the instance was created with and what the device is
+	// actually used as (never above the instance version)
+	pub fn loader_version(&self) -> u32 {
+		self.instance_ctx.loader_version
+	}
+	pub fn instance_version(&self) -> u32 {
+		self.instance_ctx.api_version
+	}
+	pub fn device_version(&self) -> u32 {
+		self.device_ctx.api_version
 	}
 	pub fn cleanup(&self) {
-		// debug messenger
+		// samplers, upload pool, timelines
+		let device = self.device_ctx.device();
+		self.sampler_cache.cleanup(device);
+		self.device_ctx.graphics_timeline.cleanup(device);
+		self.device_ctx.present_timeline.cleanup(device);
+		self.device_ctx.transfer_timeline.cleanup(device);
+		self.device_ctx.compute_timeline.cleanup(device);
 		unsafe {
-			self.instance_ctx
-				.debug_utils_loader
-				.destroy_debug_utils_messenger(self.instance_ctx.debug_messenger, None);
+			device.destroy_command_pool(self.device_ctx.upload_cmd_pool, None);
 		}
+		// pipeline cache, saved first so the next run starts warm
+		self.device_ctx.pipeline_cache.save(device);
+		self.device_ctx.pipeline_cache.cleanup(device);
 		// device
 		unsafe {
 			self.device_ctx.device().destroy_device(None);
 		}
-		// instance
-		unsafe {
-			self.instance_ctx.instance().destroy_instance(None);
-		}
+		// debug messenger + instance
+		self.instance_ctx.cleanup();
 	}
 }
@@ -1,22 +1,50 @@
 use super::{
-	Device, DeviceContext, FrameData, InstanceContext, PipelineContext, SwapchainContext, Window,
-	common::*, surface, vk,
+	Device, DeviceContext, FrameContext, FrameData, GpuProfiler, ImageTracker, ImageUsage,
+	InstanceContext, PresentSharing, QueryReport, RenderGraph, SwapchainContext, VkCore, Window,
+	barriers::{cmd_barriers, color_subresource_range, image_ownership_transfer},
+	common::*,
+	frame_queries::QUERY_REPORT_LOG_INTERVAL,
+	gpu_profiler::GPU_PROFILER_LOG_INTERVAL,
+	surface, vk,
 };
+use std::cell::{Cell, RefCell};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 pub struct VkSwap {
 	pub surface: vk::SurfaceKHR,
 	pub swapchain_ctx: SwapchainContext,
-	pub pipeline_ctx: PipelineContext,
 	pub frames: Vec<FrameData>,
-	pub cmd_pool: vk::CommandPool, // manages the memory used to store buffers
+	pub render_finished: Vec<vk::Semaphore>, // one per swapchain image
+	pub cmd_pool: vk::CommandPool,           // manages the memory used to store buffers
 	pub current_frame: u32,
+	pub image_tracker: RefCell<ImageTracker>,
+	// only used for PresentSharing::OwnershipTransfer with separate graphics/present families:
+	// one pre-recorded acquire barrier per swapchain image, submitted on the present queue
+	pub present_cmd_pool: vk::CommandPool,
+	pub present_acquire_cmds: Vec<vk::CommandBuffer>,
+	pub gpu_profiler: RefCell<GpuProfiler>,
+	pub query_report: RefCell<QueryReport>,
+	// set 0 of every pipeline that reads the camera, one set per frame from descriptor_pool
+	pub camera_set_layout: vk::DescriptorSetLayout,
+	pub descriptor_pool: vk::DescriptorPool,
+	// set on resize and by out of date/suboptimal acquires and presents, the Runner recreates the
+	// swapchain before the next frame
+	pub out_of_date: Cell<bool>,
 }
 impl VkSwap {
 	pub fn new(
 		window: &Window,
 		instance_ctx: &InstanceContext,
 		device_ctx: &DeviceContext,
+	) -> VkSwap {
+		VkSwap::with_present_sharing(window, instance_ctx, device_ctx, PresentSharing::default())
+	}
+
+	pub fn with_present_sharing(
+		window: &Window,
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+		sharing: PresentSharing,
 	) -> VkSwap {
 		let surface = unsafe {
 			ash_window::create_surface(
@@ -28,177 +56,355 @@ impl VkSwap {
 			)
 			.expect("Should have been able to create surface in create swapchain")
 		};
-		let swapchain_ctx = SwapchainContext::new(instance_ctx, device_ctx, window, surface);
-		let pipeline_ctx = PipelineContext::new(&device_ctx, swapchain_ctx.swapchain_format);
+		let swapchain_ctx =
+			SwapchainContext::new(instance_ctx, device_ctx, window, surface, sharing);
 		let cmd_pool = VkSwap::create_command_pool(device_ctx.device(), device_ctx.graphics_index);
+		device_ctx.set_name(cmd_pool, "frame cmd pool");
+		// 0 valid bits means the graphics queue can't do timestamps, the profiler stays empty
+		let timestamp_valid_bits = unsafe {
+			instance_ctx
+				.instance()
+				.get_physical_device_queue_family_properties(device_ctx.phys_device())
+				[device_ctx.graphics_index as usize]
+				.timestamp_valid_bits
+		};
+		if timestamp_valid_bits == 0 {
+			log::warn!("graphics queue doesn't support timestamps, gpu profiling disabled");
+		}
+		let camera_set_layout = VkSwap::create_camera_set_layout(device_ctx.device());
+		device_ctx.set_name(camera_set_layout, "camera set layout");
+		let descriptor_pool = VkSwap::create_descriptor_pool(device_ctx.device());
+		device_ctx.set_name(descriptor_pool, "frame descriptor pool");
 		let mut frames: Vec<FrameData> = Vec::new();
-		for _ in 0..FRAMES_IN_FLIGHT {
-			frames.push(FrameData::new(device_ctx.device(), cmd_pool));
+		for i in 0..FRAMES_IN_FLIGHT {
+			let frame = FrameData::new(
+				device_ctx,
+				cmd_pool,
+				timestamp_valid_bits,
+				descriptor_pool,
+				camera_set_layout,
+			);
+			frame
+				.camera_buffer
+				.set_name(device_ctx, &format!("frame {} camera", i));
+			device_ctx.set_name(frame.camera_set, &format!("frame {} camera set", i));
+			device_ctx.set_name(frame.cmd_buff, &format!("frame {} cmd buff", i));
+			device_ctx.set_name(frame.img_available, &format!("frame {} img available", i));
+			frames.push(frame);
+		}
+
+		let render_finished = VkSwap::create_render_finished(device_ctx, &swapchain_ctx);
+		let mut image_tracker = ImageTracker::new();
+		for &img in &swapchain_ctx.swapchain_imgs {
+			image_tracker.register(img, vk::ImageAspectFlags::COLOR, 1, 1);
 		}
 
+		let (present_cmd_pool, present_acquire_cmds) = if sharing
+			== PresentSharing::OwnershipTransfer
+			&& device_ctx.graphics_index != device_ctx.present_index
+		{
+			VkSwap::create_present_acquire_cmds(device_ctx, &swapchain_ctx.swapchain_imgs)
+		} else {
+			(vk::CommandPool::null(), Vec::new())
+		};
+
 		VkSwap {
 			surface,
 			swapchain_ctx,
-			pipeline_ctx,
 			frames,
+			render_finished,
 			current_frame: 0,
 			cmd_pool,
+			image_tracker: RefCell::new(image_tracker),
+			present_cmd_pool,
+			present_acquire_cmds,
+			gpu_profiler: RefCell::new(GpuProfiler::new()),
+			query_report: RefCell::new(QueryReport::new()),
+			camera_set_layout,
+			descriptor_pool,
+			out_of_date: Cell::new(false),
 		}
 	}
 
-	pub fn record_command_buff(&self, img_idx: u32, device: &Device) {
-		let cmd_buff = self
-			.frames
-			.get(self.current_frame as usize)
-			.expect("current frame should be valid index into frames")
-			.cmd_buff;
-		let extent = self.swapchain_ctx.swapchain_extent;
-
+	// waits for the device to go idle. everything sized or counted by the swapchain images is
+	// rebuilt, the frames (and their transient pools) adapt to the new extent by themselves
+	pub fn recreate_swapchain(
+		&mut self,
+		window: &Window,
+		instance_ctx: &InstanceContext,
+		device_ctx: &DeviceContext,
+	) {
+		let device = device_ctx.device();
 		unsafe {
 			device
-				.begin_command_buffer(cmd_buff, &vk::CommandBufferBeginInfo::default())
-				.expect("Should have been able to begin command_buffer")
-		};
-		// before starting to render, transfer swapchain image to COLOR_ATTACHMENT_OPTIMAL
-		self.transition_img_layout(
-			device,
-			img_idx,
-			vk::ImageLayout::UNDEFINED,
-			vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-			vk::AccessFlags2::empty(), // src access mask (no need to wait for previous op)
-			vk::AccessFlags2::COLOR_ATTACHMENT_WRITE, // dst access mask
-			vk::PipelineStageFlags2::TOP_OF_PIPE, // src stage
-			vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, // dst stage
+				.device_wait_idle()
+				.expect("Should have been able to wait for device idle");
+		}
+		let mut tracker = self.image_tracker.borrow_mut();
+		for &img in &self.swapchain_ctx.swapchain_imgs {
+			tracker.unregister(img);
+		}
+		self.swapchain_ctx
+			.recreate(instance_ctx, device_ctx, window, self.surface);
+		for &img in &self.swapchain_ctx.swapchain_imgs {
+			tracker.register(img, vk::ImageAspectFlags::COLOR, 1, 1);
+		}
+		drop(tracker);
+		// a failed present may have left these signaled, and the image count can change
+		for semaphore in self.render_finished.drain(..) {
+			unsafe { device.destroy_semaphore(semaphore, None) };
+		}
+		self.render_finished = VkSwap::create_render_finished(device_ctx, &self.swapchain_ctx);
+		if self.transfers_present_ownership() {
+			unsafe { device.destroy_command_pool(self.present_cmd_pool, None) };
+			(self.present_cmd_pool, self.present_acquire_cmds) =
+				VkSwap::create_present_acquire_cmds(device_ctx, &self.swapchain_ctx.swapchain_imgs);
+		}
+		self.out_of_date.set(false);
+		let extent = self.swapchain_ctx.swapchain_extent;
+		log::info!(
+			"recreated the swapchain at {}x{}",
+			extent.width,
+			extent.height
 		);
-		// vk::ClearValue is a union expression, can only hold one field
-		let clear_color = vk::ClearValue {
-			color: vk::ClearColorValue {
-				float32: [0., 0., 0., 1.],
-			},
-		};
-		let attachment_info = vk::RenderingAttachmentInfo {
-			image_view: *self
-				.swapchain_ctx
-				.swapchain_img_views
-				.get(img_idx as usize)
-				.expect("img_idx should always be valid for swapchain img views"),
-			image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-			load_op: vk::AttachmentLoadOp::CLEAR,
-			store_op: vk::AttachmentStoreOp::STORE,
-			clear_value: clear_color,
+	}
+
+	// one per swapchain image, see draw_frame
+	fn create_render_finished(
+		device_ctx: &DeviceContext,
+		swapchain_ctx: &SwapchainContext,
+	) -> Vec<vk::Semaphore> {
+		let render_finished: Vec<vk::Semaphore> = swapchain_ctx
+			.swapchain_imgs
+			.iter()
+			.map(|_| unsafe {
+				device_ctx
+					.device()
+					.create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+					.expect("Should have been able to create render_finished semaphore")
+			})
+			.collect();
+		for (i, &semaphore) in render_finished.iter().enumerate() {
+			device_ctx.set_name(semaphore, &format!("render finished {}", i));
+		}
+		render_finished
+	}
+
+	pub fn create_camera_set_layout(device: &Device) -> vk::DescriptorSetLayout {
+		let binding = vk::DescriptorSetLayoutBinding {
+			binding: 0,
+			descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+			descriptor_count: 1,
+			stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
 			..Default::default()
 		};
-		let render_info = vk::RenderingInfo {
-			render_area: vk::Rect2D {
-				offset: vk::Offset2D { x: 0, y: 0 },
-				extent: extent,
-			},
-			layer_count: 1,
-			color_attachment_count: 1,
-			p_color_attachments: &attachment_info,
+		let layout_info = vk::DescriptorSetLayoutCreateInfo {
+			binding_count: 1,
+			p_bindings: &binding,
 			..Default::default()
 		};
-
 		unsafe {
-			// begin rendering
-			device.cmd_begin_rendering(cmd_buff, &render_info);
-			// bind pipeline
-			device.cmd_bind_pipeline(
-				cmd_buff,
-				vk::PipelineBindPoint::GRAPHICS,
-				self.pipeline_ctx.graphics_pipeline,
-			);
-			// set dynamic states
-			device.cmd_set_viewport(
-				cmd_buff,
-				0,
-				&[vk::Viewport {
-					x: 0.,
-					y: 0.,
-					width: extent.width as f32,
-					height: extent.height as f32,
-					min_depth: 0.,
-					max_depth: 1.,
-				}],
-			);
-			device.cmd_set_scissor(
-				cmd_buff,
-				0,
-				&[vk::Rect2D {
-					offset: vk::Offset2D { x: 0, y: 0 },
-					extent: extent,
-				}],
-			);
+			device
+				.create_descriptor_set_layout(&layout_info, None)
+				.expect("Should have been able to create camera set layout")
+		}
+	}
 
-			device.cmd_draw(cmd_buff, 3, 1, 0, 0);
-			device.cmd_end_rendering(cmd_buff);
+	// just the per frame camera sets for now
+	pub fn create_descriptor_pool(device: &Device) -> vk::DescriptorPool {
+		let pool_size = vk::DescriptorPoolSize {
+			ty: vk::DescriptorType::UNIFORM_BUFFER,
+			descriptor_count: FRAMES_IN_FLIGHT as u32,
+		};
+		let pool_info = vk::DescriptorPoolCreateInfo {
+			max_sets: FRAMES_IN_FLIGHT as u32,
+			pool_size_count: 1,
+			p_pool_sizes: &pool_size,
+			..Default::default()
 		};
-		// transition back to present to screen
-		self.transition_img_layout(
-			device,
-			img_idx,
-			vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-			vk::ImageLayout::PRESENT_SRC_KHR,
-			vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
-			vk::AccessFlags2::empty(),
-			vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
-			vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
-		);
 		unsafe {
 			device
-				.end_command_buffer(cmd_buff)
-				.expect("Should have been able to end cmd buff")
-		};
+				.create_descriptor_pool(&pool_info, None)
+				.expect("Should have been able to create descriptor pool")
+		}
 	}
 
-	pub fn transition_img_layout(
-		&self,
-		device: &Device,
-		img_idx: u32,
-		old_layout: vk::ImageLayout,
-		new_layout: vk::ImageLayout,
-		src_access_mask: vk::AccessFlags2,
-		dst_access_mask: vk::AccessFlags2,
-		src_stage_mask: vk::PipelineStageFlags2,
-		dst_stage_mask: vk::PipelineStageFlags2,
-	) {
-		let barrier = vk::ImageMemoryBarrier2 {
-			src_stage_mask: src_stage_mask,
-			src_access_mask: src_access_mask,
-			dst_stage_mask: dst_stage_mask,
-			dst_access_mask: dst_access_mask,
-			old_layout: old_layout,
-			new_layout: new_layout,
-			src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-			dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-			image: *self
-				.swapchain_ctx
-				.swapchain_imgs
-				.get(img_idx as usize)
-				.expect("img_idx should always be valid for swapchain_imgs"),
-			subresource_range: vk::ImageSubresourceRange {
-				aspect_mask: vk::ImageAspectFlags::COLOR,
-				base_mip_level: 0,
-				level_count: 1,
-				base_array_layer: 0,
-				layer_count: 1,
-			},
+	pub fn transfers_present_ownership(&self) -> bool {
+		!self.present_acquire_cmds.is_empty()
+	}
+
+	// current frame's timestamps and queries from last time around, only call once its timeline
+	// value is reached. never waits, a frame that isn't ready is just skipped
+	pub fn collect_gpu_queries(&self, device: &Device) {
+		let frame = &self.frames[self.current_frame as usize];
+		if let Some(timings) = frame.timestamps.read_results(device) {
+			let mut profiler = self.gpu_profiler.borrow_mut();
+			profiler.record(timings);
+			if profiler
+				.frames_recorded()
+				.is_multiple_of(GPU_PROFILER_LOG_INTERVAL)
+			{
+				profiler.log_table(log::Level::Debug);
+			}
+		}
+		if let Some(results) = frame.queries.read_results(device) {
+			let mut report = self.query_report.borrow_mut();
+			report.record(results);
+			if report
+				.frames_resolved()
+				.is_multiple_of(QUERY_REPORT_LOG_INTERVAL)
+			{
+				report.log_table(log::Level::Debug);
+			}
+		}
+	}
+
+	// the acquire half of graphics -> present ownership transfers. they never change, so record
+	// them once per swapchain image
+	fn create_present_acquire_cmds(
+		device_ctx: &DeviceContext,
+		swapchain_imgs: &[vk::Image],
+	) -> (vk::CommandPool, Vec<vk::CommandBuffer>) {
+		let device = device_ctx.device();
+		let pool = VkSwap::create_command_pool(device, device_ctx.present_index);
+		device_ctx.set_name(pool, "present cmd pool");
+		let alloc_info = vk::CommandBufferAllocateInfo {
+			command_pool: pool,
+			level: vk::CommandBufferLevel::PRIMARY,
+			command_buffer_count: swapchain_imgs.len() as u32,
 			..Default::default()
 		};
-		let deps_info = vk::DependencyInfo {
-			image_memory_barrier_count: 1,
-			p_image_memory_barriers: &barrier,
-			..Default::default()
+		let cmd_buffs = unsafe {
+			device
+				.allocate_command_buffers(&alloc_info)
+				.expect("Should have been able to allocate present cmd buffs")
 		};
+		for (i, (&cmd_buff, &img)) in cmd_buffs.iter().zip(swapchain_imgs).enumerate() {
+			device_ctx.set_name(cmd_buff, &format!("present acquire {}", i));
+			let transfer = image_ownership_transfer(
+				img,
+				color_subresource_range(0, 1),
+				ImageUsage::ColorAttachment,
+				ImageUsage::Present,
+				device_ctx.graphics_index,
+				device_ctx.present_index,
+			)
+			.expect("graphics and present families should differ");
+			let begin_info = vk::CommandBufferBeginInfo {
+				flags: vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
+				..Default::default()
+			};
+			unsafe {
+				device
+					.begin_command_buffer(cmd_buff, &begin_info)
+					.expect("Should have been able to begin present cmd buff");
+			}
+			cmd_barriers(device_ctx, cmd_buff, &[transfer.acquire], &[]);
+			unsafe {
+				device
+					.end_command_buffer(cmd_buff)
+					.expect("Should have been able to end present cmd buff");
+			}
+		}
+		(pool, cmd_buffs)
+	}
 
-		let cmd_buff = self
+	// the App's passes go into a fresh graph between the frame's setup and the present transition
+	pub fn record_command_buff(
+		&self,
+		img_idx: u32,
+		vk: &VkCore,
+		alpha: f32,
+		render: impl FnOnce(&mut FrameContext),
+	) {
+		let device_ctx = &vk.device_ctx;
+		let device = device_ctx.device();
+		let frame = self
 			.frames
 			.get(self.current_frame as usize)
-			.expect("current frame should be valid index into frames")
-			.cmd_buff;
+			.expect("current frame should be valid index into frames");
+		let cmd_buff = frame.cmd_buff;
+		let extent = self.swapchain_ctx.swapchain_extent;
+
 		unsafe {
-			device.cmd_pipeline_barrier2(cmd_buff, &deps_info);
+			device
+				.begin_command_buffer(cmd_buff, &vk::CommandBufferBeginInfo::default())
+				.expect("Should have been able to begin command_buffer")
+		};
+		let frame_label = device_ctx.cmd_label(cmd_buff, &format!("frame {}", self.current_frame));
+		frame.timestamps.begin_frame(device, cmd_buff);
+		frame.queries.begin_frame(device, cmd_buff);
+		let frame_scope = frame.timestamps.profile_scope(device, cmd_buff, "frame");
+		// whatever was in the swapchain image is garbage, but its first transition has to wait
+		// on the acquire semaphore
+		let swap_img = *self
+			.swapchain_ctx
+			.swapchain_imgs
+			.get(img_idx as usize)
+			.expect("img_idx should always be valid for swapchain_imgs");
+		let swap_view = *self
+			.swapchain_ctx
+			.swapchain_img_views
+			.get(img_idx as usize)
+			.expect("img_idx should always be valid for swapchain img views");
+		let mut tracker = self.image_tracker.borrow_mut();
+		tracker.discard(swap_img, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+
+		let mut graph = RenderGraph::new();
+		graph.profile_passes(&frame.timestamps);
+		graph.collect_queries(&frame.queries);
+		let swapchain = graph.import_image("swapchain", swap_img, swap_view, extent);
+		let mut frame_ctx = FrameContext {
+			vk,
+			frame,
+			frame_index: self.current_frame,
+			alpha,
+			extent,
+			swapchain_format: self.swapchain_ctx.swapchain_format,
+			graph,
+			swapchain,
+		};
+		render(&mut frame_ctx);
+		let FrameContext {
+			mut graph,
+			swapchain,
+			..
+		} = frame_ctx;
+		// transition back to present to screen once the graph is done with it. with a separate
+		// present family the release half of the ownership transfer does the transition instead
+		let final_usage = (!self.transfers_present_ownership()).then_some(ImageUsage::Present);
+		graph.export_image(swapchain, final_usage);
+		graph.execute(
+			device_ctx,
+			cmd_buff,
+			&mut tracker,
+			&mut frame.transient_pool.borrow_mut(),
+		);
+		if let Some(transfer) = self
+			.transfers_present_ownership()
+			.then(|| {
+				image_ownership_transfer(
+					swap_img,
+					color_subresource_range(0, 1),
+					ImageUsage::ColorAttachment,
+					ImageUsage::Present,
+					device_ctx.graphics_index,
+					device_ctx.present_index,
+				)
+			})
+			.flatten()
+		{
+			cmd_barriers(device_ctx, cmd_buff, &[transfer.release], &[]);
 		}
+		// scopes and labels have to end before the cmd buff does
+		drop(frame_scope);
+		drop(frame_label);
+
+		unsafe {
+			device
+				.end_command_buffer(cmd_buff)
+				.expect("Should have been able to end cmd buff")
+		};
 	}
 
 	fn create_command_pool(device: &Device, graphics_idx: u32) -> vk::CommandPool {
@@ -216,35 +422,38 @@ impl VkSwap {
 		command_pool
 	}
 	pub fn cleanup(&self, surface_loader: &surface::Instance, device: &Device) {
+		// nothing below is safe to destroy while frames are still in flight
+		unsafe {
+			device
+				.device_wait_idle()
+				.expect("Should have been able to wait for device idle");
+		}
 		// sync objects
 		unsafe {
 			for frame in &self.frames {
 				device.destroy_semaphore(frame.img_available, None);
-				device.destroy_semaphore(frame.render_finished, None);
-				device.destroy_fence(frame.draw_fence, None);
+				frame.transient_pool.borrow_mut().cleanup(device);
+				frame.timestamps.cleanup(device);
+				frame.queries.cleanup(device);
+				frame.camera_buffer.cleanup(device);
+			}
+			for &semaphore in &self.render_finished {
+				device.destroy_semaphore(semaphore, None);
 			}
 		}
-		// views
-		unsafe {
-			self.swapchain_ctx
-				.swapchain_img_views
-				.iter()
-				.for_each(|view| device.destroy_image_view(*view, None));
-		}
-		// swap (destroys imgs)
-		unsafe {
-			self.swapchain_ctx
-				.swapchain_device
-				.destroy_swapchain(self.swapchain_ctx.swapchain, None);
-		}
-		// pipeline + pipeline layout
+		// views and swap (destroys imgs)
+		self.swapchain_ctx.cleanup(device);
+		// descriptors, destroying the pool frees the sets
 		unsafe {
-			device.destroy_pipeline_layout(self.pipeline_ctx.pipeline_layout, None);
-			device.destroy_pipeline(self.pipeline_ctx.graphics_pipeline, None);
+			device.destroy_descriptor_pool(self.descriptor_pool, None);
+			device.destroy_descriptor_set_layout(self.camera_set_layout, None);
 		}
-		// cmd pool
+		// cmd pools
 		unsafe {
 			device.destroy_command_pool(self.cmd_pool, None);
+			if self.present_cmd_pool != vk::CommandPool::null() {
+				device.destroy_command_pool(self.present_cmd_pool, None);
+			}
 		}
 		unsafe {
 			surface_loader.destroy_surface(self.surface, None);
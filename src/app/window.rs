@@ -1,14 +1,21 @@
-use super::{Application, Instant, VkCore, VkSwap};
+use super::{
+	App, FrameStats, GPU_TRACE_ENV_VAR, Instant, RunMode, Runner, VkCore, VkSwap,
+	stats::FRAME_STATS_LOG_INTERVAL,
+};
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
-use winit::event_loop::ActiveEventLoop;
+use winit::event::{DeviceEvent, DeviceId, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow};
 use winit::window::WindowAttributes;
 
-impl ApplicationHandler for Application {
+const WINDOW_TITLE: &str = "Vulkan rs";
+// frames between fps title updates, every frame would be unreadable
+const TITLE_UPDATE_INTERVAL: u64 = 30;
+
+impl<A: App> ApplicationHandler for Runner<A> {
 	// Init our graphics context on resumed because of certain platforms (e.g. Android)
 	fn resumed(&mut self, event_loop: &ActiveEventLoop) {
 		if self.window.is_none() {
-			let attributes = WindowAttributes::default().with_title("Vulkan rs");
+			let attributes = WindowAttributes::default().with_title(WINDOW_TITLE);
 			self.window = Some(
 				event_loop
 					.create_window(attributes)
@@ -17,7 +24,17 @@ impl ApplicationHandler for Application {
 		}
 		// Permanent VK
 		if self.vk.is_none() {
-			self.vk = Some(VkCore::new(self.window.as_ref().unwrap()));
+			match VkCore::new(self.window.as_ref().unwrap(), &self.config) {
+				Ok(vk) => {
+					self.frame_stats = FrameStats::new(&vk.config.stats);
+					self.vk = Some(vk);
+				}
+				Err(e) => {
+					log::error!("Could not build VkCore: {}", e);
+					event_loop.exit();
+					return;
+				}
+			}
 			log::info!("Built VkCore!");
 		}
 		// recreates on each resumed signal
@@ -29,6 +46,15 @@ impl ApplicationHandler for Application {
 			));
 			log::info!("Built VkSwap!");
 		}
+		if !self.initialized {
+			let (Some(vk), Some(vk_swap)) = (&self.vk, &self.vk_swap) else {
+				unreachable!("VkCore and VkSwap were just built");
+			};
+			self.app.init(vk, vk_swap, &self.control);
+			// setup time isn't something the simulation should catch up on
+			self.timestep.reset();
+			self.initialized = true;
+		}
 	}
 	fn suspended(&mut self, event_loop: &ActiveEventLoop) {
 		log::info!("Received suspend event, cleaning VkSwap");
@@ -37,36 +63,116 @@ impl ApplicationHandler for Application {
 			self.vk().device_ctx.device(),
 		);
 	}
+	// decides whether (and when) the next frame gets drawn
+	fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+		let Some(window) = &self.window else {
+			return;
+		};
+		match self.control.mode() {
+			RunMode::Continuous => {
+				event_loop.set_control_flow(ControlFlow::Poll);
+				window.request_redraw();
+			}
+			RunMode::Reactive => {
+				event_loop.set_control_flow(ControlFlow::Wait);
+				if self.control.take_redraw() {
+					window.request_redraw();
+				}
+			}
+			mode @ RunMode::CappedFps(_) => {
+				let interval = mode
+					.frame_interval()
+					.expect("RunControl should only hold fps caps with a valid interval");
+				let now = Instant::now();
+				if now >= self.next_frame {
+					// late frames don't get made up for, the schedule just restarts from now
+					self.next_frame = (self.next_frame + interval).max(now);
+					window.request_redraw();
+				}
+				event_loop.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+			}
+		}
+	}
 	fn window_event(
 		&mut self,
 		event_loop: &ActiveEventLoop,
 		window_id: winit::window::WindowId,
 		event: WindowEvent,
 	) {
+		self.app.on_event(&event);
+		self.input.handle_window_event(&event);
+		// anything but our own redraws is worth a frame in reactive mode
+		if !matches!(event, WindowEvent::RedrawRequested) {
+			self.control.request_redraw();
+		}
 		match event {
 			WindowEvent::CloseRequested => {
+				let profiler = self.vk_swap().gpu_profiler.borrow();
+				profiler.log_table(log::Level::Info);
+				if let Ok(path) = std::env::var(GPU_TRACE_ENV_VAR) {
+					match profiler.write_chrome_trace(&path) {
+						Ok(()) => log::info!("wrote gpu trace to {}", path),
+						Err(e) => log::error!("Could not write gpu trace to {}: {}", path, e),
+					}
+				}
+				drop(profiler);
+				self.vk_swap()
+					.query_report
+					.borrow()
+					.log_table(log::Level::Info);
+				self.frame_stats.log_table(log::Level::Info);
+				self.frame_stats.flush();
+				// waits for the device to go idle
 				self.vk_swap().cleanup(
 					self.vk().instance_ctx.surface_loader(),
 					self.vk().device_ctx.device(),
 				);
-				self.vk().cleanup();
+				let vk = self
+					.vk
+					.as_ref()
+					.expect("VkCore should have been initialized");
+				self.app.cleanup(vk);
+				vk.cleanup();
 				event_loop.exit();
 			}
-			WindowEvent::Resized(size) => { /* resize */ }
+			// recreated lazily, a drag can send many of these before the next frame
+			WindowEvent::Resized(_) => {
+				if let Some(vk_swap) = &self.vk_swap {
+					vk_swap.out_of_date.set(true);
+				}
+			}
 			WindowEvent::RedrawRequested => {
-				// tick
-				let now = Instant::now();
-				let dt = now.duration_since(self.last_frame).as_secs_f32();
-				self.last_frame = now;
-				// game logic
-				self.update(dt);
+				let tick = self.step_frame();
 				// render
-				self.draw_frame();
-				if let Some(window) = &self.window {
-					window.request_redraw();
+				let mut timings = self.draw_frame(tick.alpha);
+				timings.frame_ms = tick.dt.as_secs_f64() * 1000.;
+				self.frame_stats.record(timings);
+				let frame_count = self.frame_stats.frame_count();
+				if frame_count.is_multiple_of(FRAME_STATS_LOG_INTERVAL) {
+					self.frame_stats.log_table(log::Level::Debug);
+				}
+				if let Some(window) = &self.window
+					&& self.frame_stats.fps_in_title()
+					&& frame_count.is_multiple_of(TITLE_UPDATE_INTERVAL)
+				{
+					window.set_title(&format!(
+						"{} - {:.0} fps ({:.2} ms)",
+						WINDOW_TITLE,
+						self.frame_stats.fps(),
+						self.frame_stats.frame.avg()
+					));
 				}
 			}
 			_ => {}
 		}
 	}
+	// raw mouse motion, keeps coming when the cursor is grabbed
+	fn device_event(
+		&mut self,
+		_event_loop: &ActiveEventLoop,
+		_device_id: DeviceId,
+		event: DeviceEvent,
+	) {
+		self.input.handle_device_event(&event);
+	}
 }
@@ -0,0 +1,5 @@
+// vulkan renderer as a library: implement `App` and hand it to a `Runner`, see
+// examples/triangle.rs
+pub mod app;
+
+pub use app::{App, FrameContext, Runner, VkConfig};
@@ -0,0 +1,123 @@
+use ash::vk;
+use lvkrs::VkConfig;
+use lvkrs::app::{Gltf, OFFSCREEN_CLEAR_COLOR, VkCore, render_gltf};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+const CORNERS: [(u32, u32); 4] = [
+	(0, 0),
+	(WIDTH - 1, 0),
+	(0, HEIGHT - 1),
+	(WIDTH - 1, HEIGHT - 1),
+];
+
+// no window needed, so this runs anywhere with a vulkan device (lavapipe included).
+// without one there's nothing to check
+fn render(path: &str) -> Option<Vec<u8>> {
+	let vk = match VkCore::headless(&VkConfig::default()) {
+		Ok(vk) => vk,
+		Err(e) => {
+			eprintln!("Skipping, no vulkan device: {}", e);
+			return None;
+		}
+	};
+	let gltf = Gltf::load(path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e));
+	let extent = vk::Extent2D {
+		width: WIDTH,
+		height: HEIGHT,
+	};
+	let pixels = render_gltf(&vk, &gltf, include_bytes!("../shaders/scene.spv"), extent);
+	vk.cleanup();
+	assert_eq!(pixels.len(), (WIDTH * HEIGHT * 4) as usize);
+	Some(pixels)
+}
+
+fn pixel(pixels: &[u8], x: u32, y: u32) -> [u8; 4] {
+	let at = ((y * WIDTH + x) * 4) as usize;
+	pixels[at..at + 4].try_into().unwrap()
+}
+
+// what the R8G8B8A8_SRGB target stores for a linear color
+fn srgb(linear: [f32; 4]) -> [u8; 4] {
+	let encode = |c: f32| {
+		let c = c.clamp(0., 1.);
+		let c = if c <= 0.0031308 {
+			c * 12.92
+		} else {
+			1.055 * c.powf(1. / 2.4) - 0.055
+		};
+		(c * 255.).round() as u8
+	};
+	[
+		encode(linear[0]),
+		encode(linear[1]),
+		encode(linear[2]),
+		(linear[3].clamp(0., 1.) * 255.).round() as u8,
+	]
+}
+
+// drivers may round the srgb encode either way
+fn close(a: [u8; 4], b: [u8; 4]) -> bool {
+	a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 1)
+}
+
+#[test]
+fn renders_box_offscreen() {
+	let Some(pixels) = render("assets/box.gltf") else {
+		return;
+	};
+	// the box sits in the middle of the frame, the corners are background
+	let background = srgb(OFFSCREEN_CLEAR_COLOR);
+	for (x, y) in CORNERS {
+		let corner = pixel(&pixels, x, y);
+		assert!(
+			close(corner, background),
+			"corner {} {}: {:?}",
+			x,
+			y,
+			corner
+		);
+	}
+	let center = pixel(&pixels, WIDTH / 2, HEIGHT / 2);
+	assert!(!close(center, background), "nothing drawn at the center");
+	assert_eq!(center[3], 255);
+}
+
+#[test]
+fn renders_emissive_quad_offscreen() {
+	let Some(pixels) = render("assets/emissive_quad.gltf") else {
+		return;
+	};
+	// black base color and normals facing away from the light leave only the emissive term,
+	// and without msaa every pixel is either the quad or the clear color
+	let background = srgb(OFFSCREEN_CLEAR_COLOR);
+	let quad = srgb([1., 0.5, 0., 1.]);
+	let mut covered = 0;
+	for y in 0..HEIGHT {
+		for x in 0..WIDTH {
+			let p = pixel(&pixels, x, y);
+			if close(p, quad) {
+				covered += 1;
+			} else {
+				assert!(close(p, background), "pixel {} {}: {:?}", x, y, p);
+			}
+		}
+	}
+	// the camera frames the quad from above a corner, it never reaches the image corners
+	for (x, y) in CORNERS {
+		assert!(
+			close(pixel(&pixels, x, y), background),
+			"corner {} {}",
+			x,
+			y
+		);
+	}
+	assert!(close(pixel(&pixels, WIDTH / 2, HEIGHT / 2), quad));
+	let total = WIDTH * HEIGHT;
+	assert!(
+		covered > total / 10 && covered < total * 9 / 10,
+		"quad covers {} of {} pixels",
+		covered,
+		total
+	);
+}